@@ -2,33 +2,96 @@ use anchor_lang::prelude::*;
 
 ///PDA Seeds
 pub const POLICY_SEED: &[u8] = b"policy";
-pub const POOL_SEED: &[u8] = b"collateral_pool";
 pub const AUTHORITY_SEED: &[u8] = b"pool_authority";
-pub const LP_MINT_SEED: &[u8] = b"lp_mint";
 pub const INSURANCE_POOL_SEED: &[u8] = b"insurance_pool";
+pub const WITHDRAWAL_TICKET_SEED: &[u8] = b"withdrawal_ticket";
+pub const WITHDRAWAL_ESCROW_SEED: &[u8] = b"withdrawal_escrow";
+pub const LOCKED_POSITION_SEED: &[u8] = b"locked_position";
+pub const LOCKED_LP_VAULT_SEED: &[u8] = b"locked_lp_vault";
+pub const STAKED_LP_VAULT_SEED: &[u8] = b"staked_lp_vault";
+pub const UNDERWRITER_POSITION_SEED: &[u8] = b"underwriter_position";
+pub const PROTOCOL_TREASURY_SEED: &[u8] = b"protocol_treasury";
+pub const RESERVE_VAULT_SEED: &[u8] = b"reserve_vault";
+pub const ASSET_EXPOSURE_SEED: &[u8] = b"asset_exposure";
+pub const SETTLEMENT_ROUND_SEED: &[u8] = b"settlement_round";
+pub const CLAIM_MINT_SEED: &[u8] = b"claim_mint";
+pub const BUYER_PROFILE_SEED: &[u8] = b"buyer_profile";
+pub const DEPEG_EVENT_SEED: &[u8] = b"depeg_event";
 
 //tranches
-pub const JUNIOR_LP_MINT_SEED: &[u8] = b"junior_lp_mint";
-pub const SENIOR_LP_MINT_SEED: &[u8] = b"senior_lp_mint";
-pub const JUNIOR_COLLATERAL_POOL_SEED: &[u8] = b"junior_collateral_pool";
-pub const SENIOR_COLLATERAL_POOL_SEED: &[u8] = b"senior_collateral_pool";
+pub const TRANCHE_SEED: &[u8] = b"tranche";
+pub const TRANCHE_LP_MINT_SEED: &[u8] = b"tranche_lp_mint";
+pub const TRANCHE_VAULT_SEED: &[u8] = b"tranche_vault";
+pub const MAX_TRANCHES: u8 = 8; // every tranche is loaded for waterfall checks, so keep it small
 
-//  New Premium Distribution
-pub const JUNIOR_PREMIUM_SHARE_BPS: u64 = 8000; // Junior tranche gets 80% of premiums
-pub const SENIOR_PREMIUM_SHARE_BPS: u64 = 2000; // Senior tranche gets 20% of premiums
+//  Premium Distribution
+pub const MAX_TARGET_APR_BPS: u64 = 5000; // no tranche's coupon can target more than 50% APR
+pub const MIN_JUNIOR_PREMIUM_SHARE_BPS: u64 = 5000; // Dynamic split never pays junior less than 50%
+pub const MAX_JUNIOR_PREMIUM_SHARE_BPS: u64 = 9000; // Dynamic split never pays junior more than 90%
+
+// Protocol fee, taken off each premium before it reaches the tranches. Starts at zero.
+pub const MAX_PROTOCOL_FEE_BPS: u64 = 2000; // the protocol never takes more than 20% of a premium
+
+// Reserve fund. Part of each premium is set aside to pay claims before LP capital is touched.
+pub const RESERVE_PREMIUM_BPS: u64 = 1000; // 10% of each premium goes to the reserve
+pub const MAX_RESERVE_PREMIUM_BPS: u64 = 5000;
+
+// Withdrawals wait this long between request and execution so LPs can't pull capital the
+// moment an oracle starts to wobble.
+pub const WITHDRAWAL_COOLDOWN_SECONDS: i64 = 60 * 60 * 24 * 3;
+pub const MAX_WITHDRAWAL_COOLDOWN_SECONDS: i64 = 60 * 60 * 24 * 30;
+
+// Deposit lockups. Longer locks earn a bigger slice of premiums and count as firmer backing.
+pub const LOCK_BOOST_BPS_PER_YEAR: u64 = 5000; // a one-year lock earns premiums on 1.5x its LP
+pub const MAX_LOCK_BOOST_BPS_PER_YEAR: u64 = 20000;
+pub const MAX_LOCK_DURATION_SECONDS: i64 = 60 * 60 * 24 * 365;
+pub const UNLOCKED_CAPITAL_WEIGHT_BPS: u64 = 8000; // unlocked capital backs 80% of its value
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+// Longest the authority can make newly staked LP tokens wait before they earn premiums.
+pub const MAX_LP_WARMUP_SECONDS: i64 = 60 * 60 * 24 * 30;
+
+// Deposit caps start open; the authority can tighten them while the pool bootstraps.
+pub const NO_DEPOSIT_CAP: u64 = u64::MAX;
+
+// LP share pricing. Virtual shares and collateral keep the first deposit from setting an
+// arbitrary share price.
+pub const VIRTUAL_LP_SHARES: u64 = 1_000_000;
+pub const VIRTUAL_COLLATERAL: u64 = 1_000_000;
+pub const SHARE_PRICE_PRECISION: u128 = 1_000_000_000_000;
 
 ///Policy Parameters
 pub const SECONDS_30: u64 = 60 * 60 * 24;
+pub const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+// Liability is bucketed by expiry day, so no policy may outlast the bucket ring.
+pub const LIABILITY_BUCKET_COUNT: usize = 64;
+pub const MAX_POLICY_DURATION_SECONDS: i64 = 60 * SECONDS_PER_DAY;
+// A single stablecoin's depeg triggers all its policies at once, so no one asset may carry
+// more than this share of the pool's capital in payout liability by default.
+pub const MAX_ASSET_LIABILITY_BPS: u64 = 5000;
+pub const NO_ASSET_LIABILITY_CAP: u64 = u64::MAX;
+// Per-buyer limits, so a single wallet can't take all of a pool's capacity.
+pub const MAX_BUYER_ACTIVE_POLICIES: u64 = 16;
+pub const NO_BUYER_COVERAGE_CAP: u64 = u64::MAX;
+pub const SECONDS_PER_YEAR: u64 = 60 * 60 * 24 * 365;
 // pub const POLICY_TERM: i64 = 3; //for testing
 
 pub const DEPEG_THRESHOLD_PRICE: i64 = 98_500_000;
-// pub const DEPEG_THRESHOLD_PRICE: u64 = 10000000; //for testing
+pub const PEG_PRICE: i64 = 100_000_000; // $1.00 at the 8 decimals oracle prices are scaled to
+                                        // pub const DEPEG_THRESHOLD_PRICE: u64 = 10000000; //for testing
 
 pub const BINARY_PAYOUT_BPS: u16 = 1000;
 pub const PREMIUM_RATE_BPS: u64 = 50;
 pub const MAX_ORACLE_AGE_SECONDS: u64 = 60;
+// Exits read the collateral price with more slack, so LPs aren't locked in by a slow feed.
+pub const MAX_EXIT_ORACLE_AGE_SECONDS: u64 = 600;
 pub const MAX_CONFIDENCE_VALUE: u64 = 70000;
 pub const MAX_CONFIDENCE_BPS: u64 = 20; // 20 bps = 0.20%
+                                        // A depeg comes with a wide confidence interval, so a recorded price may have a wider one.
+pub const MAX_OBSERVATION_CONFIDENCE_BPS: u64 = 200; // 2%
+                                                     // A day's price can be recorded from any update published within this long of the day's
+                                                     // end, so a missed minute can't leave its policies unable to settle.
+pub const MAX_OBSERVATION_DELAY_SECONDS: i64 = SECONDS_PER_DAY;
 
 pub const USDC_MINT_PUBKEY: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
 pub const USDT_MINT_PUBKEY: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
@@ -36,7 +99,7 @@ pub const USDT_MINT_PUBKEY: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11Mc
 // pub const PYTH_USDC_USD_FEED: Pubkey = pubkey!("Gnt27xtC473ZT2Mw5u8wZ68Z3gULkSTb5DuxJy7eJotD");
 // pub const PYTH_USDT_USD_FEED: Pubkey = pubkey!("3vxLXJqLqF3JG5TCbYycbKWRBbCJQLxudq4nTEMAscUX");
 
-pub const PYTH_USDC_USD_FEED_ID: &'static str =
+pub const PYTH_USDC_USD_FEED_ID: &str =
     "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
-pub const PYTH_USDT_USD_FEED_ID: &'static str =
+pub const PYTH_USDT_USD_FEED_ID: &str =
     "0x2b89b9dc8fdf9f34709a5b106b472f0f39bb6ca9ce04b0fd7f2e971688e2e53b";
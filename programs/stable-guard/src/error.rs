@@ -73,4 +73,108 @@ pub enum StableGuardError {
 
     #[msg("Invalid Policy Owner")]
     InvalidPolicyOwner,
+
+    #[msg("LP token account does not match the tranche's LP mint")]
+    InvalidLpMint,
+
+    #[msg("Only the pool authority can perform this action")]
+    UnauthorizedPoolAuthority,
+
+    #[msg("Invalid pool configuration value")]
+    InvalidPoolConfig,
+
+    #[msg("Output amount is below the minimum accepted")]
+    SlippageExceeded,
+
+    #[msg("Withdrawal ticket is still in its cooldown period")]
+    WithdrawalCooldownActive,
+
+    #[msg("Invalid lock duration")]
+    InvalidLockDuration,
+
+    #[msg("A locked position account must be passed exactly when a lock duration is given")]
+    LockedPositionMismatch,
+
+    #[msg("Locked position has not reached its unlock time")]
+    PositionStillLocked,
+
+    #[msg("No premiums to claim")]
+    NoPremiumsToClaim,
+
+    #[msg("Not enough staked LP tokens that are unlocked")]
+    InsufficientStakedLp,
+
+    #[msg("Policy has already expired")]
+    PolicyExpired,
+
+    #[msg("Deposit exceeds the tranche or pool collateral cap")]
+    DepositCapExceeded,
+
+    #[msg("A tranche would have less junior collateral below it than its attachment point")]
+    JuniorRatioBelowMinimum,
+
+    #[msg("Tranche accounts must be passed for every tranche, most junior first")]
+    InvalidTrancheAccounts,
+
+    #[msg("Pool already has the maximum number of tranches")]
+    TooManyTranches,
+
+    #[msg("LP tokens can only be switched into a different tranche")]
+    SameTrancheSwitch,
+
+    #[msg("Only the treasury authority can claim protocol fees")]
+    UnauthorizedTreasuryAuthority,
+
+    #[msg("No protocol fees to claim")]
+    NoProtocolFeesToClaim,
+
+    #[msg("Reserve top-up amount should be greater than 0")]
+    ReserveTopUpZero,
+
+    #[msg(
+        "The settlement round for the policy's insured stablecoin and expiry day must be passed"
+    )]
+    InvalidSettlementRound,
+
+    #[msg("Oracle price is not below the depeg threshold")]
+    NoDepegDetected,
+
+    #[msg("No unsettled liability on this insured stablecoin and expiry day")]
+    NoLiabilityToSettle,
+
+    #[msg("A claim token account must be passed when the payout falls short")]
+    ClaimTokenAccountRequired,
+
+    #[msg("No funded claims to redeem")]
+    NoClaimsToRedeem,
+
+    #[msg("Policy would exceed the pool's liability limit for this insured stablecoin")]
+    AssetExposureLimitExceeded,
+
+    #[msg("A pool can't insure a depeg of its own collateral mint")]
+    WrongWayRisk,
+
+    #[msg("Oracle price must be positive")]
+    OraclePriceNotPositive,
+
+    #[msg("Buyer would exceed the pool's per-buyer coverage or active policy limit")]
+    BuyerLimitExceeded,
+
+    #[msg("Policy is not in the buyer's index of active policies")]
+    PolicyNotInBuyerIndex,
+
+    #[msg("Policies in this expiry window have not all expired yet")]
+    ObservationWindowNotClosed,
+
+    #[msg("No price observation recorded for the policy's expiry window")]
+    PriceObservationRequired,
+
+    #[msg("Oracle price update is not fully verified")]
+    OraclePriceUnverified,
+
+    #[msg("Oracle price was not published within MAX_OBSERVATION_DELAY_SECONDS of the observation window closing")]
+    OraclePriceOutsideWindow,
+
+    #[msg("A settlement round must be opened for a recorded depeg before its policies are paid")]
+    SettlementRoundRequired,
 }
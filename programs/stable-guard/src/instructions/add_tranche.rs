@@ -0,0 +1,153 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheSet, TrancheState};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+/// Parameters of a new tranche.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TrancheParams {
+    pub premium_weight_bps: u64,
+    pub attachment_point_bps: u64,
+    pub target_apr_bps: u64,
+    pub max_collateral: u64,
+}
+
+/// Adds a tranche on top of the pool's existing ones, so it is the most senior layer.
+#[derive(Accounts)]
+pub struct AddTranche<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = authority @ StableGuardError::UnauthorizedPoolAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TrancheState::INIT_SPACE,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[insurance_pool.tranche_count]
+        ],
+        bump
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [constants::TRANCHE_LP_MINT_SEED, tranche.key().as_ref()],
+        bump,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = pool_authority
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [constants::TRANCHE_VAULT_SEED, tranche.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = pool_authority
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [constants::LOCKED_LP_VAULT_SEED, lp_mint.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority
+    )]
+    pub locked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        seeds = [constants::STAKED_LP_VAULT_SEED, lp_mint.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority
+    )]
+    pub staked_lp_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's authority PDA
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> AddTranche<'info> {
+    /// Every existing tranche is passed in `remaining_accounts`, most junior first, so the
+    /// new tranche's attachment point can be checked against the one below it.
+    pub fn add_tranche(
+        &mut self,
+        bumps: &AddTrancheBumps,
+        params: TrancheParams,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            self.insurance_pool.tranche_count < constants::MAX_TRANCHES,
+            StableGuardError::TooManyTranches
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let index = self.insurance_pool.tranche_count;
+
+        self.tranche.set_inner(TrancheState {
+            insurance_pool: self.insurance_pool.key(),
+            index,
+            lp_mint: self.lp_mint.key(),
+            collateral_vault: self.collateral_vault.key(),
+            locked_lp_vault: self.locked_lp_vault.key(),
+            staked_lp_vault: self.staked_lp_vault.key(),
+            bump: bumps.tranche,
+            premium_weight_bps: params.premium_weight_bps,
+            attachment_point_bps: params.attachment_point_bps,
+            max_collateral: params.max_collateral,
+            collateral: 0,
+            lp_supply: 0,
+            target_apr_bps: params.target_apr_bps,
+            coupon_shortfall: 0,
+            coupon_accrued: 0,
+            coupon_paid: 0,
+            last_coupon_accrual_timestamp: now,
+            locked_lp: 0,
+            boost_weight: 0,
+            boost_reward_per_weight: 0,
+            boost_rewards: 0,
+            staked_lp: 0,
+            premium_per_lp_share: 0,
+            unclaimed_premiums: 0,
+            unearned_premium: 0,
+            premium_release_rate: 0,
+            last_premium_release_timestamp: now,
+        });
+        self.tranche.validate_config()?;
+        let tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+        if !tranches.is_empty() {
+            require!(
+                tranches.get(tranches.len() - 1).attachment_point_bps
+                    <= self.tranche.attachment_point_bps,
+                StableGuardError::InvalidPoolConfig
+            );
+        }
+        self.insurance_pool.tranche_count = index + 1;
+
+        msg!("Tranche #{} added", index);
+        Ok(())
+    }
+}
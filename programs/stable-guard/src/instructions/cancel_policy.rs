@@ -0,0 +1,169 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, AssetExposure, BuyerProfile, InsurancePool, PolicyAccount,
+    PolicyStatus, TrancheSet,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+pub struct CancelPolicy<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            constants::POLICY_SEED,
+            insurance_pool.key().as_ref(),
+            buyer.key().as_ref(),
+            policy_account.policy_id.to_le_bytes().as_ref()
+        ],
+        bump = policy_account.bump,
+        has_one = buyer @ StableGuardError::InvalidPolicyOwner,
+    )]
+    pub policy_account: Account<'info, PolicyAccount>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::ASSET_EXPOSURE_SEED,
+            insurance_pool.key().as_ref(),
+            policy_account.insured_stablecoin_mint.as_ref()
+        ],
+        bump = asset_exposure.bump,
+    )]
+    pub asset_exposure: Box<Account<'info, AssetExposure>>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::BUYER_PROFILE_SEED,
+            insurance_pool.key().as_ref(),
+            buyer.key().as_ref()
+        ],
+        bump = buyer_profile.bump,
+    )]
+    pub buyer_profile: Account<'info, BuyerProfile>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = buyer
+    )]
+    pub buyer_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the refund transfers.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(address = policy_account.mint)]
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelPolicy<'info> {
+    /// Every tranche and its collateral vault are passed in `remaining_accounts`, most
+    /// junior first.
+    pub fn cancel_policy(
+        &mut self,
+        bumps: &CancelPolicyBumps,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            self.policy_account.status == PolicyStatus::Active,
+            StableGuardError::PolicyAlreadyProcessed
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now < self.policy_account.expiry_timestamp,
+            StableGuardError::PolicyExpired
+        );
+
+        let policy_duration = self
+            .policy_account
+            .expiry_timestamp
+            .checked_sub(self.policy_account.start_timestamp)
+            .ok_or(StableGuardError::CalculationError)?;
+        let remaining_seconds = self.policy_account.expiry_timestamp - now;
+
+        let mut tranches = TrancheSet::load_with_vaults(&self.insurance_pool, remaining_accounts)?;
+        let mut total_refund = 0u64;
+        for (index, tranche_premium) in self.policy_account.tranche_premiums.iter().enumerate() {
+            let tranche = tranches.get_mut(index);
+            // LPs keep the premium for the time the policy was covered.
+            tranche.release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+            let refund = tranche.cancel_policy_premium(
+                *tranche_premium,
+                policy_duration,
+                remaining_seconds,
+            )?;
+            self.refund_premium(tranches.vault(index).clone(), refund, bumps.pool_authority)?;
+            total_refund = total_refund
+                .checked_add(refund)
+                .ok_or(StableGuardError::CalculationError)?;
+        }
+        tranches.exit()?;
+
+        self.insurance_pool.total_insured_value = self
+            .insurance_pool
+            .total_insured_value
+            .checked_sub(self.policy_account.insured_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.asset_exposure.remove_policy(
+            self.policy_account.payout_amount,
+            self.policy_account.expiry_timestamp,
+        )?;
+        self.buyer_profile.remove_policy(
+            self.policy_account.policy_id,
+            self.policy_account.insured_amount,
+        )?;
+        self.insurance_pool.liability_schedule.remove(
+            self.policy_account.expiry_timestamp,
+            self.policy_account.payout_amount,
+        )?;
+        self.policy_account.status = PolicyStatus::Cancelled;
+
+        msg!(
+            "Policy #{} cancelled. Refunded {} of unearned premium.",
+            self.policy_account.policy_id,
+            total_refund
+        );
+        Ok(())
+    }
+
+    fn refund_premium(
+        &self,
+        from: AccountInfo<'info>,
+        amount: u64,
+        authority_bump: u8,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[authority_bump]];
+        let signer_seeds = &[&authority_seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from,
+            mint: self.mint.to_account_info(),
+            to: self.buyer_token_account.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx, amount, self.mint.decimals)
+    }
+}
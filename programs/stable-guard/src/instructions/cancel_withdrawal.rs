@@ -0,0 +1,108 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheState, WithdrawalTicket};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{
+    close_account, transfer_checked, CloseAccount, Mint, Token, TokenAccount, TransferChecked,
+};
+
+/// Drops a pending withdrawal ticket and hands the escrowed LP tokens back to the
+/// underwriter, so a new ticket can be requested.
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    #[account(mut)]
+    pub underwriter: Signer<'info>,
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        close = underwriter,
+        seeds = [
+            constants::WITHDRAWAL_TICKET_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = withdrawal_ticket.bump,
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    #[account(
+        mut,
+        seeds = [constants::WITHDRAWAL_ESCROW_SEED, withdrawal_ticket.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = underwriter
+    )]
+    pub underwriter_lp_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(address = tranche.lp_mint @ StableGuardError::InvalidLpMint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// CHECK: The program's master authority PDA, owner of the LP escrow.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelWithdrawal<'info> {
+    pub fn cancel_withdrawal(&mut self, bumps: &CancelWithdrawalBumps) -> Result<()> {
+        let lp_amount = self.withdrawal_ticket.lp_amount;
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.withdrawal_escrow.to_account_info(),
+                    mint: self.lp_mint.to_account_info(),
+                    to: self.underwriter_lp_account.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_amount,
+            self.lp_mint.decimals,
+        )?;
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            CloseAccount {
+                account: self.withdrawal_escrow.to_account_info(),
+                destination: self.underwriter.to_account_info(),
+                authority: self.pool_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        msg!(
+            "Withdrawal of {} tranche #{} LP tokens cancelled.",
+            lp_amount,
+            self.withdrawal_ticket.tranche_index
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,12 @@
 pub use crate::constants;
-use crate::{error::StableGuardError, InsurancePool, PolicyAccount, PolicyStatus};
+use crate::{
+    error::StableGuardError, AssetExposure, BuyerProfile, DepegEvent, InsurancePool,
+    LiabilitySchedule, PolicyAccount, PolicyStatus, SettlementRound, TrancheSet,
+};
 use anchor_lang::prelude::*;
-use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
+use anchor_spl::token::{
+    mint_to, transfer_checked, Mint, MintTo, Token, TokenAccount, TransferChecked,
+};
 
 #[derive(Accounts)]
 
@@ -11,7 +15,7 @@ pub struct CheckAndPayout<'info> {
     pub policy_owner: UncheckedAccount<'info>, //buyer no longer signs. Anyone can call this to settle the policy
     #[account(
         mut,
-        seeds=[constants::POLICY_SEED,policy_account.buyer.key().as_ref(),policy_account.policy_id.to_le_bytes().as_ref()],
+        seeds=[constants::POLICY_SEED,insurance_pool.key().as_ref(),policy_account.buyer.key().as_ref(),policy_account.policy_id.to_le_bytes().as_ref()],
         bump = policy_account.bump,
         constraint = policy_account.buyer == policy_owner.key() @ StableGuardError::InvalidPolicyOwner
     )]
@@ -25,10 +29,68 @@ pub struct CheckAndPayout<'info> {
     pub insurance_pool: Account<'info, InsurancePool>,
     #[account(
         mut,
-        seeds = [constants::POOL_SEED,mint.key().as_ref()],
+        seeds = [
+            constants::ASSET_EXPOSURE_SEED,
+            insurance_pool.key().as_ref(),
+            policy_account.insured_stablecoin_mint.as_ref()
+        ],
+        bump = asset_exposure.bump,
+    )]
+    pub asset_exposure: Box<Account<'info, AssetExposure>>,
+    #[account(
+        mut,
+        seeds = [
+            constants::BUYER_PROFILE_SEED,
+            insurance_pool.key().as_ref(),
+            policy_owner.key().as_ref()
+        ],
+        bump = buyer_profile.bump,
+    )]
+    pub buyer_profile: Account<'info, BuyerProfile>,
+    /// Required when a settlement round was opened for the policy's insured stablecoin and
+    /// expiry day, which it must be before a depegged policy is paid.
+    #[account(
+        mut,
+        seeds = [
+            constants::SETTLEMENT_ROUND_SEED,
+            insurance_pool.key().as_ref(),
+            policy_account.insured_stablecoin_mint.as_ref(),
+            LiabilitySchedule::expiry_day(policy_account.expiry_timestamp).to_le_bytes().as_ref()
+        ],
+        bump = settlement_round.bump,
+    )]
+    pub settlement_round: Option<Account<'info, SettlementRound>>,
+    /// Price recorded for the policy's insured stablecoin and expiry day.
+    #[account(
+        seeds = [
+            constants::DEPEG_EVENT_SEED,
+            insurance_pool.key().as_ref(),
+            policy_account.insured_stablecoin_mint.as_ref(),
+            LiabilitySchedule::expiry_day(policy_account.expiry_timestamp).to_le_bytes().as_ref()
+        ],
+        bump = depeg_event.bump,
+    )]
+    pub depeg_event: Option<Account<'info, DepegEvent>>,
+    #[account(
+        mut,
+        seeds = [constants::CLAIM_MINT_SEED, insurance_pool.key().as_ref()],
+        bump
+    )]
+    pub claim_mint: Account<'info, Mint>,
+    /// Receives claim tokens for any part of the payout the pool can't cover. Required only
+    /// when there is a shortfall.
+    #[account(
+        mut,
+        token::mint = claim_mint,
+        token::authority = policy_owner
+    )]
+    pub owner_claim_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [constants::RESERVE_VAULT_SEED, mint.key().as_ref()],
         bump
     )]
-    pub collateral_token_pool: Account<'info, TokenAccount>,
+    pub reserve_vault: Account<'info, TokenAccount>,
     /// CHECK: The program's master authority PDA, required to sign for the payout transfer.
     #[account(
         seeds = [constants::AUTHORITY_SEED],
@@ -42,15 +104,21 @@ pub struct CheckAndPayout<'info> {
     )]
     pub payout_token_account: Account<'info, TokenAccount>,
     #[account(
-        address = collateral_token_pool.mint
+        address = reserve_vault.mint
     )]
     pub mint: Account<'info, Mint>,
-    pub pyth_price_update: Account<'info, PriceUpdateV2>,
     pub token_program: Program<'info, Token>,
 }
 
 impl<'info> CheckAndPayout<'info> {
-    pub fn check_payout(&mut self, bumps: &CheckAndPayoutBumps, _policy_id: u64) -> Result<()> {
+    /// Every tranche of the pool is passed in `remaining_accounts`, each followed by its
+    /// collateral vault, most junior first.
+    pub fn check_payout(
+        &mut self,
+        bumps: &CheckAndPayoutBumps,
+        _policy_id: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
         msg!(
             "Checking policy #{} for settlement...",
             self.policy_account.policy_id
@@ -61,123 +129,53 @@ impl<'info> CheckAndPayout<'info> {
             self.policy_account.status == PolicyStatus::Active,
             StableGuardError::PolicyAlreadyProcessed
         );
+        let now = Clock::get()?.unix_timestamp;
         require!(
-            Clock::get()?.unix_timestamp >= self.policy_account.expiry_timestamp,
+            now >= self.policy_account.expiry_timestamp,
             StableGuardError::PolicyNotExpired
         );
-        msg!("Policy is active and expired. Proceeding with oracle check.");
+        msg!("Policy is active and expired. Proceeding with settlement.");
 
-        // --- 2. Oracle Price Fetching ---
-        let relevant_feed_id_str = match self.policy_account.insured_stablecoin_mint {
-            key if key == constants::USDC_MINT_PUBKEY => constants::PYTH_USDC_USD_FEED_ID,
-            key if key == constants::USDT_MINT_PUBKEY => constants::PYTH_USDT_USD_FEED_ID,
-            _ => return err!(StableGuardError::InvalidStablecoinMint),
-        };
-        let feed_id = get_feed_id_from_hex(relevant_feed_id_str)?;
-
-        // Fetch the price, ensuring it's not older than the maximum allowed age.
-        // This is a critical defense against using stale data during network issues.
-        let price_data = self.pyth_price_update.get_price_no_older_than(
-            &Clock::get()?,
-            constants::MAX_ORACLE_AGE_SECONDS,
-            &feed_id,
-        )?;
+        let mut tranches = TrancheSet::load_with_vaults(&self.insurance_pool, remaining_accounts)?;
+        self.settle_premiums(now, &mut tranches)?;
+
+        // --- 2. Recorded Price ---
+        // Every policy expiring on the same day settles at the one price recorded for it,
+        // whichever keeper processes it.
+        let depeg_event = self
+            .depeg_event
+            .as_ref()
+            .ok_or(StableGuardError::PriceObservationRequired)?;
         msg!(
-            "Fetched price from Pyth feed {}: {}",
-            relevant_feed_id_str,
-            price_data.price
+            "Settling at price {} recorded for day {}.",
+            depeg_event.observed_price,
+            depeg_event.expiry_day
         );
-
-        // --- 3. Price Scaling ---
-        // Pyth prices have a dynamic exponent. We must scale the price to a common
-        // 8-decimal format to safely compare it with our `depeg_threshold`.
-        let scaled_pyth_price = {
-            let pyth_mantissa = price_data.price;
-            let pyth_exponent = price_data.exponent;
-            const TARGET_DECIMALS: i32 = 8;
-
-            require!(
-                pyth_exponent <= 0,
-                StableGuardError::OracleExponentUnexpected
-            );
-            let scale_difference = pyth_exponent.abs() - TARGET_DECIMALS;
-
-            if scale_difference > 0 {
-                pyth_mantissa
-                    .checked_div(10i64.pow(scale_difference as u32))
-                    .ok_or(StableGuardError::CalculationError)?
-            } else if scale_difference < 0 {
-                pyth_mantissa
-                    .checked_mul(10i64.pow(scale_difference.abs() as u32))
-                    .ok_or(StableGuardError::CalculationError)?
-            } else {
-                pyth_mantissa
-            }
-        };
-        msg!("Scaled oracle price (8 decimals): {}", scaled_pyth_price);
-
-        // --- 4. Confidence Check ---
-        // We must check the oracle's confidence interval. A wide interval suggests market
-        // turmoil or potential oracle issues. We calculate a max allowed confidence as a
-        // percentage (BPS) of the price itself.
-        let max_allowable_confidence = (scaled_pyth_price.abs() as u64)
-            .checked_mul(constants::MAX_CONFIDENCE_BPS)
-            .ok_or(StableGuardError::CalculationError)?
-            .checked_div(10000)
-            .ok_or(StableGuardError::CalculationError)?;
-
+        // A depeg is only paid out through a round, which sizes every policy's share of the
+        // collateral at once rather than paying whichever policy settles first in full.
+        let settled_in_round = depeg_event.settlement_round_opened;
         require!(
-            price_data.conf <= max_allowable_confidence,
-            StableGuardError::OracleConfidenceTooWide
+            !depeg_event.depegged || settled_in_round,
+            StableGuardError::SettlementRoundRequired
         );
-        msg!(
-            "Oracle confidence check passed ({} <= {}).",
-            price_data.conf,
-            max_allowable_confidence
+        require!(
+            self.settlement_round.is_some() == settled_in_round,
+            StableGuardError::InvalidSettlementRound
         );
 
-        // --- 5. De-peg Decision ---
-        if scaled_pyth_price < self.insurance_pool.depeg_threshold as i64 {
-            // --- 6a. Payout Execution ---
-            msg!("De-peg event DETECTED. Executing payout.");
-            require!(
-                self.collateral_token_pool.amount >= self.policy_account.payout_amount,
-                StableGuardError::InsufficientPoolCollateralForPayout
-            );
-
-            let authority_seeds_bump = bumps.pool_authority;
-            let authority_seeds = &[constants::AUTHORITY_SEED, &[authority_seeds_bump]];
-            let signer_seeds = &[&authority_seeds[..]];
-
-            let cpi_accounts_transfer = TransferChecked {
-                from: self.collateral_token_pool.to_account_info(),
-                to: self.payout_token_account.to_account_info(),
-                mint: self.mint.to_account_info(),
-                authority: self.pool_authority.to_account_info(),
-            };
-            let cpi_ctx_transfer = CpiContext::new_with_signer(
-                self.token_program.to_account_info(),
-                cpi_accounts_transfer,
-                signer_seeds,
-            );
-            transfer_checked(
-                cpi_ctx_transfer,
-                self.policy_account.payout_amount,
-                self.mint.decimals,
-            )?;
-
-            self.policy_account.status = PolicyStatus::ExpiredPaid;
-            msg!(
-                "Payout of {} transferred successfully.",
-                self.policy_account.payout_amount
-            );
+        let payout_amount = self.policy_account.payout_amount;
+        if settled_in_round {
+            // --- 3. Settlement Round ---
+            // The round only opens on a recorded depeg, so the policy is paid the round's
+            // recovery ratio from what the round set aside.
+            self.settle_in_round(payout_amount, bumps)?;
         } else {
-            // --- 6b. No Payout ---
+            // --- 4. No Payout ---
             msg!("No de-peg event detected. Closing policy without payout.");
             self.policy_account.status = PolicyStatus::ExpiredNotPaid;
         }
 
-        // --- 7. Final State Update ---
+        // --- 5. Final State Update ---
         // In both cases (paid or not), the policy is now settled, so we reduce the
         // pool's total insured value.
         self.insurance_pool.total_insured_value = self
@@ -185,8 +183,150 @@ impl<'info> CheckAndPayout<'info> {
             .total_insured_value
             .checked_sub(self.policy_account.insured_amount)
             .ok_or(StableGuardError::CalculationError)?;
+        if settled_in_round {
+            // The round took over the policy's liability when it opened.
+            self.asset_exposure.remove_settled_policy()?;
+        } else {
+            self.asset_exposure
+                .remove_policy(payout_amount, self.policy_account.expiry_timestamp)?;
+            self.insurance_pool
+                .liability_schedule
+                .remove(self.policy_account.expiry_timestamp, payout_amount)?;
+        }
+        self.buyer_profile.remove_policy(
+            self.policy_account.policy_id,
+            self.policy_account.insured_amount,
+        )?;
+        tranches.exit()?;
         msg!("Pool total insured value updated. Settlement complete.");
 
         Ok(())
     }
+
+    /// Pays the policy its round's share of the payout out of the settlement fund, and
+    /// claim tokens for the rest.
+    fn settle_in_round(&mut self, payout_amount: u64, bumps: &CheckAndPayoutBumps) -> Result<()> {
+        let round = self
+            .settlement_round
+            .as_ref()
+            .ok_or(StableGuardError::InvalidSettlementRound)?;
+        let paid = round.recovery_amount(payout_amount)?;
+        msg!(
+            "Settling in the round for day {} at {} bps recovery.",
+            round.expiry_day,
+            round.recovery_ratio_bps
+        );
+        self.pay_out(self.reserve_vault.to_account_info(), paid, bumps)?;
+        self.insurance_pool.settlement_fund = self
+            .insurance_pool
+            .settlement_fund
+            .checked_sub(paid)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.issue_claims(payout_amount - paid, bumps)?;
+
+        let round = self
+            .settlement_round
+            .as_mut()
+            .ok_or(StableGuardError::InvalidSettlementRound)?;
+        if round.record_settlement(payout_amount, paid)? {
+            // Rounding each payout down leaves a little of the set-aside collateral unpaid.
+            let unpaid = round.unpaid_set_aside()?;
+            self.insurance_pool.settlement_fund = self
+                .insurance_pool
+                .settlement_fund
+                .checked_sub(unpaid)
+                .ok_or(StableGuardError::CalculationError)?;
+            self.insurance_pool.add_to_reserve(unpaid)?;
+            msg!(
+                "Every policy in the round for day {} is settled. {} returned to the reserve.",
+                round.expiry_day,
+                unpaid
+            );
+        }
+        self.policy_account.status = PolicyStatus::ExpiredPaid;
+        msg!("Payout of {} transferred successfully.", paid);
+        Ok(())
+    }
+
+    /// Mints claim tokens for the part of the payout the pool couldn't cover. They are
+    /// redeemed from future premiums and reserve top-ups.
+    fn issue_claims(&mut self, shortfall: u64, bumps: &CheckAndPayoutBumps) -> Result<()> {
+        if shortfall == 0 {
+            return Ok(());
+        }
+        let owner_claim_account = self
+            .owner_claim_account
+            .as_ref()
+            .ok_or(StableGuardError::ClaimTokenAccountRequired)?;
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        mint_to(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                MintTo {
+                    mint: self.claim_mint.to_account_info(),
+                    to: owner_claim_account.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            shortfall,
+        )?;
+        self.insurance_pool.issue_claims(shortfall)?;
+        msg!(
+            "Issued {} claim tokens for the payout shortfall.",
+            shortfall
+        );
+        Ok(())
+    }
+
+    fn pay_out(
+        &self,
+        from: AccountInfo<'info>,
+        amount: u64,
+        bumps: &CheckAndPayoutBumps,
+    ) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+
+        let cpi_accounts_transfer = TransferChecked {
+            from,
+            to: self.payout_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx_transfer = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts_transfer,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx_transfer, amount, self.mint.decimals)
+    }
+
+    /// Releases this policy's premium up to its expiry, then stops releasing it.
+    fn settle_premiums(&self, now: i64, tranches: &mut TrancheSet<'info>) -> Result<()> {
+        let distribution_mode = self.insurance_pool.premium_distribution_mode;
+        let expiry_timestamp = self.policy_account.expiry_timestamp;
+        let policy_duration = expiry_timestamp
+            .checked_sub(self.policy_account.start_timestamp)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        for index in 0..tranches.len() {
+            let tranche = tranches.get_mut(index);
+            tranche.release_premiums(expiry_timestamp, distribution_mode)?;
+            // Tranches added after the policy was bought hold none of its premium.
+            if let Some(tranche_premium) = self.policy_account.tranche_premiums.get(index) {
+                tranche.settle_policy_premium(
+                    *tranche_premium,
+                    policy_duration,
+                    expiry_timestamp,
+                )?;
+            }
+            tranche.release_premiums(now, distribution_mode)?;
+        }
+        Ok(())
+    }
 }
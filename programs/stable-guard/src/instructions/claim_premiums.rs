@@ -0,0 +1,106 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheState, UnderwriterPosition};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+pub struct ClaimPremiums<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            owner.key().as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = underwriter_position.bump,
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = tranche.collateral_vault
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the premium transfer.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ClaimPremiums<'info> {
+    pub fn claim_premiums(&mut self, bumps: &ClaimPremiumsBumps) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+        let forfeited = self
+            .underwriter_position
+            .settle_premiums(self.tranche.premium_per_lp_share, now)?;
+        self.tranche.forfeit_premiums(forfeited)?;
+
+        let claim_amount = self.underwriter_position.premiums_owed;
+        require!(claim_amount > 0, StableGuardError::NoPremiumsToClaim);
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: self.collateral_vault.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.owner_token_account.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx, claim_amount, self.collateral_mint.decimals)?;
+
+        self.tranche.release_claimed_premiums(claim_amount)?;
+        self.underwriter_position.premiums_owed = 0;
+        self.underwriter_position.premiums_claimed = self
+            .underwriter_position
+            .premiums_claimed
+            .checked_add(claim_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        msg!(
+            "Claimed {} premiums from tranche #{}",
+            claim_amount,
+            self.tranche.index
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+pub struct ClaimProtocolFees<'info> {
+    pub treasury_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = treasury_authority @ StableGuardError::UnauthorizedTreasuryAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [constants::PROTOCOL_TREASURY_SEED, collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint
+    )]
+    pub destination_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the fee transfer.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ClaimProtocolFees<'info> {
+    /// Pays the fees accrued and not yet claimed to `destination_token_account`. Tokens sent
+    /// to the treasury vault by anyone else are not fees and stay put.
+    pub fn claim_protocol_fees(&mut self, bumps: &ClaimProtocolFeesBumps) -> Result<()> {
+        let claim_amount = self
+            .insurance_pool
+            .total_protocol_fees_accrued
+            .checked_sub(self.insurance_pool.total_protocol_fees_claimed)
+            .ok_or(StableGuardError::CalculationError)?;
+        require!(claim_amount > 0, StableGuardError::NoProtocolFeesToClaim);
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: self.treasury_vault.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.destination_token_account.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx, claim_amount, self.collateral_mint.decimals)?;
+
+        self.insurance_pool.total_protocol_fees_claimed = self
+            .insurance_pool
+            .total_protocol_fees_claimed
+            .checked_add(claim_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        msg!("Claimed {} of protocol fees", claim_amount);
+        Ok(())
+    }
+}
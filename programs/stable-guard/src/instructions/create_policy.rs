@@ -2,9 +2,13 @@ pub use crate::constants;
 use crate::state::policy::PolicyAccount;
 use crate::state::policy_status::PolicyStatus;
 use crate::state::pool::InsurancePool;
-use crate::{error::StableGuardError, USDC_MINT_PUBKEY};
+use crate::{
+    error::StableGuardError, oracle, AssetExposure, BuyerProfile, LiabilitySchedule, TrancheSet,
+    USDC_MINT_PUBKEY,
+};
 use anchor_lang::prelude::*;
 use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 
 #[derive(Accounts)]
 #[instruction(insured_amount:u64,policy_duration_seconds: i64)]
@@ -17,10 +21,10 @@ pub struct CreatePolicy<'info> {
         bump=insurance_pool.bump,
     )]
     pub insurance_pool: Account<'info, InsurancePool>,
-    #[account(  
+    #[account(
         init,
         payer = buyer,
-        seeds=[constants::POLICY_SEED,buyer.key().as_ref(),insurance_pool.last_policy_id
+        seeds=[constants::POLICY_SEED,insurance_pool.key().as_ref(),buyer.key().as_ref(),insurance_pool.last_policy_id
         .checked_add(1)
         .unwrap()
         .to_le_bytes()
@@ -39,11 +43,17 @@ pub struct CreatePolicy<'info> {
 
     #[account(
         mut,
-        seeds = [constants::POOL_SEED,mint.key().as_ref()],
-        bump,
-        token::mint = mint
+        seeds = [constants::PROTOCOL_TREASURY_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [constants::RESERVE_VAULT_SEED, mint.key().as_ref()],
+        bump
     )]
-    pub collateral_token_pool: Account<'info, TokenAccount>,
+    pub reserve_vault: Account<'info, TokenAccount>,
 
     #[account(
         constraint =  mint.key() == USDC_MINT_PUBKEY  @ StableGuardError::UnsupportedStablecoinMint //comment while testing
@@ -51,6 +61,36 @@ pub struct CreatePolicy<'info> {
     pub mint: Account<'info, Mint>,
 
     pub insured_stablecoin_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + AssetExposure::INIT_SPACE,
+        seeds = [
+            constants::ASSET_EXPOSURE_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub asset_exposure: Box<Account<'info, AssetExposure>>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerProfile::INIT_SPACE,
+        seeds = [
+            constants::BUYER_PROFILE_SEED,
+            insurance_pool.key().as_ref(),
+            buyer.key().as_ref()
+        ],
+        bump
+    )]
+    pub buyer_profile: Account<'info, BuyerProfile>,
+
+    /// Price update for the collateral mint, to value the liability its collateral backs.
+    pub collateral_price_update: Account<'info, PriceUpdateV2>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
 }
@@ -60,61 +100,201 @@ impl<'info> CreatePolicy<'info> {
         &mut self,
         bumps: &CreatePolicyBumps,
         insured_amount: u64,
-        policy_duration_seconds: i64, 
+        policy_duration_seconds: i64,
+        remaining_accounts: &'info [AccountInfo<'info>],
     ) -> Result<()> {
-        self.insurance_pool.last_policy_id = self.insurance_pool.last_policy_id.checked_add(1).ok_or(StableGuardError::CalculationError)?;
+        self.insurance_pool.last_policy_id = self
+            .insurance_pool
+            .last_policy_id
+            .checked_add(1)
+            .ok_or(StableGuardError::CalculationError)?;
         let new_policy_id = self.insurance_pool.last_policy_id;
         // Inside the createpolicy handler in create_policy.rs
-        if self.insured_stablecoin_mint.key() != constants::USDC_MINT_PUBKEY &&
-            self.insured_stablecoin_mint.key() != constants::USDT_MINT_PUBKEY {
+        if self.insured_stablecoin_mint.key() != constants::USDC_MINT_PUBKEY
+            && self.insured_stablecoin_mint.key() != constants::USDT_MINT_PUBKEY
+        {
             return err!(StableGuardError::UnsupportedStablecoinMint); // Or InvalidStablecoinMint
-            }   
+        }
+        // Payouts are made in the collateral mint, so cover against its own depeg would pay
+        // out in the very token that just lost its value.
+        require!(
+            self.insured_stablecoin_mint.key() != self.insurance_pool.collateral_mint,
+            StableGuardError::WrongWayRisk
+        );
+        require!(
+            policy_duration_seconds > 0
+                && policy_duration_seconds <= constants::MAX_POLICY_DURATION_SECONDS,
+            StableGuardError::InvalidPolicuDuration
+        );
+
+        if self.asset_exposure.insurance_pool == Pubkey::default() {
+            self.asset_exposure.set_inner(AssetExposure {
+                insurance_pool: self.insurance_pool.key(),
+                insured_mint: self.insured_stablecoin_mint.key(),
+                active_policies: 0,
+                active_liability: 0,
+                bump: bumps.asset_exposure,
+                max_liability: constants::NO_ASSET_LIABILITY_CAP,
+                max_liability_bps: constants::MAX_ASSET_LIABILITY_BPS,
+                liability_schedule: LiabilitySchedule::default(),
+            });
+        }
+        if self.buyer_profile.insurance_pool == Pubkey::default() {
+            self.buyer_profile.set_inner(BuyerProfile {
+                buyer: self.buyer.key(),
+                insurance_pool: self.insurance_pool.key(),
+                active_policy_ids: Vec::new(),
+                active_insured_amount: 0,
+                bump: bumps.buyer_profile,
+            });
+        }
+        // A single wallet can't soak up the pool's capacity.
+        require!(
+            self.buyer_profile.within_limits(
+                insured_amount,
+                self.insurance_pool.max_buyer_insured_amount,
+                self.insurance_pool.max_buyer_active_policies,
+            )?,
+            StableGuardError::BuyerLimitExceeded
+        );
         let current_timestamp = Clock::get()?.unix_timestamp;
-        
+
+        // Every tranche and its vault, most junior first, since the premium is split across all of them.
+        let mut tranches = TrancheSet::load_with_vaults(&self.insurance_pool, remaining_accounts)?;
+
+        // Bring share prices up to date before pricing against the pool.
+        for index in 0..tranches.len() {
+            tranches.get_mut(index).release_premiums(
+                current_timestamp,
+                self.insurance_pool.premium_distribution_mode,
+            )?;
+        }
+
         let expiry_timestamp = current_timestamp
             .checked_add(policy_duration_seconds)
             .ok_or(StableGuardError::CalculationError)?;
 
-        //read the current state of the pool
-        let pool = &self.insurance_pool;
+        let total_collateral = tranches.total_collateral()?;
+
+        let payout_amount = (insured_amount.checked_mul(constants::BINARY_PAYOUT_BPS as u64))
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000)
+            .ok_or(StableGuardError::CalculationError)?;
 
-        //calucation of the pool's utilization in basis points
-        let utilization_bps = if pool.total_collateral>0{
-            (pool.total_insured_value as u128).checked_mul(10000).ok_or(StableGuardError::CalculationError)?.checked_div(pool.total_collateral as u128).unwrap_or(0) as u64
+        // One stablecoin's depeg can't be allowed to take the whole pool with it.
+        require!(
+            self.asset_exposure
+                .within_limits(payout_amount, total_collateral)?,
+            StableGuardError::AssetExposureLimitExceeded
+        );
 
-        }else{
-            0 //if no collateral in the pool then utilization is 0
-        };
+        // No new exposure while any tranche lacks enough junior capital beneath it.
+        tranches.require_subordinated(0..tranches.len())?;
 
-        //determining the dynamic rate. Base rate + Utilization rate 
-        let dynamic_rate_bps = constants::PREMIUM_RATE_BPS.checked_add(utilization_bps).ok_or(StableGuardError::CalculationError)?;
+        // Locked capital counts in full towards capacity, unlocked capital only in part.
+        let underwriting_capacity =
+            tranches.underwriting_capacity(self.insurance_pool.unlocked_capital_weight_bps)?;
+        let total_liability = self
+            .insurance_pool
+            .total_liability()?
+            .checked_add(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        let collateral_price =
+            oracle::scaled_oracle_price(&self.collateral_price_update, self.mint.key())?;
+        let liability_in_collateral =
+            oracle::liability_in_collateral(total_liability, collateral_price)?;
+        require!(
+            underwriting_capacity >= liability_in_collateral,
+            StableGuardError::InsufficientPoolCollateralForPayout
+        );
 
-        let premium_paid = insured_amount.checked_mul(dynamic_rate_bps).ok_or(StableGuardError::CalculationError)?.checked_div(10000).ok_or(StableGuardError::CalculationError)?;
+        //calucation of the pool's utilization in basis points, measured the same way as the
+        //capacity check above so the price rises as the pool nears its limit
+        let utilization_bps = if underwriting_capacity > 0 {
+            (liability_in_collateral as u128)
+                .checked_mul(10000)
+                .ok_or(StableGuardError::CalculationError)?
+                .checked_div(underwriting_capacity as u128)
+                .unwrap_or(0) as u64
+        } else {
+            0 //if no capacity in the pool then utilization is 0
+        };
 
-        let payout_amount = (insured_amount.checked_mul(constants::BINARY_PAYOUT_BPS as u64))
+        //determining the dynamic rate. Base rate + Utilization rate
+        let dynamic_rate_bps = constants::PREMIUM_RATE_BPS
+            .checked_add(utilization_bps)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        let premium_paid = insured_amount
+            .checked_mul(dynamic_rate_bps)
             .ok_or(StableGuardError::CalculationError)?
             .checked_div(10000)
             .ok_or(StableGuardError::CalculationError)?;
 
-        require!(self.insurance_pool.total_collateral>=self.insurance_pool.total_insured_value.checked_add(payout_amount).ok_or(StableGuardError::CalculationError)?,StableGuardError::InsufficientPoolCollateralForPayout);
-        
-        let cpi_accounts = TransferChecked {
-            from: self.buyer_token_account.to_account_info(),
-            mint: self.mint.to_account_info(),
-            to: self.collateral_token_pool.to_account_info(),
-            authority: self.buyer.to_account_info(),
-        };
+        self.insurance_pool.total_insured_value = self
+            .insurance_pool
+            .total_insured_value
+            .checked_add(insured_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.asset_exposure
+            .add_policy(payout_amount, expiry_timestamp)?;
+        self.buyer_profile
+            .add_policy(new_policy_id, insured_amount)?;
+        self.insurance_pool
+            .liability_schedule
+            .add(expiry_timestamp, payout_amount)?;
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        // Route the premium straight into the tranche vaults so it accrues to the LPs of each tranche.
+        // The split is priced on the pool's exposure including this policy.
+        // The protocol's fee is taken off the top and never refunded; the tranches split the rest.
+        let protocol_fee = self.insurance_pool.protocol_fee(premium_paid)?;
+        self.transfer_premium(self.treasury_vault.to_account_info(), protocol_fee)?;
+        self.insurance_pool.total_protocol_fees_accrued = self
+            .insurance_pool
+            .total_protocol_fees_accrued
+            .checked_add(protocol_fee)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        // Unredeemed claim tokens are senior to the LPs, so the premium funds them first.
+        let premium_after_fee = premium_paid - protocol_fee;
+        let mut net_premium = premium_after_fee;
+        let claims_funding = self.insurance_pool.fund_claims(net_premium)?;
+        net_premium -= claims_funding;
+
+        // The reserve's part is set aside to absorb claims before they reach LP capital. It is
+        // sized on the premium after the fee, so the fee never comes out of the reserve's cut.
+        let reserve_contribution = self
+            .insurance_pool
+            .reserve_contribution(premium_after_fee)?
+            .min(net_premium);
+        net_premium -= reserve_contribution;
+        self.transfer_premium(
+            self.reserve_vault.to_account_info(),
+            claims_funding + reserve_contribution,
+        )?;
+        self.insurance_pool.add_to_reserve(reserve_contribution)?;
 
-        transfer_checked(cpi_ctx, premium_paid, self.mint.decimals)?;
+        let tranche_premiums =
+            tranches.split_premium(&self.insurance_pool, net_premium, current_timestamp)?;
+
+        for (index, tranche_premium) in tranche_premiums.iter().enumerate() {
+            self.transfer_premium(tranches.vault(index).clone(), *tranche_premium)?;
+
+            // The premium is earned over the policy's term, so LPs can't capture it by
+            // depositing just before a purchase and leaving right after.
+            tranches
+                .get_mut(index)
+                .add_unearned_premium(*tranche_premium, policy_duration_seconds)?;
+        }
+        tranches.exit()?;
 
         self.policy_account.set_inner(PolicyAccount {
-            policy_id:new_policy_id,
+            policy_id: new_policy_id,
             buyer: self.buyer.key(),
             insured_stablecoin_mint: self.insured_stablecoin_mint.key(),
             insured_amount,
             premium_paid,
+            tranche_premiums,
             payout_amount,
             start_timestamp: current_timestamp,
             expiry_timestamp,
@@ -123,11 +303,6 @@ impl<'info> CreatePolicy<'info> {
             mint: self.mint.key(),
         });
 
-        self.insurance_pool.total_insured_value = self
-            .insurance_pool
-            .total_insured_value
-            .checked_add(insured_amount)
-            .ok_or(StableGuardError::CalculationError)?;
         msg!(
             "Policy #{} created for buyer {}. Insured amount: {}. Premium paid: {}",
             new_policy_id,
@@ -137,4 +312,21 @@ impl<'info> CreatePolicy<'info> {
         );
         Ok(())
     }
+
+    fn transfer_premium(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let cpi_accounts = TransferChecked {
+            from: self.buyer_token_account.to_account_info(),
+            mint: self.mint.to_account_info(),
+            to,
+            authority: self.buyer.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+        transfer_checked(cpi_ctx, amount, self.mint.decimals)
+    }
 }
@@ -1,12 +1,16 @@
 pub use crate::constants;
-use crate::{error::StableGuardError, InsurancePool, Tranche};
+use crate::{
+    error::StableGuardError, InsurancePool, LockedPosition, TrancheSet, TrancheState,
+    UnderwriterPosition,
+};
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{mint_to, Mint, MintTo, Token, TokenAccount, Transfer},
+    token::{mint_to, transfer, Mint, MintTo, Token, TokenAccount, Transfer},
 };
 
 #[derive(Accounts)]
+#[instruction(deposit_amount: u64, min_lp_out: u64, tranche_index: u8)]
 pub struct DepositCollateral<'info> {
     #[account(mut)]
     pub underwriter: Signer<'info>,
@@ -25,33 +29,63 @@ pub struct DepositCollateral<'info> {
 
     #[account(
         mut,
-        seeds= [constants::JUNIOR_COLLATERAL_POOL_SEED,collateral_mint.key().as_ref()],
-        bump
+        seeds = [constants::TRANCHE_SEED, insurance_pool.key().as_ref(), &[tranche_index]],
+        bump = tranche.bump,
     )]
-    pub junior_collateral_pool: Account<'info, TokenAccount>,
+    pub tranche: Account<'info, TrancheState>,
 
     #[account(
         mut,
-        seeds= [constants::SENIOR_COLLATERAL_POOL_SEED,collateral_mint.key().as_ref()],
-        bump
+        address = tranche.collateral_vault
     )]
-    pub senior_collateral_pool: Account<'info, TokenAccount>,
+    pub collateral_vault: Account<'info, TokenAccount>,
 
     #[account(
         mut,
-        address = insurance_pool.junior_lp_mint
+        address = tranche.lp_mint
     )]
-    pub junior_lp_mint: Account<'info, Mint>,
+    pub lp_mint: Account<'info, Mint>,
 
     #[account(
         mut,
-        address = insurance_pool.senior_lp_mint
+        address = tranche.locked_lp_vault
     )]
-    pub senior_lp_mint: Account<'info, Mint>,
+    pub locked_lp_vault: Account<'info, TokenAccount>,
 
-    ///CHECK: this will be the underwriter's ATA for junior or senior LP tokens
-    #[account(mut)]
-    pub underwriter_lp_token_account: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = tranche.staked_lp_vault
+    )]
+    pub staked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = underwriter,
+        space = 8 + UnderwriterPosition::INIT_SPACE,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[tranche_index]
+        ],
+        bump
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    /// Only passed for locked deposits.
+    #[account(
+        init,
+        payer = underwriter,
+        space = 8 + LockedPosition::INIT_SPACE,
+        seeds = [
+            constants::LOCKED_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            insurance_pool.last_locked_position_id.checked_add(1).unwrap().to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub locked_position: Option<Account<'info, LockedPosition>>,
 
     /// CHECK: The program's master authority PDA.
     #[account(seeds = [constants::AUTHORITY_SEED], bump)]
@@ -64,85 +98,153 @@ pub struct DepositCollateral<'info> {
 }
 
 impl<'info> DepositCollateral<'info> {
-    pub fn deposit_collateral(&mut self, deposit_amount: u64, tranche: Tranche) -> Result<()> {
-        //just determine which tranche and state to use
-
-        let (target_collateral_pool, target_lp_mint, tranche_total_collateral, lp_supply) =
-            match tranche {
-                Tranche::Junior => (
-                    self.junior_collateral_pool.to_account_info(),
-                    &self.junior_lp_mint,
-                    self.insurance_pool.junior_tranche_collateral,
-                    self.junior_lp_mint.supply,
-                ),
-                Tranche::Senior => (
-                    self.senior_collateral_pool.to_account_info(),
-                    &self.senior_lp_mint,
-                    self.insurance_pool.senior_tranche_collateral,
-                    self.senior_lp_mint.supply,
-                ),
-            };
-
-        //i transfer collateral from underwriter to whichever vault
+    /// Every tranche of the pool is passed in `remaining_accounts`, most junior first, to
+    /// check the deposit against the pool cap and the attachment points.
+    pub fn deposit_collateral(
+        &mut self,
+        bumps: &DepositCollateralBumps,
+        deposit_amount: u64,
+        min_lp_out: u64,
+        tranche_index: u8,
+        lock_duration_seconds: Option<i64>,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let index = tranche_index as usize;
+
+        // settle the coupon on the old balance before it changes, and release premium earned
+        // up to now so it is priced into the old LP shares only
+        self.tranche
+            .accrue_coupon(now, self.insurance_pool.coupon_applies())?;
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+
+        if let Some(lock_duration_seconds) = lock_duration_seconds {
+            require!(
+                lock_duration_seconds > 0
+                    && lock_duration_seconds <= self.insurance_pool.max_lock_duration_seconds,
+                StableGuardError::InvalidLockDuration
+            );
+        }
+        require!(
+            lock_duration_seconds.is_some() == self.locked_position.is_some(),
+            StableGuardError::LockedPositionMismatch
+        );
+
+        let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+        tranches.sync(&self.tranche);
+        require!(
+            deposit_amount
+                <= tranches.deposit_capacity(index, self.insurance_pool.max_pool_collateral)?,
+            StableGuardError::DepositCapExceeded
+        );
+
+        //i transfer collateral from underwriter to the tranche vault
         transfer(
             CpiContext::new(
                 self.token_program.to_account_info(),
                 Transfer {
                     from: self.underwriter_token_account.to_account_info(),
-                    to: target_collateral_pool,
+                    to: self.collateral_vault.to_account_info(),
                     authority: self.underwriter.to_account_info(),
                 },
             ),
             deposit_amount,
-        );
+        )?;
 
         //calculate LP tokens to mint
 
-        let lp_tokens = if lp_supply == 0 || tranche_total_collateral == 0 {
-            deposit_amount
-        } else {
-            (deposit_amount as u128)
-                .checked_mul(lp_supply as u128)
-                .ok_or(StableGuardError::CalculationError)?
-                .checked_div(tranche_total_collateral as u128)
-                .ok_or(StableGuardError::CalculationError)? as u64
-        };
+        let lp_tokens = self.tranche.lp_tokens_for_deposit(deposit_amount)?;
 
         require!(lp_tokens > 0, StableGuardError::DepositTooSmallToMintLp);
+        require!(lp_tokens >= min_lp_out, StableGuardError::SlippageExceeded);
 
-        //minting lp tokens to underwriter
+        //minting lp tokens staked for the underwriter, or into the locked vault for locked
+        //deposits
+        let lp_destination = if lock_duration_seconds.is_some() {
+            self.locked_lp_vault.to_account_info()
+        } else {
+            self.staked_lp_vault.to_account_info()
+        };
         mint_to(
             CpiContext::new_with_signer(
                 self.token_program.to_account_info(),
                 MintTo {
-                    mint: target_lp_mint.to_account_info(),
-                    to: self.underwriter_lp_token_account.to_account_info(),
+                    mint: self.lp_mint.to_account_info(),
+                    to: lp_destination,
                     authority: self.pool_authority.to_account_info(),
                 },
-                &[&[constants::AUTHORITY_SEED, &[self.bumps.pool_authority]]],
+                &[&[constants::AUTHORITY_SEED, &[bumps.pool_authority]]],
             ),
-            lp_tokens_to_mint,
+            lp_tokens,
         )?;
 
-        //updating the state of the pool for that tranche
-
-        match tranche {
-            Tranche::Junior => {
-                self.insurance_pool.junior_tranche_collateral = self
-                    .insurance_pool
-                    .junior_tranche_collateral
-                    .checked_add(deposit_amount)
-                    .ok_or(StableGuardError::CalculationError)?;
-            }
-            Tranche::Senior => {
-                self.insurance_pool.senior_tranche_collateral = self
-                    .insurance_pool
-                    .senior_tranche_collateral
-                    .checked_add(deposit_amount)
-                    .ok_or(StableGuardError::CalculationError)?;
-            }
+        //updating the state of the tranche
+        self.tranche.record_lp_minted(lp_tokens)?;
+        self.tranche.record_lp_staked(lp_tokens)?;
+        self.tranche.credit(deposit_amount)?;
+
+        // The new capital dilutes the subordination of this tranche and every tranche below it.
+        tranches.sync(&self.tranche);
+        tranches.require_subordinated(0..index + 1)?;
+
+        //record the deposit against the underwriter's position
+        if self.underwriter_position.owner == Pubkey::default() {
+            self.underwriter_position
+                .set_inner(UnderwriterPosition::new(
+                    self.underwriter.key(),
+                    self.insurance_pool.key(),
+                    tranche_index,
+                    bumps.underwriter_position,
+                ));
+        }
+        let forfeited = self.underwriter_position.record_deposit(
+            deposit_amount,
+            lp_tokens,
+            lock_duration_seconds.is_some(),
+            self.tranche.premium_per_lp_share,
+            now,
+            self.insurance_pool.lp_warmup_seconds,
+        )?;
+        self.tranche.forfeit_premiums(forfeited)?;
+
+        if let (Some(lock_duration_seconds), Some(locked_position)) =
+            (lock_duration_seconds, self.locked_position.as_mut())
+        {
+            let boost_weight = self
+                .insurance_pool
+                .boost_weight_for_lock(lp_tokens, lock_duration_seconds)?;
+            self.insurance_pool.last_locked_position_id = self
+                .insurance_pool
+                .last_locked_position_id
+                .checked_add(1)
+                .ok_or(StableGuardError::CalculationError)?;
+            self.tranche.add_locked_position(lp_tokens, boost_weight)?;
+
+            let unlock_timestamp = now
+                .checked_add(lock_duration_seconds)
+                .ok_or(StableGuardError::CalculationError)?;
+            locked_position.set_inner(LockedPosition {
+                position_id: self.insurance_pool.last_locked_position_id,
+                owner: self.underwriter.key(),
+                insurance_pool: self.insurance_pool.key(),
+                tranche_index,
+                deposit_amount,
+                lp_amount: lp_tokens,
+                boost_weight,
+                boost_reward_per_weight_snapshot: self.tranche.boost_reward_per_weight,
+                lock_start_timestamp: now,
+                unlock_timestamp,
+                bump: bumps.locked_position.unwrap(),
+            });
+            msg!(
+                "Locked {} LP tokens until {} with boost weight {}",
+                lp_tokens,
+                unlock_timestamp,
+                boost_weight
+            );
         }
 
-        Ok(());
+        Ok(())
     }
 }
@@ -0,0 +1,253 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, oracle, InsurancePool, TrancheSet, TrancheState, UnderwriterPosition,
+    WithdrawalTicket,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{
+    burn, close_account, transfer_checked, Burn, CloseAccount, Mint, Token, TokenAccount,
+    TransferChecked,
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    #[account(mut)]
+    pub underwriter: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        close = underwriter,
+        seeds = [
+            constants::WITHDRAWAL_TICKET_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = withdrawal_ticket.bump,
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    /// Opened empty if the underwriter is withdrawing LP tokens they were sent rather than
+    /// minted.
+    #[account(
+        init_if_needed,
+        payer = underwriter,
+        space = 8 + UnderwriterPosition::INIT_SPACE,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        seeds = [constants::WITHDRAWAL_ESCROW_SEED, withdrawal_ticket.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = underwriter
+    )]
+    pub underwriter_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = tranche.collateral_vault
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = tranche.lp_mint
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the collateral transfer
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    /// Price update for the collateral mint, to value the liability its collateral backs.
+    pub collateral_price_update: Account<'info, PriceUpdateV2>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ExecuteWithdrawal<'info> {
+    /// Takes the pool's tranches as `TrancheSet::load` does, for the liability and
+    /// attachment point checks.
+    pub fn execute_withdrawal(
+        &mut self,
+        bumps: &ExecuteWithdrawalBumps,
+        min_collateral_out: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let index = self.withdrawal_ticket.tranche_index as usize;
+        let lp_amount_to_burn = self.withdrawal_ticket.lp_amount;
+        let now = Clock::get()?.unix_timestamp;
+        msg!(
+            "Executing tranche #{} withdrawal for {} LP tokens...",
+            index,
+            lp_amount_to_burn
+        );
+
+        // Settle the coupon on the old balance before it changes.
+        self.tranche
+            .accrue_coupon(now, self.insurance_pool.coupon_applies())?;
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+        let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+        tranches.sync(&self.tranche);
+        if self.underwriter_position.owner == Pubkey::default() {
+            self.underwriter_position
+                .set_inner(UnderwriterPosition::new(
+                    self.underwriter.key(),
+                    self.insurance_pool.key(),
+                    self.withdrawal_ticket.tranche_index,
+                    bumps.underwriter_position,
+                ));
+        }
+
+        // --- 1. Cooldown Check ---
+        require!(
+            now >= self.withdrawal_ticket.unlock_timestamp,
+            StableGuardError::WithdrawalCooldownActive
+        );
+
+        let tranche_collateral = self.tranche.collateral;
+        require!(
+            self.tranche.lp_supply > 0,
+            StableGuardError::NolpTokensToBurn
+        );
+
+        // --- 2. Calculate Collateral to Return ---
+        // Priced now rather than at request time, so the escrowed LP tokens share in any
+        // losses the tranche took while the ticket was cooling down.
+        let collateral_to_withdraw = self.tranche.collateral_for_lp_tokens(lp_amount_to_burn)?;
+
+        require!(
+            collateral_to_withdraw > 0,
+            StableGuardError::WithdrawalResultsInZeroUsdc
+        );
+        require!(
+            collateral_to_withdraw >= min_collateral_out,
+            StableGuardError::SlippageExceeded
+        );
+
+        // --- 3. CRITICAL Solvency Check ---
+        // Each tranche may only withdraw surplus over the liability it carries. Losses hit
+        // the junior tranches first, so their capital stays locked while it backs policies,
+        // and senior LPs can only leave if the tranches below still cover the rest.
+        let remaining_tranche_collateral = tranche_collateral
+            .checked_sub(collateral_to_withdraw)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        let total_liability = oracle::exit_liability_in_collateral(
+            &self.insurance_pool,
+            &self.collateral_price_update,
+            self.collateral_mint.key(),
+        )?;
+        require!(
+            remaining_tranche_collateral >= tranches.liability(index, total_liability)?,
+            StableGuardError::WithdrawalBlockedByUtilization
+        );
+        msg!(
+            "Solvency check passed. Withdrawing {} of collateral.",
+            collateral_to_withdraw
+        );
+
+        let authority_seeds_bump = bumps.pool_authority;
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[authority_seeds_bump]];
+        let signer_seeds = &[&authority_seeds[..]];
+
+        // --- 4. Burn Escrowed LP Tokens ---
+        let cpi_accounts_burn = Burn {
+            mint: self.lp_mint.to_account_info(),
+            from: self.withdrawal_escrow.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx_burn = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts_burn,
+            signer_seeds,
+        );
+        burn(cpi_ctx_burn, lp_amount_to_burn)?;
+        msg!("LP tokens burned.");
+
+        let cpi_accounts_close = CloseAccount {
+            account: self.withdrawal_escrow.to_account_info(),
+            destination: self.underwriter.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx_close = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts_close,
+            signer_seeds,
+        );
+        close_account(cpi_ctx_close)?;
+
+        // --- 5. Transfer Collateral to Underwriter ---
+        let cpi_accounts_transfer = TransferChecked {
+            from: self.collateral_vault.to_account_info(),
+            to: self.underwriter_token_account.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx_transfer = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts_transfer,
+            signer_seeds,
+        );
+        transfer_checked(
+            cpi_ctx_transfer,
+            collateral_to_withdraw,
+            self.collateral_mint.decimals,
+        )?;
+        msg!("Collateral transferred to underwriter.");
+
+        // --- 6. Update Tranche State ---
+        self.tranche.record_lp_burned(lp_amount_to_burn)?;
+        self.tranche.debit(collateral_to_withdraw)?;
+        // Pulling capital out of this tranche thins the cushion under every tranche above it.
+        tranches.sync(&self.tranche);
+        tranches.require_subordinated(index + 1..tranches.len())?;
+        self.underwriter_position
+            .record_withdrawal(lp_amount_to_burn, collateral_to_withdraw)?;
+        msg!(
+            "Tranche #{} collateral updated to: {}",
+            index,
+            remaining_tranche_collateral
+        );
+        msg!("Withdrawal successful.");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+pub use crate::constants;
+use crate::InsurancePool;
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Liability that stops backing policies once the day ending at `release_timestamp` is
+/// over and its policies are settled.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LiabilityRelease {
+    pub release_timestamp: i64,
+    pub liability: u64,
+}
+
+/// When the capital backing the pool's policies frees up for withdrawals.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct CapitalReleaseSchedule {
+    pub total_liability: u64,
+    /// Liability of policies long expired but not yet settled.
+    pub overdue_liability: u64,
+    pub releases: Vec<LiabilityRelease>,
+}
+
+#[derive(Accounts)]
+pub struct GetCapitalReleaseSchedule<'info> {
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    pub collateral_mint: Account<'info, Mint>,
+}
+
+impl<'info> GetCapitalReleaseSchedule<'info> {
+    pub fn get_capital_release_schedule(&self) -> Result<CapitalReleaseSchedule> {
+        let schedule = &self.insurance_pool.liability_schedule;
+        Ok(CapitalReleaseSchedule {
+            total_liability: schedule.total_liability()?,
+            overdue_liability: schedule.overdue_liability,
+            releases: schedule
+                .upcoming()
+                .iter()
+                .map(|bucket| LiabilityRelease {
+                    release_timestamp: (bucket.expiry_day + 1) * constants::SECONDS_PER_DAY,
+                    liability: bucket.liability,
+                })
+                .collect(),
+        })
+    }
+}
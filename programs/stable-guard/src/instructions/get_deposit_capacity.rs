@@ -0,0 +1,56 @@
+pub use crate::constants;
+use crate::{InsurancePool, TrancheSet};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Collateral held in one tranche against its cap, and how much more it accepts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TrancheCapacity {
+    pub index: u8,
+    pub collateral: u64,
+    pub max_collateral: u64,
+    pub deposit_capacity: u64,
+}
+
+/// Collateral held against the deposit caps, and how much more each tranche accepts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct DepositCapacity {
+    pub max_pool_collateral: u64,
+    pub tranches: Vec<TrancheCapacity>,
+}
+
+/// Expects every tranche of the pool in the remaining accounts, most junior first.
+#[derive(Accounts)]
+pub struct GetDepositCapacity<'info> {
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    pub collateral_mint: Account<'info, Mint>,
+}
+
+impl<'info> GetDepositCapacity<'info> {
+    pub fn get_deposit_capacity(
+        &self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<DepositCapacity> {
+        let pool = &self.insurance_pool;
+        let tranches = TrancheSet::load(pool, remaining_accounts)?;
+        let mut capacities = Vec::with_capacity(tranches.len());
+        for index in 0..tranches.len() {
+            let tranche = tranches.get(index);
+            capacities.push(TrancheCapacity {
+                index: tranche.index,
+                collateral: tranche.collateral,
+                max_collateral: tranche.max_collateral,
+                deposit_capacity: tranches.deposit_capacity(index, pool.max_pool_collateral)?,
+            });
+        }
+        Ok(DepositCapacity {
+            max_pool_collateral: pool.max_pool_collateral,
+            tranches: capacities,
+        })
+    }
+}
@@ -0,0 +1,79 @@
+pub use crate::constants;
+use crate::{InsurancePool, TrancheState, UnderwriterPosition};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Snapshot of an underwriter's position valued at the tranche's current share price.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct UnderwriterPnl {
+    pub lp_balance: u64,
+    pub deposited_principal: u64,
+    pub withdrawn_amount: u64,
+    pub avg_entry_share_price: u128,
+    pub current_value: u64,
+    pub cost_basis: u64,
+    /// Claimed premiums are counted in `realized_pnl`, claimable ones in `unrealized_pnl`.
+    pub premiums_claimed: u64,
+    pub premiums_claimable: u64,
+    pub realized_pnl: i64,
+    pub unrealized_pnl: i64,
+}
+
+#[derive(Accounts)]
+pub struct GetUnderwriterPnl<'info> {
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter_position.owner.as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = underwriter_position.bump,
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+    pub collateral_mint: Account<'info, Mint>,
+}
+
+impl<'info> GetUnderwriterPnl<'info> {
+    pub fn get_underwriter_pnl(&self) -> Result<UnderwriterPnl> {
+        let position = &self.underwriter_position;
+        let current_value = self.tranche.collateral_for_lp_tokens(position.lp_balance)?;
+        let cost_basis = position.cost_basis(position.lp_balance)?;
+
+        let mut settled_position = (**position).clone();
+        settled_position.settle_premiums(
+            self.tranche.premium_per_lp_share,
+            Clock::get()?.unix_timestamp,
+        )?;
+        let premiums_claimable = settled_position.premiums_owed;
+
+        Ok(UnderwriterPnl {
+            lp_balance: position.lp_balance,
+            deposited_principal: position.deposited_principal,
+            withdrawn_amount: position.withdrawn_amount,
+            avg_entry_share_price: position.avg_entry_share_price,
+            current_value,
+            cost_basis,
+            premiums_claimed: position.premiums_claimed,
+            premiums_claimable,
+            realized_pnl: position.realized_pnl + position.premiums_claimed as i64,
+            unrealized_pnl: current_value as i64 - cost_basis as i64 + premiums_claimable as i64,
+        })
+    }
+}
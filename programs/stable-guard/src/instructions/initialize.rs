@@ -1,5 +1,6 @@
 pub use crate::constants;
 use crate::state::pool::InsurancePool;
+use crate::{LiabilitySchedule, PremiumDistributionMode, PremiumSplitMode};
 use anchor_lang::prelude::*;
 use anchor_spl::token::{Mint, Token, TokenAccount};
 
@@ -20,42 +21,32 @@ pub struct Initialize<'info> {
     #[account(
         init,
         payer = authority,
-        seeds = [constants::JUNIOR_LP_MINT_SEED, collateral_mint.key().as_ref()],
+        seeds = [constants::PROTOCOL_TREASURY_SEED, collateral_mint.key().as_ref()],
         bump,
-        mint::decimals = collateral_mint.decimals,
-        mint::authority = pool_authority
+        token::mint = collateral_mint,
+        token::authority = pool_authority
     )]
-    pub junior_lp_mint: Account<'info, Mint>,
+    pub treasury_vault: Account<'info, TokenAccount>,
 
     #[account(
         init,
         payer = authority,
-        seeds = [constants::JUNIOR_COLLATERAL_POOL_SEED, collateral_mint.key().as_ref()],
+        seeds = [constants::RESERVE_VAULT_SEED, collateral_mint.key().as_ref()],
         bump,
         token::mint = collateral_mint,
         token::authority = pool_authority
     )]
-    pub junior_collateral_pool: Account<'info, TokenAccount>,
+    pub reserve_vault: Account<'info, TokenAccount>,
 
     #[account(
         init,
         payer = authority,
-        seeds = [constants::SENIOR_LP_MINT_SEED, collateral_mint.key().as_ref()],
+        seeds = [constants::CLAIM_MINT_SEED, insurance_pool.key().as_ref()],
         bump,
         mint::decimals = collateral_mint.decimals,
         mint::authority = pool_authority
     )]
-    pub senior_lp_mint: Account<'info, Mint>,
-
-    #[account(
-        init,
-        payer = authority,
-        seeds = [constants::SENIOR_COLLATERAL_POOL_SEED, collateral_mint.key().as_ref()],
-        bump,
-        token::mint = collateral_mint,
-        token::authority = pool_authority
-    )]
-    pub senior_collateral_pool: Account<'info, TokenAccount>,
+    pub claim_mint: Account<'info, Mint>,
 
     ///CHECK: The program's authority PDA
     #[account(
@@ -76,16 +67,34 @@ impl<'info> Initialize<'info> {
         self.insurance_pool.set_inner(InsurancePool {
             authority: self.authority.key(),
             collateral_mint: self.collateral_mint.key(),
-            total_insured_amount: 0,
+            total_insured_value: 0,
             depeg_threshold,
             last_policy_id: 0,
             bump: bumps.insurance_pool,
-            junior_tranche_collateral: 0,
-            senior_tranche_collateral: 0,
-            junior_lp_mint: self.junior_lp_mint.key(),
-            senior_lp_mint: self.senior_lp_mint.key(),
-            junior_tranche_share: constants::JUNIOR_PREMIUM_SHARE_BPS,
-            senior_tranche_share: constants::SENIOR_PREMIUM_SHARE_BPS,
+            tranche_count: 0,
+            premium_split_mode: PremiumSplitMode::SeniorCoupon,
+            min_junior_premium_share_bps: constants::MIN_JUNIOR_PREMIUM_SHARE_BPS,
+            max_junior_premium_share_bps: constants::MAX_JUNIOR_PREMIUM_SHARE_BPS,
+            withdrawal_cooldown_seconds: constants::WITHDRAWAL_COOLDOWN_SECONDS,
+            last_locked_position_id: 0,
+            lock_boost_bps_per_year: constants::LOCK_BOOST_BPS_PER_YEAR,
+            max_lock_duration_seconds: constants::MAX_LOCK_DURATION_SECONDS,
+            unlocked_capital_weight_bps: constants::UNLOCKED_CAPITAL_WEIGHT_BPS,
+            premium_distribution_mode: PremiumDistributionMode::Compound,
+            lp_warmup_seconds: 0,
+            max_pool_collateral: constants::NO_DEPOSIT_CAP,
+            treasury_authority: self.authority.key(),
+            protocol_fee_bps: 0,
+            total_protocol_fees_accrued: 0,
+            total_protocol_fees_claimed: 0,
+            reserve_premium_bps: constants::RESERVE_PREMIUM_BPS,
+            reserve_balance: 0,
+            settlement_fund: 0,
+            outstanding_claims: 0,
+            claims_fund: 0,
+            liability_schedule: LiabilitySchedule::default(),
+            max_buyer_insured_amount: constants::NO_BUYER_COVERAGE_CAP,
+            max_buyer_active_policies: constants::MAX_BUYER_ACTIVE_POLICIES,
         });
 
         Ok(())
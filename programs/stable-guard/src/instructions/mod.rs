@@ -1,11 +1,51 @@
+pub mod add_tranche;
+pub mod cancel_policy;
+pub mod cancel_withdrawal;
 pub mod check_and_payout;
+pub mod claim_premiums;
+pub mod claim_protocol_fees;
 pub mod create_policy;
 pub mod deposit_collateral;
+pub mod execute_withdrawal;
+pub mod get_capital_release_schedule;
+pub mod get_deposit_capacity;
+pub mod get_underwriter_pnl;
 pub mod initialize;
-pub mod withdraw_collateral;
+pub mod open_settlement_round;
+pub mod record_price_observation;
+pub mod redeem_claims;
+pub mod request_withdrawal;
+pub mod stake_lp;
+pub mod switch_tranche;
+pub mod top_up_reserve;
+pub mod unlock_position;
+pub mod unstake_lp;
+pub mod update_asset_exposure_limits;
+pub mod update_pool_config;
+pub mod update_tranche_config;
 
+pub use add_tranche::*;
+pub use cancel_policy::*;
+pub use cancel_withdrawal::*;
 pub use check_and_payout::*;
+pub use claim_premiums::*;
+pub use claim_protocol_fees::*;
 pub use create_policy::*;
 pub use deposit_collateral::*;
+pub use execute_withdrawal::*;
+pub use get_capital_release_schedule::*;
+pub use get_deposit_capacity::*;
+pub use get_underwriter_pnl::*;
 pub use initialize::*;
-pub use withdraw_collateral::*;
+pub use open_settlement_round::*;
+pub use record_price_observation::*;
+pub use redeem_claims::*;
+pub use request_withdrawal::*;
+pub use stake_lp::*;
+pub use switch_tranche::*;
+pub use top_up_reserve::*;
+pub use unlock_position::*;
+pub use unstake_lp::*;
+pub use update_asset_exposure_limits::*;
+pub use update_pool_config::*;
+pub use update_tranche_config::*;
@@ -0,0 +1,189 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, AssetExposure, DepegEvent, InsurancePool, SettlementRound, TrancheSet,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+/// Fixes the share of its payout every unsettled policy on an insured stablecoin expiring
+/// on `expiry_day` receives, and sets that collateral aside. The depeg is the one recorded
+/// for the day once all of those policies expired, so a passing dip in the price can't
+/// open a round. Anyone can open it, like `check_and_payout`.
+#[derive(Accounts)]
+#[instruction(expiry_day: i64)]
+pub struct OpenSettlementRound<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::ASSET_EXPOSURE_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref()
+        ],
+        bump = asset_exposure.bump,
+    )]
+    pub asset_exposure: Box<Account<'info, AssetExposure>>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::DEPEG_EVENT_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref(),
+            expiry_day.to_le_bytes().as_ref()
+        ],
+        bump = depeg_event.bump,
+        constraint = depeg_event.depegged @ StableGuardError::NoDepegDetected,
+    )]
+    pub depeg_event: Account<'info, DepegEvent>,
+
+    #[account(
+        init,
+        payer = keeper,
+        space = 8 + SettlementRound::INIT_SPACE,
+        seeds = [
+            constants::SETTLEMENT_ROUND_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref(),
+            expiry_day.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub settlement_round: Account<'info, SettlementRound>,
+
+    #[account(
+        mut,
+        seeds = [constants::RESERVE_VAULT_SEED, collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, owner of the tranche vaults.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub insured_stablecoin_mint: Account<'info, Mint>,
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> OpenSettlementRound<'info> {
+    /// Every tranche of the pool is passed in `remaining_accounts`, each followed by its
+    /// collateral vault, most junior first.
+    pub fn open_settlement_round(
+        &mut self,
+        bumps: &OpenSettlementRoundBumps,
+        expiry_day: i64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        // Only policies expiring on the day are in the round, and all of them have expired
+        // since the day's price was recorded.
+        let total_liability = self.asset_exposure.take_expired_liability(expiry_day)?;
+        require!(total_liability > 0, StableGuardError::NoLiabilityToSettle);
+
+        let mut tranches = TrancheSet::load_with_vaults(&self.insurance_pool, remaining_accounts)?;
+        let available_collateral = tranches
+            .total_collateral()?
+            .checked_add(self.insurance_pool.reserve_balance)
+            .ok_or(StableGuardError::CalculationError)?;
+        // The ratio is the share of what this round owes that the pool can cover.
+        let recovery_ratio_bps =
+            SettlementRound::recovery_ratio_bps(total_liability, available_collateral)?;
+
+        self.settlement_round.set_inner(SettlementRound {
+            insurance_pool: self.insurance_pool.key(),
+            insured_mint: self.insured_stablecoin_mint.key(),
+            expiry_day,
+            opened_timestamp: Clock::get()?.unix_timestamp,
+            depeg_price: self.depeg_event.observed_price,
+            total_liability,
+            available_collateral,
+            recovery_ratio_bps,
+            set_aside: 0,
+            settled_liability: 0,
+            paid_out: 0,
+            bump: bumps.settlement_round,
+        });
+        let set_aside = self.settlement_round.recovery_amount(total_liability)?;
+        self.set_aside(set_aside, &mut tranches, bumps)?;
+        self.settlement_round.set_aside = set_aside;
+        self.depeg_event.settlement_round_opened = true;
+        tranches.exit()?;
+
+        // The round owes the policies in it from here on, not the pool.
+        let day_start = expiry_day
+            .checked_mul(constants::SECONDS_PER_DAY)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.insurance_pool
+            .liability_schedule
+            .remove(day_start, total_liability)?;
+
+        msg!(
+            "Settlement round opened for day {} owing {}. Recovery ratio: {} bps, {} set aside",
+            expiry_day,
+            total_liability,
+            recovery_ratio_bps,
+            set_aside
+        );
+        Ok(())
+    }
+
+    /// Moves `amount` into the pool's settlement fund in the order a payout draws on the
+    /// pool: the reserve first, then the tranches, most junior first.
+    fn set_aside(
+        &mut self,
+        amount: u64,
+        tranches: &mut TrancheSet<'info>,
+        bumps: &OpenSettlementRoundBumps,
+    ) -> Result<()> {
+        let from_reserve = amount.min(self.insurance_pool.reserve_balance);
+        let mut remaining = amount - from_reserve;
+        self.insurance_pool.reserve_balance -= from_reserve;
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        for index in 0..tranches.len() {
+            let loss = remaining.min(tranches.get(index).collateral);
+            if loss == 0 {
+                continue;
+            }
+            transfer_checked(
+                CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    TransferChecked {
+                        from: tranches.vault(index).clone(),
+                        mint: self.collateral_mint.to_account_info(),
+                        to: self.reserve_vault.to_account_info(),
+                        authority: self.pool_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                loss,
+                self.collateral_mint.decimals,
+            )?;
+            tranches.get_mut(index).debit(loss)?;
+            remaining -= loss;
+            msg!("Tranche #{} set aside {} for the round.", index, loss);
+        }
+
+        self.insurance_pool.settlement_fund = self
+            .insurance_pool
+            .settlement_fund
+            .checked_add(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, oracle, DepegEvent, InsurancePool};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+/// Records the price every policy on an insured stablecoin expiring on `expiry_day` settles
+/// at. Anyone can record it once the day is over, from a price update published within
+/// `MAX_OBSERVATION_DELAY_SECONDS` of the day's end; the account can only be written once.
+#[derive(Accounts)]
+#[instruction(expiry_day: i64)]
+pub struct RecordPriceObservation<'info> {
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        init,
+        payer = keeper,
+        space = 8 + DepegEvent::INIT_SPACE,
+        seeds = [
+            constants::DEPEG_EVENT_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref(),
+            expiry_day.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub depeg_event: Account<'info, DepegEvent>,
+
+    pub insured_stablecoin_mint: Account<'info, Mint>,
+    pub pyth_price_update: Account<'info, PriceUpdateV2>,
+    pub collateral_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RecordPriceObservation<'info> {
+    pub fn record_price_observation(
+        &mut self,
+        bumps: &RecordPriceObservationBumps,
+        expiry_day: i64,
+    ) -> Result<()> {
+        // Policies expiring later in the day must not settle at a price from before their
+        // expiry.
+        let now = Clock::get()?.unix_timestamp;
+        let window_end =
+            DepegEvent::window_end(expiry_day).ok_or(StableGuardError::CalculationError)?;
+        require!(
+            now >= window_end,
+            StableGuardError::ObservationWindowNotClosed
+        );
+
+        let latest_observation =
+            DepegEvent::latest_observation(expiry_day).ok_or(StableGuardError::CalculationError)?;
+        let (observed_price, observed_timestamp) = oracle::scaled_oracle_price_published_between(
+            &self.pyth_price_update,
+            self.insured_stablecoin_mint.key(),
+            window_end,
+            latest_observation,
+            constants::MAX_OBSERVATION_CONFIDENCE_BPS,
+        )?;
+        // Fixed now, so a later change of the threshold can't split the window either.
+        let depegged = observed_price < self.insurance_pool.depeg_threshold as i64;
+
+        self.depeg_event.set_inner(DepegEvent {
+            insurance_pool: self.insurance_pool.key(),
+            insured_mint: self.insured_stablecoin_mint.key(),
+            expiry_day,
+            observed_price,
+            observed_timestamp,
+            depegged,
+            settlement_round_opened: false,
+            bump: bumps.depeg_event,
+        });
+
+        msg!(
+            "Recorded price {} for policies expiring on day {}. Depeg: {}",
+            observed_price,
+            expiry_day,
+            depegged
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{burn, transfer_checked, Burn, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+pub struct RedeemClaims<'info> {
+    pub holder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [constants::CLAIM_MINT_SEED, insurance_pool.key().as_ref()],
+        bump
+    )]
+    pub claim_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = claim_mint,
+        token::authority = holder
+    )]
+    pub holder_claim_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [constants::RESERVE_VAULT_SEED, collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = holder
+    )]
+    pub holder_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the redemption.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RedeemClaims<'info> {
+    /// Redeems up to `amount` claim tokens one for one against the claims fund. Claims are
+    /// redeemed as they are funded, so only part of `amount` may be redeemed.
+    pub fn redeem_claims(&mut self, bumps: &RedeemClaimsBumps, amount: u64) -> Result<()> {
+        let redeem_amount = amount
+            .min(self.holder_claim_account.amount)
+            .min(self.insurance_pool.claims_fund);
+        require!(redeem_amount > 0, StableGuardError::NoClaimsToRedeem);
+
+        burn(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                Burn {
+                    mint: self.claim_mint.to_account_info(),
+                    from: self.holder_claim_account.to_account_info(),
+                    authority: self.holder.to_account_info(),
+                },
+            ),
+            redeem_amount,
+        )?;
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        let cpi_accounts = TransferChecked {
+            from: self.reserve_vault.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.holder_token_account.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx, redeem_amount, self.collateral_mint.decimals)?;
+
+        self.insurance_pool.redeem_claims(redeem_amount)?;
+
+        msg!(
+            "Redeemed {} claim tokens. {} claims outstanding",
+            redeem_amount,
+            self.insurance_pool.outstanding_claims
+        );
+        Ok(())
+    }
+}
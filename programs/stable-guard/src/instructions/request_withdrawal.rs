@@ -0,0 +1,117 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheState, WithdrawalTicket};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+#[instruction(lp_amount: u64, tranche_index: u8)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub underwriter: Signer<'info>,
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        init,
+        payer = underwriter,
+        space = 8 + WithdrawalTicket::INIT_SPACE,
+        seeds = [
+            constants::WITHDRAWAL_TICKET_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[tranche_index]
+        ],
+        bump
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    #[account(
+        init,
+        payer = underwriter,
+        seeds = [constants::WITHDRAWAL_ESCROW_SEED, withdrawal_ticket.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = pool_authority
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = underwriter
+    )]
+    pub underwriter_lp_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [constants::TRANCHE_SEED, insurance_pool.key().as_ref(), &[tranche_index]],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(address = tranche.lp_mint @ StableGuardError::InvalidLpMint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// CHECK: The program's master authority PDA, owner of the LP escrow.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RequestWithdrawal<'info> {
+    pub fn request_withdrawal(
+        &mut self,
+        bumps: &RequestWithdrawalBumps,
+        lp_amount: u64,
+        tranche_index: u8,
+    ) -> Result<()> {
+        require!(lp_amount > 0, StableGuardError::WithdrawalAmountZero);
+        require!(
+            self.underwriter_lp_account.amount >= lp_amount,
+            StableGuardError::InsufficientLpTokensToBurn
+        );
+
+        // Lock the LP tokens up front. They stay in the tranche's supply, so they keep
+        // sharing in losses, and in premiums that grow the collateral, until the ticket is
+        // executed. Staked LP tokens have to be unstaked before they can be requested.
+        let cpi_accounts = TransferChecked {
+            from: self.underwriter_lp_account.to_account_info(),
+            mint: self.lp_mint.to_account_info(),
+            to: self.withdrawal_escrow.to_account_info(),
+            authority: self.underwriter.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer_checked(cpi_ctx, lp_amount, self.lp_mint.decimals)?;
+
+        let requested_timestamp = Clock::get()?.unix_timestamp;
+        let unlock_timestamp = requested_timestamp
+            .checked_add(self.insurance_pool.withdrawal_cooldown_seconds)
+            .ok_or(StableGuardError::CalculationError)?;
+
+        self.withdrawal_ticket.set_inner(WithdrawalTicket {
+            owner: self.underwriter.key(),
+            insurance_pool: self.insurance_pool.key(),
+            tranche_index,
+            lp_amount,
+            requested_timestamp,
+            unlock_timestamp,
+            bump: bumps.withdrawal_ticket,
+        });
+
+        msg!(
+            "Withdrawal of {} tranche #{} LP tokens requested. Executable from {}",
+            lp_amount,
+            tranche_index,
+            unlock_timestamp
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheState, UnderwriterPosition};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+/// Moves LP tokens from the owner's wallet into the tranche's staked vault, where they earn
+/// claimable premiums for the owner's position once they have warmed up. LP tokens that
+/// arrived by transfer are staked at a zero cost basis.
+#[derive(Accounts)]
+#[instruction(lp_amount: u64, tranche_index: u8)]
+pub struct StakeLp<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + UnderwriterPosition::INIT_SPACE,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            owner.key().as_ref(),
+            &[tranche_index]
+        ],
+        bump
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        seeds = [constants::TRANCHE_SEED, insurance_pool.key().as_ref(), &[tranche_index]],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = tranche.staked_lp_vault
+    )]
+    pub staked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = owner
+    )]
+    pub owner_lp_account: Account<'info, TokenAccount>,
+
+    #[account(address = tranche.lp_mint @ StableGuardError::InvalidLpMint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> StakeLp<'info> {
+    pub fn stake_lp(
+        &mut self,
+        bumps: &StakeLpBumps,
+        lp_amount: u64,
+        tranche_index: u8,
+    ) -> Result<()> {
+        require!(lp_amount > 0, StableGuardError::WithdrawalAmountZero);
+        let now = Clock::get()?.unix_timestamp;
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+
+        transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.owner_lp_account.to_account_info(),
+                    mint: self.lp_mint.to_account_info(),
+                    to: self.staked_lp_vault.to_account_info(),
+                    authority: self.owner.to_account_info(),
+                },
+            ),
+            lp_amount,
+            self.lp_mint.decimals,
+        )?;
+
+        if self.underwriter_position.owner == Pubkey::default() {
+            self.underwriter_position
+                .set_inner(UnderwriterPosition::new(
+                    self.owner.key(),
+                    self.insurance_pool.key(),
+                    tranche_index,
+                    bumps.underwriter_position,
+                ));
+        }
+        let forfeited = self.underwriter_position.stake(
+            lp_amount,
+            self.tranche.premium_per_lp_share,
+            now,
+            self.insurance_pool.lp_warmup_seconds,
+        )?;
+        self.tranche.forfeit_premiums(forfeited)?;
+        self.tranche.record_lp_staked(lp_amount)?;
+
+        msg!("Staked {} tranche #{} LP tokens", lp_amount, tranche_index);
+        Ok(())
+    }
+}
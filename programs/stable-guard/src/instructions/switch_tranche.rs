@@ -0,0 +1,338 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, oracle, InsurancePool, TrancheSet, TrancheState, UnderwriterPosition,
+    WithdrawalTicket,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{
+    burn, close_account, mint_to, transfer_checked, Burn, CloseAccount, Mint, MintTo, Token,
+    TokenAccount, TransferChecked,
+};
+use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
+
+/// Executes a cooled-down withdrawal ticket into another tranche instead of paying it out:
+/// the escrowed LP tokens are burned, their collateral moves between the tranche vaults
+/// and LP tokens of the target tranche are minted at its current share price, staked for
+/// the underwriter.
+#[derive(Accounts)]
+#[instruction(target_tranche_index: u8)]
+pub struct SwitchTranche<'info> {
+    #[account(mut)]
+    pub underwriter: Signer<'info>,
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        close = underwriter,
+        seeds = [
+            constants::WITHDRAWAL_TICKET_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = withdrawal_ticket.bump,
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    #[account(
+        mut,
+        seeds = [constants::WITHDRAWAL_ESCROW_SEED, withdrawal_ticket.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_escrow: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump = source_tranche.bump,
+    )]
+    pub source_tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = source_tranche.collateral_vault
+    )]
+    pub source_collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = source_tranche.lp_mint
+    )]
+    pub source_lp_mint: Account<'info, Mint>,
+
+    /// Opened empty if the underwriter is switching LP tokens they were sent rather than
+    /// minted.
+    #[account(
+        init_if_needed,
+        payer = underwriter,
+        space = 8 + UnderwriterPosition::INIT_SPACE,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[withdrawal_ticket.tranche_index]
+        ],
+        bump
+    )]
+    pub source_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[target_tranche_index]
+        ],
+        bump = target_tranche.bump,
+    )]
+    pub target_tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = target_tranche.collateral_vault
+    )]
+    pub target_collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = target_tranche.lp_mint
+    )]
+    pub target_lp_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = underwriter,
+        space = 8 + UnderwriterPosition::INIT_SPACE,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            underwriter.key().as_ref(),
+            &[target_tranche_index]
+        ],
+        bump
+    )]
+    pub target_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        address = target_tranche.staked_lp_vault
+    )]
+    pub target_staked_lp_vault: Account<'info, TokenAccount>,
+
+    /// CHECK: The program's master authority PDA, required to sign for the burn, the
+    /// collateral transfer and the mint.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    /// Price update for the collateral mint, to value the liability its collateral backs.
+    pub collateral_price_update: Account<'info, PriceUpdateV2>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SwitchTranche<'info> {
+    /// Takes the pool's tranches as `TrancheSet::load` does, for the liability and
+    /// attachment point checks.
+    pub fn switch_tranche(
+        &mut self,
+        bumps: &SwitchTrancheBumps,
+        target_tranche_index: u8,
+        min_lp_out: u64,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let source_index = self.withdrawal_ticket.tranche_index as usize;
+        let target_index = target_tranche_index as usize;
+        require!(
+            source_index != target_index,
+            StableGuardError::SameTrancheSwitch
+        );
+        let lp_amount_to_burn = self.withdrawal_ticket.lp_amount;
+        let now = Clock::get()?.unix_timestamp;
+        msg!(
+            "Switching {} LP tokens from tranche #{} to tranche #{}...",
+            lp_amount_to_burn,
+            source_index,
+            target_index
+        );
+
+        // Settle coupons and premium releases on the old balances before they change.
+        let coupon_applies = self.insurance_pool.coupon_applies();
+        let distribution_mode = self.insurance_pool.premium_distribution_mode;
+        self.source_tranche.accrue_coupon(now, coupon_applies)?;
+        self.source_tranche
+            .release_premiums(now, distribution_mode)?;
+        self.target_tranche.accrue_coupon(now, coupon_applies)?;
+        self.target_tranche
+            .release_premiums(now, distribution_mode)?;
+        let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+        tranches.sync(&self.source_tranche);
+        tranches.sync(&self.target_tranche);
+        if self.source_position.owner == Pubkey::default() {
+            self.source_position.set_inner(UnderwriterPosition::new(
+                self.underwriter.key(),
+                self.insurance_pool.key(),
+                self.withdrawal_ticket.tranche_index,
+                bumps.source_position,
+            ));
+        }
+
+        // --- 1. Cooldown Check ---
+        // Switching gives up the source tranche's risk the same way a withdrawal does.
+        require!(
+            now >= self.withdrawal_ticket.unlock_timestamp,
+            StableGuardError::WithdrawalCooldownActive
+        );
+        require!(
+            self.source_tranche.lp_supply > 0,
+            StableGuardError::NolpTokensToBurn
+        );
+
+        // --- 2. Price Both Legs ---
+        let collateral_to_move = self
+            .source_tranche
+            .collateral_for_lp_tokens(lp_amount_to_burn)?;
+
+        require!(
+            collateral_to_move > 0,
+            StableGuardError::WithdrawalResultsInZeroUsdc
+        );
+        require!(
+            collateral_to_move <= self.target_tranche.deposit_room(),
+            StableGuardError::DepositCapExceeded
+        );
+
+        let lp_tokens = self
+            .target_tranche
+            .lp_tokens_for_deposit(collateral_to_move)?;
+        require!(lp_tokens > 0, StableGuardError::DepositTooSmallToMintLp);
+        require!(lp_tokens >= min_lp_out, StableGuardError::SlippageExceeded);
+
+        // --- 3. Solvency Check ---
+        // The source tranche keeps backing the liability it carries, exactly as if the
+        // collateral were withdrawn.
+        let remaining_source_collateral = self
+            .source_tranche
+            .collateral
+            .checked_sub(collateral_to_move)
+            .ok_or(StableGuardError::CalculationError)?;
+        let total_liability = oracle::exit_liability_in_collateral(
+            &self.insurance_pool,
+            &self.collateral_price_update,
+            self.collateral_mint.key(),
+        )?;
+        require!(
+            remaining_source_collateral >= tranches.liability(source_index, total_liability)?,
+            StableGuardError::WithdrawalBlockedByUtilization
+        );
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+
+        // --- 4. Burn Escrowed LP Tokens ---
+        burn(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                Burn {
+                    mint: self.source_lp_mint.to_account_info(),
+                    from: self.withdrawal_escrow.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_amount_to_burn,
+        )?;
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            CloseAccount {
+                account: self.withdrawal_escrow.to_account_info(),
+                destination: self.underwriter.to_account_info(),
+                authority: self.pool_authority.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+
+        // --- 5. Move Collateral Between Vaults ---
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.source_collateral_vault.to_account_info(),
+                    to: self.target_collateral_vault.to_account_info(),
+                    mint: self.collateral_mint.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            collateral_to_move,
+            self.collateral_mint.decimals,
+        )?;
+
+        // --- 6. Mint Target LP Tokens ---
+        mint_to(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                MintTo {
+                    mint: self.target_lp_mint.to_account_info(),
+                    to: self.target_staked_lp_vault.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_tokens,
+        )?;
+
+        // --- 7. Update Tranche State ---
+        self.source_tranche.record_lp_burned(lp_amount_to_burn)?;
+        self.source_tranche.debit(collateral_to_move)?;
+        self.target_tranche.record_lp_minted(lp_tokens)?;
+        self.target_tranche.record_lp_staked(lp_tokens)?;
+        self.target_tranche.credit(collateral_to_move)?;
+
+        // Capital moving between layers can thin the cushion under any tranche.
+        tranches.sync(&self.source_tranche);
+        tranches.sync(&self.target_tranche);
+        tranches.require_subordinated(0..tranches.len())?;
+
+        // --- 8. Update Positions ---
+        self.source_position
+            .record_withdrawal(lp_amount_to_burn, collateral_to_move)?;
+
+        if self.target_position.owner == Pubkey::default() {
+            self.target_position.set_inner(UnderwriterPosition::new(
+                self.underwriter.key(),
+                self.insurance_pool.key(),
+                target_tranche_index,
+                bumps.target_position,
+            ));
+        }
+        let forfeited = self.target_position.record_deposit(
+            collateral_to_move,
+            lp_tokens,
+            false,
+            self.target_tranche.premium_per_lp_share,
+            now,
+            self.insurance_pool.lp_warmup_seconds,
+        )?;
+        self.target_tranche.forfeit_premiums(forfeited)?;
+
+        msg!(
+            "Moved {} of collateral into {} tranche #{} LP tokens.",
+            collateral_to_move,
+            lp_tokens,
+            target_index
+        );
+        Ok(())
+    }
+}
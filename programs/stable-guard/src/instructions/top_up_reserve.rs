@@ -0,0 +1,60 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+/// Adds external funds to the pool's reserve, which pays claims before any tranche.
+/// Outstanding claim tokens are funded from it first.
+#[derive(Accounts)]
+pub struct TopUpReserve<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = authority @ StableGuardError::UnauthorizedPoolAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [constants::RESERVE_VAULT_SEED, collateral_mint.key().as_ref()],
+        bump
+    )]
+    pub reserve_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = authority
+    )]
+    pub funding_token_account: Account<'info, TokenAccount>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> TopUpReserve<'info> {
+    pub fn top_up_reserve(&mut self, amount: u64) -> Result<()> {
+        require!(amount > 0, StableGuardError::ReserveTopUpZero);
+
+        let cpi_accounts = TransferChecked {
+            from: self.funding_token_account.to_account_info(),
+            mint: self.collateral_mint.to_account_info(),
+            to: self.reserve_vault.to_account_info(),
+            authority: self.authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer_checked(cpi_ctx, amount, self.collateral_mint.decimals)?;
+
+        self.insurance_pool.add_to_reserve(amount)?;
+
+        msg!(
+            "Reserve topped up by {}. Reserve balance: {}",
+            amount,
+            self.insurance_pool.reserve_balance
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, InsurancePool, LockedPosition, TrancheState, UnderwriterPosition,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+#[derive(Accounts)]
+pub struct UnlockPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        close = owner,
+        seeds = [
+            constants::LOCKED_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            owner.key().as_ref(),
+            locked_position.position_id.to_le_bytes().as_ref()
+        ],
+        bump = locked_position.bump,
+    )]
+    pub locked_position: Account<'info, LockedPosition>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[locked_position.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = tranche.locked_lp_vault
+    )]
+    pub locked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            owner.key().as_ref(),
+            &[locked_position.tranche_index]
+        ],
+        bump = underwriter_position.bump,
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        address = tranche.staked_lp_vault
+    )]
+    pub staked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = tranche.collateral_vault
+    )]
+    pub collateral_vault: Account<'info, TokenAccount>,
+
+    #[account(address = tranche.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// CHECK: The program's master authority PDA, owner of the locked LP vault.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> UnlockPosition<'info> {
+    pub fn unlock_position(&mut self, bumps: &UnlockPositionBumps) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        // Release premium earned up to now so the boost reward includes it.
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+
+        let position = &self.locked_position;
+        require!(
+            now >= position.unlock_timestamp,
+            StableGuardError::PositionStillLocked
+        );
+
+        // Boost premium earned by the position since it was opened.
+        let boost_reward = (position.boost_weight as u128)
+            .checked_mul(
+                self.tranche
+                    .boost_reward_per_weight
+                    .checked_sub(position.boost_reward_per_weight_snapshot)
+                    .ok_or(StableGuardError::CalculationError)?,
+            )
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(constants::REWARD_PRECISION)
+            .ok_or(StableGuardError::CalculationError)? as u64;
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+
+        // --- 1. Move the LP tokens into the staked vault ---
+        // They keep earning claimable premiums; the owner can unstake them from there.
+        let cpi_accounts_lp = TransferChecked {
+            from: self.locked_lp_vault.to_account_info(),
+            mint: self.lp_mint.to_account_info(),
+            to: self.staked_lp_vault.to_account_info(),
+            authority: self.pool_authority.to_account_info(),
+        };
+        let cpi_ctx_lp = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts_lp,
+            signer_seeds,
+        );
+        transfer_checked(cpi_ctx_lp, position.lp_amount, self.lp_mint.decimals)?;
+
+        // --- 2. Pay out the boost premium ---
+        if boost_reward > 0 {
+            let cpi_accounts_reward = TransferChecked {
+                from: self.collateral_vault.to_account_info(),
+                mint: self.collateral_mint.to_account_info(),
+                to: self.owner_token_account.to_account_info(),
+                authority: self.pool_authority.to_account_info(),
+            };
+            let cpi_ctx_reward = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts_reward,
+                signer_seeds,
+            );
+            transfer_checked(cpi_ctx_reward, boost_reward, self.collateral_mint.decimals)?;
+        }
+
+        // --- 3. Update Tranche State ---
+        let lp_amount = position.lp_amount;
+        let boost_weight = position.boost_weight;
+        self.tranche
+            .remove_locked_position(lp_amount, boost_weight, boost_reward)?;
+        self.underwriter_position.record_unlock(lp_amount)?;
+
+        msg!(
+            "Position #{} unlocked: {} LP tokens staked, {} boost premium paid",
+            self.locked_position.position_id,
+            lp_amount,
+            boost_reward
+        );
+        Ok(())
+    }
+}
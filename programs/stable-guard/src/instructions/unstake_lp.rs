@@ -0,0 +1,105 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheState, UnderwriterPosition};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked};
+
+/// Moves staked LP tokens back to the owner's wallet, for example to request a withdrawal.
+/// Premiums earned so far stay claimable; the LP tokens stop earning claimable premiums.
+/// LP tokens still warming up can't be unstaked.
+#[derive(Accounts)]
+pub struct UnstakeLp<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::UNDERWRITER_POSITION_SEED,
+            insurance_pool.key().as_ref(),
+            owner.key().as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = underwriter_position.bump,
+    )]
+    pub underwriter_position: Account<'info, UnderwriterPosition>,
+
+    #[account(
+        mut,
+        seeds = [
+            constants::TRANCHE_SEED,
+            insurance_pool.key().as_ref(),
+            &[underwriter_position.tranche_index]
+        ],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    #[account(
+        mut,
+        address = tranche.staked_lp_vault
+    )]
+    pub staked_lp_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = owner
+    )]
+    pub owner_lp_account: Account<'info, TokenAccount>,
+
+    #[account(address = tranche.lp_mint @ StableGuardError::InvalidLpMint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// CHECK: The program's master authority PDA, owner of the staked LP vault.
+    #[account(
+        seeds = [constants::AUTHORITY_SEED],
+        bump
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> UnstakeLp<'info> {
+    pub fn unstake_lp(&mut self, bumps: &UnstakeLpBumps, lp_amount: u64) -> Result<()> {
+        require!(lp_amount > 0, StableGuardError::WithdrawalAmountZero);
+        let now = Clock::get()?.unix_timestamp;
+        self.tranche
+            .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+
+        let forfeited =
+            self.underwriter_position
+                .unstake(lp_amount, self.tranche.premium_per_lp_share, now)?;
+        self.tranche.forfeit_premiums(forfeited)?;
+        self.tranche.record_lp_unstaked(lp_amount)?;
+
+        let authority_seeds = &[constants::AUTHORITY_SEED, &[bumps.pool_authority]];
+        let signer_seeds = &[&authority_seeds[..]];
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.staked_lp_vault.to_account_info(),
+                    mint: self.lp_mint.to_account_info(),
+                    to: self.owner_lp_account.to_account_info(),
+                    authority: self.pool_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            lp_amount,
+            self.lp_mint.decimals,
+        )?;
+
+        msg!(
+            "Unstaked {} tranche #{} LP tokens",
+            lp_amount,
+            self.tranche.index
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, AssetExposure, InsurancePool, LiabilitySchedule};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Liability limits for one insured stablecoin.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AssetExposureLimits {
+    pub max_liability: u64,
+    pub max_liability_bps: u64,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAssetExposureLimits<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = authority @ StableGuardError::UnauthorizedPoolAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    pub insured_stablecoin_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + AssetExposure::INIT_SPACE,
+        seeds = [
+            constants::ASSET_EXPOSURE_SEED,
+            insurance_pool.key().as_ref(),
+            insured_stablecoin_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub asset_exposure: Box<Account<'info, AssetExposure>>,
+
+    pub collateral_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> UpdateAssetExposureLimits<'info> {
+    /// Limits only gate new policies, so they may be set below the liability already held.
+    pub fn update_asset_exposure_limits(
+        &mut self,
+        bumps: &UpdateAssetExposureLimitsBumps,
+        limits: AssetExposureLimits,
+    ) -> Result<()> {
+        require!(
+            limits.max_liability_bps <= 10000,
+            StableGuardError::InvalidPoolConfig
+        );
+
+        if self.asset_exposure.insurance_pool == Pubkey::default() {
+            self.asset_exposure.set_inner(AssetExposure {
+                insurance_pool: self.insurance_pool.key(),
+                insured_mint: self.insured_stablecoin_mint.key(),
+                active_policies: 0,
+                active_liability: 0,
+                bump: bumps.asset_exposure,
+                max_liability: limits.max_liability,
+                max_liability_bps: limits.max_liability_bps,
+                liability_schedule: LiabilitySchedule::default(),
+            });
+        } else {
+            self.asset_exposure.max_liability = limits.max_liability;
+            self.asset_exposure.max_liability_bps = limits.max_liability_bps;
+        }
+
+        msg!(
+            "Liability limits for {} set to {} and {} bps of pool collateral",
+            self.insured_stablecoin_mint.key(),
+            limits.max_liability,
+            limits.max_liability_bps
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+pub use crate::constants;
+use crate::{
+    error::StableGuardError, InsurancePool, PremiumDistributionMode, PremiumSplitMode, TrancheSet,
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Pool parameters the authority can tune after initialization. Fields left as `None`
+/// keep their current value.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PoolConfigArgs {
+    pub premium_split_mode: Option<PremiumSplitMode>,
+    pub min_junior_premium_share_bps: Option<u64>,
+    pub max_junior_premium_share_bps: Option<u64>,
+    pub withdrawal_cooldown_seconds: Option<i64>,
+    pub lock_boost_bps_per_year: Option<u64>,
+    pub max_lock_duration_seconds: Option<i64>,
+    pub unlocked_capital_weight_bps: Option<u64>,
+    pub premium_distribution_mode: Option<PremiumDistributionMode>,
+    pub lp_warmup_seconds: Option<i64>,
+    pub max_pool_collateral: Option<u64>,
+    pub protocol_fee_bps: Option<u64>,
+    pub treasury_authority: Option<Pubkey>,
+    pub reserve_premium_bps: Option<u64>,
+    pub max_buyer_insured_amount: Option<u64>,
+    pub max_buyer_active_policies: Option<u64>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = authority @ StableGuardError::UnauthorizedPoolAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    pub collateral_mint: Account<'info, Mint>,
+}
+
+impl<'info> UpdatePoolConfig<'info> {
+    /// Switching `premium_split_mode` or `premium_distribution_mode` needs every tranche
+    /// passed in `remaining_accounts`, most junior first, so their coupons and premium
+    /// releases can be settled under the old mode.
+    pub fn update_pool_config(
+        &mut self,
+        args: PoolConfigArgs,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        if let Some(premium_split_mode) = args.premium_split_mode {
+            // Settle the coupon under the old mode so no coupon accrues across the switch.
+            let now = Clock::get()?.unix_timestamp;
+            let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+            for index in 0..tranches.len() {
+                tranches
+                    .get_mut(index)
+                    .accrue_coupon(now, self.insurance_pool.coupon_applies())?;
+            }
+            tranches.exit()?;
+            self.insurance_pool.premium_split_mode = premium_split_mode;
+            msg!("Premium split mode set to {:?}", premium_split_mode);
+        }
+
+        if let Some(min_share_bps) = args.min_junior_premium_share_bps {
+            self.insurance_pool.min_junior_premium_share_bps = min_share_bps;
+        }
+        if let Some(max_share_bps) = args.max_junior_premium_share_bps {
+            self.insurance_pool.max_junior_premium_share_bps = max_share_bps;
+        }
+        require!(
+            self.insurance_pool.min_junior_premium_share_bps
+                <= self.insurance_pool.max_junior_premium_share_bps
+                && self.insurance_pool.max_junior_premium_share_bps <= 10000,
+            StableGuardError::InvalidPoolConfig
+        );
+
+        if let Some(cooldown_seconds) = args.withdrawal_cooldown_seconds {
+            require!(
+                (0..=constants::MAX_WITHDRAWAL_COOLDOWN_SECONDS).contains(&cooldown_seconds),
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.withdrawal_cooldown_seconds = cooldown_seconds;
+            msg!("Withdrawal cooldown set to {} seconds", cooldown_seconds);
+        }
+
+        // Boosts only apply to new locks; existing positions keep the weight they locked in.
+        if let Some(lock_boost_bps_per_year) = args.lock_boost_bps_per_year {
+            require!(
+                lock_boost_bps_per_year <= constants::MAX_LOCK_BOOST_BPS_PER_YEAR,
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.lock_boost_bps_per_year = lock_boost_bps_per_year;
+        }
+        if let Some(max_lock_duration_seconds) = args.max_lock_duration_seconds {
+            require!(
+                (0..=constants::MAX_LOCK_DURATION_SECONDS).contains(&max_lock_duration_seconds),
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.max_lock_duration_seconds = max_lock_duration_seconds;
+        }
+        if let Some(unlocked_capital_weight_bps) = args.unlocked_capital_weight_bps {
+            require!(
+                unlocked_capital_weight_bps <= 10000,
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.unlocked_capital_weight_bps = unlocked_capital_weight_bps;
+        }
+
+        // Premiums already set aside stay claimable after switching back to compounding.
+        if let Some(premium_distribution_mode) = args.premium_distribution_mode {
+            // Release what was earned up to now under the old mode before switching.
+            let now = Clock::get()?.unix_timestamp;
+            let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+            for index in 0..tranches.len() {
+                tranches
+                    .get_mut(index)
+                    .release_premiums(now, self.insurance_pool.premium_distribution_mode)?;
+            }
+            tranches.exit()?;
+            self.insurance_pool.premium_distribution_mode = premium_distribution_mode;
+            msg!(
+                "Premium distribution mode set to {:?}",
+                premium_distribution_mode
+            );
+        }
+
+        if let Some(lp_warmup_seconds) = args.lp_warmup_seconds {
+            require!(
+                (0..=constants::MAX_LP_WARMUP_SECONDS).contains(&lp_warmup_seconds),
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.lp_warmup_seconds = lp_warmup_seconds;
+            msg!("LP warm-up set to {} seconds", lp_warmup_seconds);
+        }
+        self.insurance_pool.validate_lp_warmup()?;
+
+        // Caps only gate new deposits, so they may be set below what the pool already holds.
+        if let Some(max_pool_collateral) = args.max_pool_collateral {
+            self.insurance_pool.max_pool_collateral = max_pool_collateral;
+            msg!("Pool collateral cap set to {}", max_pool_collateral);
+        }
+
+        // A new fee only applies to policies bought after the change.
+        if let Some(protocol_fee_bps) = args.protocol_fee_bps {
+            require!(
+                protocol_fee_bps <= constants::MAX_PROTOCOL_FEE_BPS,
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.protocol_fee_bps = protocol_fee_bps;
+            msg!("Protocol fee set to {} bps", protocol_fee_bps);
+        }
+        if let Some(treasury_authority) = args.treasury_authority {
+            self.insurance_pool.treasury_authority = treasury_authority;
+            msg!("Treasury authority set to {}", treasury_authority);
+        }
+        if let Some(reserve_premium_bps) = args.reserve_premium_bps {
+            require!(
+                reserve_premium_bps <= constants::MAX_RESERVE_PREMIUM_BPS,
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.reserve_premium_bps = reserve_premium_bps;
+            msg!(
+                "Reserve share of premiums set to {} bps",
+                reserve_premium_bps
+            );
+        }
+
+        // Buyer limits only gate new policies; existing cover runs to expiry.
+        if let Some(max_buyer_insured_amount) = args.max_buyer_insured_amount {
+            self.insurance_pool.max_buyer_insured_amount = max_buyer_insured_amount;
+            msg!("Per-buyer coverage cap set to {}", max_buyer_insured_amount);
+        }
+        if let Some(max_buyer_active_policies) = args.max_buyer_active_policies {
+            require!(
+                max_buyer_active_policies <= constants::MAX_BUYER_ACTIVE_POLICIES,
+                StableGuardError::InvalidPoolConfig
+            );
+            self.insurance_pool.max_buyer_active_policies = max_buyer_active_policies;
+            msg!(
+                "Per-buyer active policy cap set to {}",
+                max_buyer_active_policies
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+pub use crate::constants;
+use crate::{error::StableGuardError, InsurancePool, TrancheSet, TrancheState};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Tranche parameters the authority can tune after the tranche is added. Fields left as
+/// `None` keep their current value.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct TrancheConfigArgs {
+    pub premium_weight_bps: Option<u64>,
+    pub attachment_point_bps: Option<u64>,
+    pub target_apr_bps: Option<u64>,
+    pub max_collateral: Option<u64>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateTrancheConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [constants::INSURANCE_POOL_SEED, collateral_mint.key().as_ref()],
+        bump = insurance_pool.bump,
+        has_one = authority @ StableGuardError::UnauthorizedPoolAuthority,
+    )]
+    pub insurance_pool: Account<'info, InsurancePool>,
+
+    #[account(
+        mut,
+        seeds = [constants::TRANCHE_SEED, insurance_pool.key().as_ref(), &[tranche.index]],
+        bump = tranche.bump,
+    )]
+    pub tranche: Account<'info, TrancheState>,
+
+    pub collateral_mint: Account<'info, Mint>,
+}
+
+impl<'info> UpdateTrancheConfig<'info> {
+    /// Changing `attachment_point_bps` needs every tranche passed in `remaining_accounts`,
+    /// most junior first, to keep attachment points in order of seniority.
+    pub fn update_tranche_config(
+        &mut self,
+        args: TrancheConfigArgs,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        if let Some(premium_weight_bps) = args.premium_weight_bps {
+            self.tranche.premium_weight_bps = premium_weight_bps;
+        }
+        // Like deposit caps, a new attachment point only gates future deposits, withdrawals
+        // and policies.
+        if let Some(attachment_point_bps) = args.attachment_point_bps {
+            self.tranche.attachment_point_bps = attachment_point_bps;
+            let mut tranches = TrancheSet::load(&self.insurance_pool, remaining_accounts)?;
+            tranches.sync(&self.tranche);
+            tranches.require_attachment_order()?;
+        }
+        if let Some(target_apr_bps) = args.target_apr_bps {
+            // Charge the old rate up to now before switching to the new one.
+            self.tranche.accrue_coupon(
+                Clock::get()?.unix_timestamp,
+                self.insurance_pool.coupon_applies(),
+            )?;
+            self.tranche.target_apr_bps = target_apr_bps;
+        }
+        if let Some(max_collateral) = args.max_collateral {
+            self.tranche.max_collateral = max_collateral;
+        }
+        self.tranche.validate_config()?;
+
+        msg!("Tranche #{} config updated", self.tranche.index);
+        Ok(())
+    }
+}
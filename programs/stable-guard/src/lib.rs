@@ -2,6 +2,7 @@
 pub mod constants;
 pub mod error;
 pub mod instructions;
+pub mod oracle;
 pub mod state;
 
 use anchor_lang::prelude::*;
@@ -20,32 +21,197 @@ pub mod stable_guard {
         ctx.accounts.initialize(&ctx.bumps, depeg_threshold)?;
         Ok(())
     }
-    pub fn create_policy(
-        ctx: Context<CreatePolicy>,
+
+    pub fn add_tranche<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AddTranche<'info>>,
+        params: TrancheParams,
+    ) -> Result<()> {
+        ctx.accounts
+            .add_tranche(&ctx.bumps, params, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn update_pool_config<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdatePoolConfig<'info>>,
+        args: PoolConfigArgs,
+    ) -> Result<()> {
+        ctx.accounts
+            .update_pool_config(args, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn update_tranche_config<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateTrancheConfig<'info>>,
+        args: TrancheConfigArgs,
+    ) -> Result<()> {
+        ctx.accounts
+            .update_tranche_config(args, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn top_up_reserve(ctx: Context<TopUpReserve>, amount: u64) -> Result<()> {
+        ctx.accounts.top_up_reserve(amount)?;
+        Ok(())
+    }
+
+    pub fn update_asset_exposure_limits(
+        ctx: Context<UpdateAssetExposureLimits>,
+        limits: AssetExposureLimits,
+    ) -> Result<()> {
+        ctx.accounts
+            .update_asset_exposure_limits(&ctx.bumps, limits)?;
+        Ok(())
+    }
+
+    pub fn create_policy<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CreatePolicy<'info>>,
         insured_amount: u64,
         policy_duration_seconds: i64,
+    ) -> Result<()> {
+        ctx.accounts.create_policy(
+            &ctx.bumps,
+            insured_amount,
+            policy_duration_seconds,
+            ctx.remaining_accounts,
+        )?;
+        Ok(())
+    }
+
+    pub fn deposit_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositCollateral<'info>>,
+        deposit_amount: u64,
+        min_lp_out: u64,
+        tranche_index: u8,
+        lock_duration_seconds: Option<i64>,
+    ) -> Result<()> {
+        ctx.accounts.deposit_collateral(
+            &ctx.bumps,
+            deposit_amount,
+            min_lp_out,
+            tranche_index,
+            lock_duration_seconds,
+            ctx.remaining_accounts,
+        )?;
+        Ok(())
+    }
+
+    pub fn stake_lp(ctx: Context<StakeLp>, lp_amount: u64, tranche_index: u8) -> Result<()> {
+        ctx.accounts
+            .stake_lp(&ctx.bumps, lp_amount, tranche_index)?;
+        Ok(())
+    }
+
+    pub fn unstake_lp(ctx: Context<UnstakeLp>, lp_amount: u64) -> Result<()> {
+        ctx.accounts.unstake_lp(&ctx.bumps, lp_amount)?;
+        Ok(())
+    }
+
+    pub fn request_withdrawal(
+        ctx: Context<RequestWithdrawal>,
+        lp_amount: u64,
+        tranche_index: u8,
     ) -> Result<()> {
         ctx.accounts
-            .create_policy(&ctx.bumps, insured_amount, policy_duration_seconds)?;
+            .request_withdrawal(&ctx.bumps, lp_amount, tranche_index)?;
         Ok(())
     }
 
-    pub fn deposit_collateral(ctx: Context<DepositCollateral>, deposit_amount: u64) -> Result<()> {
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        ctx.accounts.cancel_withdrawal(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn execute_withdrawal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteWithdrawal<'info>>,
+        min_collateral_out: u64,
+    ) -> Result<()> {
         ctx.accounts
-            .deposit_collateral(&ctx.bumps, deposit_amount)?;
+            .execute_withdrawal(&ctx.bumps, min_collateral_out, ctx.remaining_accounts)?;
         Ok(())
     }
 
-    pub fn withdraw_collateral(
-        ctx: Context<WithdrawCollateral>,
-        lp_amount_to_burn: u64,
+    pub fn switch_tranche<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwitchTranche<'info>>,
+        target_tranche_index: u8,
+        min_lp_out: u64,
     ) -> Result<()> {
-        ctx.accounts.withdraw(&ctx.bumps, lp_amount_to_burn)?;
+        ctx.accounts.switch_tranche(
+            &ctx.bumps,
+            target_tranche_index,
+            min_lp_out,
+            ctx.remaining_accounts,
+        )?;
+        Ok(())
+    }
+
+    pub fn unlock_position(ctx: Context<UnlockPosition>) -> Result<()> {
+        ctx.accounts.unlock_position(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn claim_premiums(ctx: Context<ClaimPremiums>) -> Result<()> {
+        ctx.accounts.claim_premiums(&ctx.bumps)?;
         Ok(())
     }
 
-    pub fn check_and_payout(ctx: Context<CheckAndPayout>, policy_id: u64) -> Result<()> {
-        ctx.accounts.check_payout(&ctx.bumps, policy_id)?;
+    pub fn claim_protocol_fees(ctx: Context<ClaimProtocolFees>) -> Result<()> {
+        ctx.accounts.claim_protocol_fees(&ctx.bumps)?;
+        Ok(())
+    }
+
+    pub fn redeem_claims(ctx: Context<RedeemClaims>, amount: u64) -> Result<()> {
+        ctx.accounts.redeem_claims(&ctx.bumps, amount)?;
+        Ok(())
+    }
+
+    pub fn get_underwriter_pnl(ctx: Context<GetUnderwriterPnl>) -> Result<UnderwriterPnl> {
+        ctx.accounts.get_underwriter_pnl()
+    }
+
+    pub fn get_deposit_capacity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetDepositCapacity<'info>>,
+    ) -> Result<DepositCapacity> {
+        ctx.accounts.get_deposit_capacity(ctx.remaining_accounts)
+    }
+
+    pub fn get_capital_release_schedule(
+        ctx: Context<GetCapitalReleaseSchedule>,
+    ) -> Result<CapitalReleaseSchedule> {
+        ctx.accounts.get_capital_release_schedule()
+    }
+
+    pub fn cancel_policy<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelPolicy<'info>>,
+    ) -> Result<()> {
+        ctx.accounts
+            .cancel_policy(&ctx.bumps, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn open_settlement_round<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenSettlementRound<'info>>,
+        expiry_day: i64,
+    ) -> Result<()> {
+        ctx.accounts
+            .open_settlement_round(&ctx.bumps, expiry_day, ctx.remaining_accounts)?;
+        Ok(())
+    }
+
+    pub fn record_price_observation(
+        ctx: Context<RecordPriceObservation>,
+        expiry_day: i64,
+    ) -> Result<()> {
+        ctx.accounts
+            .record_price_observation(&ctx.bumps, expiry_day)?;
+        Ok(())
+    }
+
+    pub fn check_and_payout<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CheckAndPayout<'info>>,
+        policy_id: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .check_payout(&ctx.bumps, policy_id, ctx.remaining_accounts)?;
         Ok(())
     }
 }
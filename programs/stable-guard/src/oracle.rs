@@ -0,0 +1,249 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::{
+    get_feed_id_from_hex, FeedId, Price, PriceUpdateV2, VerificationLevel,
+};
+
+use crate::{constants, error::StableGuardError, InsurancePool};
+
+/// Reads the Pyth price of `mint` from `price_update`, scaled to 8 decimals so it can be
+/// compared with the pool's `depeg_threshold`. Fails on stale prices and on confidence
+/// intervals too wide to trust.
+pub fn scaled_oracle_price(price_update: &PriceUpdateV2, mint: Pubkey) -> Result<i64> {
+    let price_data = fetch_price(price_update, mint, constants::MAX_ORACLE_AGE_SECONDS)?;
+    scale_price(&price_data, constants::MAX_CONFIDENCE_BPS)
+}
+
+/// Like `scaled_oracle_price`, but reads a price published between `earliest` and `latest`
+/// rather than a recent one, so a price for a past moment can be recorded after the fact.
+/// The confidence interval may be as wide as `max_confidence_bps`. Returns the price and
+/// its publish time.
+pub fn scaled_oracle_price_published_between(
+    price_update: &PriceUpdateV2,
+    mint: Pubkey,
+    earliest: i64,
+    latest: i64,
+    max_confidence_bps: u64,
+) -> Result<(i64, i64)> {
+    let (relevant_feed_id_str, feed_id) = feed_id(mint)?;
+
+    require!(
+        price_update.verification_level.gte(VerificationLevel::Full),
+        StableGuardError::OraclePriceUnverified
+    );
+    let price_data = price_update.get_price_unchecked(&feed_id)?;
+    check_published_between(price_data.publish_time, earliest, latest)?;
+    msg!(
+        "Fetched price from Pyth feed {} published at {}: {}",
+        relevant_feed_id_str,
+        price_data.publish_time,
+        price_data.price
+    );
+
+    Ok((
+        scale_price(&price_data, max_confidence_bps)?,
+        price_data.publish_time,
+    ))
+}
+
+/// Scales `price_data` to 8 decimals and checks its confidence interval is no wider than
+/// `max_confidence_bps` of the price.
+fn scale_price(price_data: &Price, max_confidence_bps: u64) -> Result<i64> {
+    // Pyth prices have a dynamic exponent. We must scale the price to a common
+    // 8-decimal format to safely compare it with our `depeg_threshold`.
+    let scaled_pyth_price = scale_to_target_decimals(price_data.price, price_data.exponent)?;
+    msg!("Scaled oracle price (8 decimals): {}", scaled_pyth_price);
+
+    // We must check the oracle's confidence interval. A wide interval suggests market
+    // turmoil or potential oracle issues.
+    check_confidence(price_data.price, price_data.conf, max_confidence_bps)?;
+
+    Ok(scaled_pyth_price)
+}
+
+/// The pool's payout liability in collateral tokens, for checks on capital leaving the
+/// pool. LPs must be able to exit in the very markets where the oracle is least sure, so
+/// the price may be up to `MAX_EXIT_ORACLE_AGE_SECONDS` old and a wide confidence interval
+/// doesn't fail the exit: the collateral is valued at the low end of it instead.
+pub fn exit_liability_in_collateral(
+    pool: &InsurancePool,
+    price_update: &PriceUpdateV2,
+    collateral_mint: Pubkey,
+) -> Result<u64> {
+    let price_data = fetch_price(
+        price_update,
+        collateral_mint,
+        constants::MAX_EXIT_ORACLE_AGE_SECONDS,
+    )?;
+    let price = scale_to_target_decimals(price_data.price, price_data.exponent)?;
+    let confidence = scale_to_target_decimals(
+        i64::try_from(price_data.conf).map_err(|_| StableGuardError::CalculationError)?,
+        price_data.exponent,
+    )?;
+    liability_in_collateral(pool.total_liability()?, price.saturating_sub(confidence))
+}
+
+/// Collateral tokens needed to cover `liability` at `collateral_price` (8 decimals).
+/// Liability is owed in dollars, so collateral trading below its peg covers less of it,
+/// while collateral trading above its peg is still only counted at the peg.
+pub fn liability_in_collateral(liability: u64, collateral_price: i64) -> Result<u64> {
+    let price = collateral_price.min(constants::PEG_PRICE);
+    require!(price > 0, StableGuardError::OraclePriceNotPositive);
+    let collateral = (liability as u128)
+        .checked_mul(constants::PEG_PRICE as u128)
+        .ok_or(StableGuardError::CalculationError)?
+        .checked_div(price as u128)
+        .ok_or(StableGuardError::CalculationError)?;
+    Ok(u64::try_from(collateral).map_err(|_| StableGuardError::CalculationError)?)
+}
+
+/// Fetches the price of `mint` from its Pyth feed, no older than `max_age` seconds.
+fn fetch_price(price_update: &PriceUpdateV2, mint: Pubkey, max_age: u64) -> Result<Price> {
+    let (relevant_feed_id_str, feed_id) = feed_id(mint)?;
+
+    // Fetch the price, ensuring it's not older than the maximum allowed age.
+    // This is a critical defense against using stale data during network issues.
+    let price_data = price_update.get_price_no_older_than(&Clock::get()?, max_age, &feed_id)?;
+    msg!(
+        "Fetched price from Pyth feed {}: {}",
+        relevant_feed_id_str,
+        price_data.price
+    );
+    Ok(price_data)
+}
+
+/// Fails unless `publish_time` is within `[earliest, latest]`.
+fn check_published_between(publish_time: i64, earliest: i64, latest: i64) -> Result<()> {
+    require!(
+        publish_time >= earliest && publish_time <= latest,
+        StableGuardError::OraclePriceOutsideWindow
+    );
+    Ok(())
+}
+
+/// Fails if `conf` is wider than `max_confidence_bps` of `price`. Both share the price's
+/// exponent, so they are compared before scaling.
+fn check_confidence(price: i64, conf: u64, max_confidence_bps: u64) -> Result<()> {
+    // We calculate a max allowed confidence as a percentage (BPS) of the price itself.
+    let max_allowable_confidence = price
+        .unsigned_abs()
+        .checked_mul(max_confidence_bps)
+        .ok_or(StableGuardError::CalculationError)?
+        .checked_div(10000)
+        .ok_or(StableGuardError::CalculationError)?;
+
+    require!(
+        conf <= max_allowable_confidence,
+        StableGuardError::OracleConfidenceTooWide
+    );
+    msg!(
+        "Oracle confidence check passed ({} <= {}).",
+        conf,
+        max_allowable_confidence
+    );
+    Ok(())
+}
+
+/// The Pyth feed `mint` is priced by, with its hex id for logging.
+fn feed_id(mint: Pubkey) -> Result<(&'static str, FeedId)> {
+    let relevant_feed_id_str = match mint {
+        key if key == constants::USDC_MINT_PUBKEY => constants::PYTH_USDC_USD_FEED_ID,
+        key if key == constants::USDT_MINT_PUBKEY => constants::PYTH_USDT_USD_FEED_ID,
+        _ => return err!(StableGuardError::InvalidStablecoinMint),
+    };
+    Ok((
+        relevant_feed_id_str,
+        get_feed_id_from_hex(relevant_feed_id_str)?,
+    ))
+}
+
+/// Scales a Pyth mantissa with `exponent` to the 8 decimals prices are compared at.
+fn scale_to_target_decimals(mantissa: i64, exponent: i32) -> Result<i64> {
+    const TARGET_DECIMALS: i32 = 8;
+
+    require!(exponent <= 0, StableGuardError::OracleExponentUnexpected);
+    let scale_difference = exponent.abs() - TARGET_DECIMALS;
+
+    if scale_difference > 0 {
+        Ok(mantissa
+            .checked_div(10i64.pow(scale_difference as u32))
+            .ok_or(StableGuardError::CalculationError)?)
+    } else if scale_difference < 0 {
+        Ok(mantissa
+            .checked_mul(10i64.pow(scale_difference.unsigned_abs()))
+            .ok_or(StableGuardError::CalculationError)?)
+    } else {
+        Ok(mantissa)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_are_scaled_to_eight_decimals() {
+        assert_eq!(
+            scale_to_target_decimals(99_990_000, -8).unwrap(),
+            99_990_000
+        );
+        assert_eq!(scale_to_target_decimals(9_999, -4).unwrap(), 99_990_000);
+        assert_eq!(
+            scale_to_target_decimals(999_900_000_000, -12).unwrap(),
+            99_990_000
+        );
+        assert!(scale_to_target_decimals(1, 2).is_err());
+    }
+
+    #[test]
+    fn prices_published_on_either_edge_of_the_window_are_accepted() {
+        assert!(check_published_between(1_000, 1_000, 2_000).is_ok());
+        assert!(check_published_between(2_000, 1_000, 2_000).is_ok());
+        assert!(check_published_between(999, 1_000, 2_000).is_err());
+        assert!(check_published_between(2_001, 1_000, 2_000).is_err());
+    }
+
+    #[test]
+    fn confidence_up_to_the_limit_is_accepted() {
+        // 20 bps of $1.00 at 8 decimals.
+        assert!(check_confidence(100_000_000, 200_000, 20).is_ok());
+        assert!(check_confidence(100_000_000, 200_001, 20).is_err());
+        // A depeg observation allows a wider interval.
+        assert!(check_confidence(95_000_000, 1_900_000, 200).is_ok());
+        assert!(check_confidence(95_000_000, 1_900_001, 200).is_err());
+    }
+
+    #[test]
+    fn confidence_is_compared_at_the_price_exponent() {
+        // $1.00 with an exponent of -6, and 20 bps of it.
+        assert!(check_confidence(1_000_000, 2_000, 20).is_ok());
+        assert!(check_confidence(1_000_000, 2_001, 20).is_err());
+    }
+
+    #[test]
+    fn liability_grows_as_collateral_falls_below_its_peg() {
+        assert_eq!(
+            liability_in_collateral(1_000_000, constants::PEG_PRICE).unwrap(),
+            1_000_000
+        );
+        // At $0.80 it takes 1.25 collateral tokens to pay each dollar owed.
+        assert_eq!(
+            liability_in_collateral(1_000_000, 80_000_000).unwrap(),
+            1_250_000
+        );
+        assert_eq!(liability_in_collateral(1_000, 99_999_999).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn collateral_above_its_peg_counts_only_at_the_peg() {
+        assert_eq!(
+            liability_in_collateral(1_000_000, 120_000_000).unwrap(),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn a_non_positive_collateral_price_is_rejected() {
+        assert!(liability_in_collateral(1_000_000, 0).is_err());
+        assert!(liability_in_collateral(1_000_000, -1).is_err());
+    }
+}
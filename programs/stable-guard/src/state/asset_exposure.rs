@@ -0,0 +1,165 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::StableGuardError, LiabilitySchedule};
+
+/// Liability a pool carries on one insured stablecoin. A depeg of that stablecoin
+/// triggers all of it at once.
+#[account]
+#[derive(InitSpace)]
+pub struct AssetExposure {
+    pub insurance_pool: Pubkey,
+    pub insured_mint: Pubkey,
+    pub active_policies: u64,
+    /// Sum of `payout_amount` over the active policies not yet taken over by a settlement
+    /// round.
+    pub active_liability: u64,
+    pub bump: u8,
+
+    /// Most payout liability the pool takes on this stablecoin.
+    pub max_liability: u64,
+    /// Most payout liability the pool takes on this stablecoin, in BPS of the collateral
+    /// across all tranches.
+    pub max_liability_bps: u64,
+
+    /// `active_liability` by expiry day, so a settlement round can take over exactly the
+    /// policies expiring on its day.
+    pub liability_schedule: LiabilitySchedule,
+}
+
+impl AssetExposure {
+    /// Whether taking on `payout_amount` more liability keeps this stablecoin within its
+    /// limits against `pool_collateral`.
+    pub fn within_limits(&self, payout_amount: u64, pool_collateral: u64) -> Result<bool> {
+        let liability = self
+            .active_liability
+            .checked_add(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        let liability_bps = (liability as u128)
+            .checked_mul(10000)
+            .ok_or(StableGuardError::CalculationError)?;
+        let max_liability_bps = (pool_collateral as u128)
+            .checked_mul(self.max_liability_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(liability <= self.max_liability && liability_bps <= max_liability_bps)
+    }
+
+    pub fn add_policy(&mut self, payout_amount: u64, expiry_timestamp: i64) -> Result<()> {
+        self.active_policies = self
+            .active_policies
+            .checked_add(1)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.active_liability = self
+            .active_liability
+            .checked_add(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.liability_schedule.add(expiry_timestamp, payout_amount)
+    }
+
+    pub fn remove_policy(&mut self, payout_amount: u64, expiry_timestamp: i64) -> Result<()> {
+        self.remove_settled_policy()?;
+        self.active_liability = self
+            .active_liability
+            .checked_sub(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.liability_schedule
+            .remove(expiry_timestamp, payout_amount)
+    }
+
+    /// Hands the liability of the policies expiring on `expiry_day` over to a settlement
+    /// round and returns it. The policies stay active until each one is settled.
+    pub fn take_expired_liability(&mut self, expiry_day: i64) -> Result<u64> {
+        let liability = self.liability_schedule.take(expiry_day);
+        self.active_liability = self
+            .active_liability
+            .checked_sub(liability)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(liability)
+    }
+
+    /// Removes a policy whose liability a settlement round already took over.
+    pub fn remove_settled_policy(&mut self) -> Result<()> {
+        self.active_policies = self
+            .active_policies
+            .checked_sub(1)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants;
+
+    const DAY: i64 = constants::SECONDS_PER_DAY;
+
+    fn exposure() -> AssetExposure {
+        AssetExposure {
+            insurance_pool: Pubkey::default(),
+            insured_mint: Pubkey::default(),
+            active_policies: 0,
+            active_liability: 0,
+            bump: 0,
+            max_liability: constants::NO_ASSET_LIABILITY_CAP,
+            max_liability_bps: constants::MAX_ASSET_LIABILITY_BPS,
+            liability_schedule: LiabilitySchedule::default(),
+        }
+    }
+
+    #[test]
+    fn policies_add_and_remove_their_payout_liability() {
+        let mut exposure = exposure();
+        exposure.add_policy(100, 10 * DAY).unwrap();
+        exposure.add_policy(250, 10 * DAY).unwrap();
+        assert_eq!(exposure.active_policies, 2);
+        assert_eq!(exposure.active_liability, 350);
+
+        exposure.remove_policy(100, 10 * DAY).unwrap();
+        assert_eq!(exposure.active_policies, 1);
+        assert_eq!(exposure.active_liability, 250);
+        assert!(exposure.remove_policy(300, 10 * DAY).is_err());
+    }
+
+    #[test]
+    fn a_round_takes_over_the_liability_of_its_day_only() {
+        let mut exposure = exposure();
+        exposure.add_policy(100, 10 * DAY).unwrap();
+        exposure.add_policy(50, 10 * DAY + 1).unwrap();
+        exposure.add_policy(20, 11 * DAY).unwrap();
+
+        assert_eq!(exposure.take_expired_liability(10).unwrap(), 150);
+        assert_eq!(exposure.active_liability, 20);
+        assert_eq!(exposure.active_policies, 3);
+
+        // The round's policies leave without touching the liability it took over.
+        exposure.remove_settled_policy().unwrap();
+        exposure.remove_settled_policy().unwrap();
+        assert_eq!(exposure.active_policies, 1);
+        assert_eq!(exposure.active_liability, 20);
+        assert_eq!(exposure.take_expired_liability(10).unwrap(), 0);
+    }
+
+    #[test]
+    fn liability_is_capped_as_a_share_of_pool_collateral() {
+        let mut exposure = exposure();
+        exposure.add_policy(400, 10 * DAY).unwrap();
+
+        // 50% of 1000 is 500, so 100 more fits and 101 doesn't.
+        assert!(exposure.within_limits(100, 1000).unwrap());
+        assert!(!exposure.within_limits(101, 1000).unwrap());
+        assert!(!exposure.within_limits(1, 0).unwrap());
+    }
+
+    #[test]
+    fn liability_is_capped_at_an_absolute_amount() {
+        let mut exposure = AssetExposure {
+            max_liability: 450,
+            max_liability_bps: 10000,
+            ..exposure()
+        };
+        exposure.add_policy(400, 10 * DAY).unwrap();
+
+        assert!(exposure.within_limits(50, 10_000).unwrap());
+        assert!(!exposure.within_limits(51, 10_000).unwrap());
+    }
+}
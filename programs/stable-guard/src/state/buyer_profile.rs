@@ -0,0 +1,138 @@
+use anchor_lang::prelude::*;
+
+use crate::{constants::MAX_BUYER_ACTIVE_POLICIES, error::StableGuardError};
+
+/// A buyer's active cover in one pool, checked against the pool's per-buyer limits. It also
+/// indexes the buyer's active policies, so a wallet can load them all from this one account.
+#[account]
+#[derive(InitSpace)]
+pub struct BuyerProfile {
+    pub buyer: Pubkey,
+    pub insurance_pool: Pubkey,
+    /// Ids of the active policies, each derivable from
+    /// `[POLICY_SEED, insurance_pool, buyer, policy_id]`.
+    #[max_len(MAX_BUYER_ACTIVE_POLICIES)]
+    pub active_policy_ids: Vec<u64>,
+    /// Sum of `insured_amount` over the active policies.
+    pub active_insured_amount: u64,
+    pub bump: u8,
+}
+
+impl BuyerProfile {
+    pub fn active_policies(&self) -> u64 {
+        self.active_policy_ids.len() as u64
+    }
+
+    /// Whether one more policy insuring `insured_amount` keeps the buyer within
+    /// `max_insured_amount` and `max_active_policies`.
+    pub fn within_limits(
+        &self,
+        insured_amount: u64,
+        max_insured_amount: u64,
+        max_active_policies: u64,
+    ) -> Result<bool> {
+        let insured = self
+            .active_insured_amount
+            .checked_add(insured_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(self.active_policies() < max_active_policies && insured <= max_insured_amount)
+    }
+
+    pub fn add_policy(&mut self, policy_id: u64, insured_amount: u64) -> Result<()> {
+        require!(
+            self.active_policies() < MAX_BUYER_ACTIVE_POLICIES,
+            StableGuardError::BuyerLimitExceeded
+        );
+        self.active_policy_ids.push(policy_id);
+        self.active_insured_amount = self
+            .active_insured_amount
+            .checked_add(insured_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn remove_policy(&mut self, policy_id: u64, insured_amount: u64) -> Result<()> {
+        let position = self
+            .active_policy_ids
+            .iter()
+            .position(|id| *id == policy_id)
+            .ok_or(StableGuardError::PolicyNotInBuyerIndex)?;
+        self.active_policy_ids.swap_remove(position);
+        self.active_insured_amount = self
+            .active_insured_amount
+            .checked_sub(insured_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> BuyerProfile {
+        BuyerProfile {
+            buyer: Pubkey::default(),
+            insurance_pool: Pubkey::default(),
+            active_policy_ids: Vec::new(),
+            active_insured_amount: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn policies_add_and_remove_their_id_and_insured_amount() {
+        let mut profile = profile();
+        profile.add_policy(3, 1_000).unwrap();
+        profile.add_policy(7, 500).unwrap();
+        profile.add_policy(9, 250).unwrap();
+        assert_eq!(profile.active_policy_ids, vec![3, 7, 9]);
+        assert_eq!(profile.active_insured_amount, 1_750);
+
+        profile.remove_policy(3, 1_000).unwrap();
+        assert_eq!(profile.active_policy_ids, vec![9, 7]);
+        assert_eq!(profile.active_policies(), 2);
+        assert_eq!(profile.active_insured_amount, 750);
+        assert!(profile.remove_policy(7, 800).is_err());
+    }
+
+    #[test]
+    fn removing_a_policy_missing_from_the_index_fails() {
+        let mut profile = profile();
+        profile.add_policy(3, 1_000).unwrap();
+
+        assert!(profile.remove_policy(4, 1_000).is_err());
+        assert_eq!(profile.active_policy_ids, vec![3]);
+        assert_eq!(profile.active_insured_amount, 1_000);
+    }
+
+    #[test]
+    fn the_index_holds_at_most_the_policy_cap() {
+        let mut profile = profile();
+        for policy_id in 0..MAX_BUYER_ACTIVE_POLICIES {
+            profile.add_policy(policy_id, 1).unwrap();
+        }
+
+        assert!(profile.add_policy(MAX_BUYER_ACTIVE_POLICIES, 1).is_err());
+        assert_eq!(profile.active_policies(), MAX_BUYER_ACTIVE_POLICIES);
+    }
+
+    #[test]
+    fn coverage_is_capped_per_buyer() {
+        let mut profile = profile();
+        profile.add_policy(1, 1_000).unwrap();
+
+        assert!(profile.within_limits(500, 1_500, 16).unwrap());
+        assert!(!profile.within_limits(501, 1_500, 16).unwrap());
+    }
+
+    #[test]
+    fn active_policies_are_capped_per_buyer() {
+        let mut profile = profile();
+        profile.add_policy(1, 1).unwrap();
+        profile.add_policy(2, 1).unwrap();
+
+        assert!(profile.within_limits(1, u64::MAX, 3).unwrap());
+        assert!(!profile.within_limits(1, u64::MAX, 2).unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use anchor_lang::prelude::*;
+
+use crate::constants;
+
+/// The oracle price an insured stablecoin is settled at for every policy expiring on one
+/// day. Recorded once, after the last of those policies has expired, so policies in the
+/// same window can't settle differently depending on which price update a keeper used.
+#[account]
+#[derive(InitSpace)]
+pub struct DepegEvent {
+    pub insurance_pool: Pubkey,
+    pub insured_mint: Pubkey,
+    /// Days since the Unix epoch, as in the pool's liability schedule.
+    pub expiry_day: i64,
+    /// Oracle price observed for the window, scaled to 8 decimals.
+    pub observed_price: i64,
+    /// When the oracle published `observed_price`.
+    pub observed_timestamp: i64,
+    /// Whether `observed_price` was below the pool's depeg threshold when recorded.
+    pub depegged: bool,
+    /// Whether a settlement round was opened for the day. Its policies are then paid only
+    /// through the round.
+    pub settlement_round_opened: bool,
+    pub bump: u8,
+}
+
+impl DepegEvent {
+    /// When the last policy expiring on `expiry_day` has expired.
+    pub fn window_end(expiry_day: i64) -> Option<i64> {
+        expiry_day
+            .checked_add(1)?
+            .checked_mul(constants::SECONDS_PER_DAY)
+    }
+
+    /// Latest publish time of a price recorded for `expiry_day`. The account is written
+    /// once, so the day settles at the first valid price recorded after its window closes.
+    pub fn latest_observation(expiry_day: i64) -> Option<i64> {
+        Self::window_end(expiry_day)?.checked_add(constants::MAX_OBSERVATION_DELAY_SECONDS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LiabilitySchedule;
+
+    #[test]
+    fn the_window_closes_as_the_expiry_day_ends() {
+        let day = 20_000;
+        let window_end = DepegEvent::window_end(day).unwrap();
+        assert_eq!(window_end, (day + 1) * constants::SECONDS_PER_DAY);
+        assert_eq!(LiabilitySchedule::expiry_day(window_end - 1), day);
+        assert_eq!(LiabilitySchedule::expiry_day(window_end), day + 1);
+        assert_eq!(DepegEvent::window_end(i64::MAX), None);
+    }
+
+    #[test]
+    fn a_price_can_be_recorded_until_a_day_after_the_window_closes() {
+        let day = 20_000;
+        assert_eq!(
+            DepegEvent::latest_observation(day).unwrap(),
+            DepegEvent::window_end(day + 1).unwrap()
+        );
+        assert_eq!(
+            DepegEvent::latest_observation(i64::MAX / constants::SECONDS_PER_DAY),
+            None
+        );
+    }
+}
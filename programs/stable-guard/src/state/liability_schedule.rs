@@ -0,0 +1,185 @@
+use anchor_lang::prelude::*;
+
+use crate::{constants, error::StableGuardError};
+
+/// Max-payout liability of the policies expiring on one day.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, InitSpace)]
+pub struct LiabilityBucket {
+    /// Days since the Unix epoch.
+    pub expiry_day: i64,
+    pub liability: u64,
+}
+
+/// The pool's max-payout liability, bucketed by expiry day in a ring. Policies can't run
+/// longer than the ring, so a bucket is only reused once its day is past; liability of
+/// policies still unsettled by then moves to `overdue_liability`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct LiabilitySchedule {
+    pub buckets: [LiabilityBucket; constants::LIABILITY_BUCKET_COUNT],
+    /// Liability of expired policies whose bucket has since been reused.
+    pub overdue_liability: u64,
+}
+
+impl Default for LiabilitySchedule {
+    fn default() -> Self {
+        Self {
+            buckets: [LiabilityBucket::default(); constants::LIABILITY_BUCKET_COUNT],
+            overdue_liability: 0,
+        }
+    }
+}
+
+impl LiabilitySchedule {
+    pub fn expiry_day(expiry_timestamp: i64) -> i64 {
+        expiry_timestamp.div_euclid(constants::SECONDS_PER_DAY)
+    }
+
+    fn slot(expiry_day: i64) -> usize {
+        expiry_day.rem_euclid(constants::LIABILITY_BUCKET_COUNT as i64) as usize
+    }
+
+    /// Every policy's payout still at risk.
+    pub fn total_liability(&self) -> Result<u64> {
+        self.buckets
+            .iter()
+            .try_fold(self.overdue_liability, |total, bucket| {
+                total
+                    .checked_add(bucket.liability)
+                    .ok_or(StableGuardError::CalculationError.into())
+            })
+    }
+
+    pub fn add(&mut self, expiry_timestamp: i64, payout_amount: u64) -> Result<()> {
+        let expiry_day = Self::expiry_day(expiry_timestamp);
+        let bucket = &mut self.buckets[Self::slot(expiry_day)];
+        if bucket.expiry_day != expiry_day {
+            // The bucket last held a day that is long past.
+            self.overdue_liability = self
+                .overdue_liability
+                .checked_add(bucket.liability)
+                .ok_or(StableGuardError::CalculationError)?;
+            *bucket = LiabilityBucket {
+                expiry_day,
+                liability: 0,
+            };
+        }
+        bucket.liability = bucket
+            .liability
+            .checked_add(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, expiry_timestamp: i64, payout_amount: u64) -> Result<()> {
+        let expiry_day = Self::expiry_day(expiry_timestamp);
+        let bucket = &mut self.buckets[Self::slot(expiry_day)];
+        let liability = if bucket.expiry_day == expiry_day {
+            &mut bucket.liability
+        } else {
+            &mut self.overdue_liability
+        };
+        *liability = liability
+            .checked_sub(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Takes the liability of the policies expiring on `expiry_day` out of the schedule and
+    /// returns it. Nothing is taken once the day's bucket has been reused.
+    pub fn take(&mut self, expiry_day: i64) -> u64 {
+        let bucket = &mut self.buckets[Self::slot(expiry_day)];
+        if bucket.expiry_day != expiry_day {
+            return 0;
+        }
+        std::mem::take(&mut bucket.liability)
+    }
+
+    /// Non-empty buckets, soonest expiry first.
+    pub fn upcoming(&self) -> Vec<LiabilityBucket> {
+        let mut buckets: Vec<LiabilityBucket> = self
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.liability > 0)
+            .copied()
+            .collect();
+        buckets.sort_by_key(|bucket| bucket.expiry_day);
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = constants::SECONDS_PER_DAY;
+    const RING: i64 = constants::LIABILITY_BUCKET_COUNT as i64;
+
+    #[test]
+    fn liability_is_bucketed_by_expiry_day() {
+        let mut schedule = LiabilitySchedule::default();
+        schedule.add(10 * DAY, 100).unwrap();
+        schedule.add(10 * DAY + DAY - 1, 50).unwrap();
+        schedule.add(3 * DAY, 20).unwrap();
+
+        let upcoming = schedule.upcoming();
+        assert_eq!(upcoming.len(), 2);
+        assert_eq!((upcoming[0].expiry_day, upcoming[0].liability), (3, 20));
+        assert_eq!((upcoming[1].expiry_day, upcoming[1].liability), (10, 150));
+        assert_eq!(schedule.total_liability().unwrap(), 170);
+
+        schedule.remove(10 * DAY, 150).unwrap();
+        assert_eq!(schedule.upcoming().len(), 1);
+        assert_eq!(schedule.total_liability().unwrap(), 20);
+    }
+
+    #[test]
+    fn reusing_a_bucket_moves_its_liability_to_overdue() {
+        let mut schedule = LiabilitySchedule::default();
+        schedule.add(5 * DAY, 100).unwrap();
+        schedule.add((5 + RING) * DAY, 40).unwrap();
+
+        assert_eq!(schedule.overdue_liability, 100);
+        assert_eq!(schedule.total_liability().unwrap(), 140);
+        let upcoming = schedule.upcoming();
+        assert_eq!(
+            (upcoming[0].expiry_day, upcoming[0].liability),
+            (5 + RING, 40)
+        );
+    }
+
+    #[test]
+    fn overdue_policies_are_removed_from_overdue_liability() {
+        let mut schedule = LiabilitySchedule::default();
+        schedule.add(5 * DAY, 100).unwrap();
+        schedule.add((5 + RING) * DAY, 40).unwrap();
+
+        schedule.remove(5 * DAY, 60).unwrap();
+        assert_eq!(schedule.overdue_liability, 40);
+        assert_eq!(schedule.total_liability().unwrap(), 80);
+        assert!(schedule.remove(5 * DAY, 41).is_err());
+    }
+
+    #[test]
+    fn taking_a_day_empties_only_its_bucket() {
+        let mut schedule = LiabilitySchedule::default();
+        schedule.add(10 * DAY, 100).unwrap();
+        schedule.add(11 * DAY, 30).unwrap();
+
+        assert_eq!(schedule.take(10), 100);
+        assert_eq!(schedule.take(10), 0);
+        assert_eq!(schedule.total_liability().unwrap(), 30);
+        // The bucket for day 10 + RING is the same slot, but a different day.
+        schedule.add((11 + RING) * DAY, 5).unwrap();
+        assert_eq!(schedule.take(11), 0);
+        assert_eq!(schedule.overdue_liability, 30);
+    }
+
+    #[test]
+    fn days_before_the_epoch_wrap_into_the_ring() {
+        let mut schedule = LiabilitySchedule::default();
+        schedule.add(-DAY, 10).unwrap();
+        schedule.add((RING - 1) * DAY, 20).unwrap();
+        assert_eq!(schedule.overdue_liability, 10);
+        assert_eq!(LiabilitySchedule::expiry_day(-1), -1);
+    }
+}
@@ -0,0 +1,21 @@
+use anchor_lang::prelude::*;
+
+/// A single locked deposit. Its LP tokens are held in the tranche's locked LP vault until
+/// `unlock_timestamp`, and it earns a boosted share of premiums while locked.
+#[account]
+#[derive(InitSpace)]
+pub struct LockedPosition {
+    pub position_id: u64,
+    pub owner: Pubkey,
+    pub insurance_pool: Pubkey,
+    pub tranche_index: u8,
+    pub deposit_amount: u64,
+    pub lp_amount: u64,
+    /// Extra premium weight on top of `lp_amount`.
+    pub boost_weight: u64,
+    /// The tranche's boost reward per weight when the position was opened.
+    pub boost_reward_per_weight_snapshot: u128,
+    pub lock_start_timestamp: i64,
+    pub unlock_timestamp: i64,
+    pub bump: u8,
+}
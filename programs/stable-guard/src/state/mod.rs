@@ -1,9 +1,31 @@
+pub mod asset_exposure;
+pub mod buyer_profile;
+pub mod depeg_event;
+pub mod liability_schedule;
+pub mod locked_position;
 pub mod policy;
 pub mod policy_status;
 pub mod pool;
+pub mod premium_distribution_mode;
+pub mod premium_split_mode;
+pub mod settlement_round;
 pub mod tranche;
+pub mod tranche_set;
+pub mod underwriter_position;
+pub mod withdrawal_ticket;
 
+pub use asset_exposure::*;
+pub use buyer_profile::*;
+pub use depeg_event::*;
+pub use liability_schedule::*;
+pub use locked_position::*;
 pub use policy::*;
 pub use policy_status::*;
 pub use pool::*;
+pub use premium_distribution_mode::*;
+pub use premium_split_mode::*;
+pub use settlement_round::*;
 pub use tranche::*;
+pub use tranche_set::*;
+pub use underwriter_position::*;
+pub use withdrawal_ticket::*;
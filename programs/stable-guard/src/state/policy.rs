@@ -1,6 +1,6 @@
 use anchor_lang::prelude::*;
 
-use crate::policy_status::PolicyStatus;
+use crate::{constants::MAX_TRANCHES, policy_status::PolicyStatus};
 
 #[account]
 #[derive(InitSpace)]
@@ -10,6 +10,10 @@ pub struct PolicyAccount {
     pub insured_stablecoin_mint: Pubkey,
     pub insured_amount: u64,
     pub premium_paid: u64,
+    /// How `premium_paid` was split between the tranches, most junior first. Each part is
+    /// released to its tranche over the policy's term.
+    #[max_len(MAX_TRANCHES)]
+    pub tranche_premiums: Vec<u64>,
     pub payout_amount: u64,
     pub start_timestamp: i64,
     pub expiry_timestamp: i64,
@@ -5,4 +5,5 @@ pub enum PolicyStatus {
     Active,
     ExpiredPaid,
     ExpiredNotPaid,
+    Cancelled,
 }
@@ -1,27 +1,352 @@
 use anchor_lang::prelude::*;
 
+use crate::{
+    constants, error::StableGuardError, LiabilitySchedule, PremiumDistributionMode,
+    PremiumSplitMode,
+};
+
 #[account]
 #[derive(InitSpace)]
 pub struct InsurancePool {
     pub authority: Pubkey,
     pub collateral_mint: Pubkey,
-    pub total_insured_amount: u64,
+    pub total_insured_value: u64,
     pub depeg_threshold: u64,
     pub last_policy_id: u64,
     pub bump: u8,
 
-    /// The total amount of collateral held in the high-risk, high-reward Junior tranche.
-    pub junior_tranche_collateral: u64,
-    /// The total amount of collateral held in the low-risk, low-reward Senior tranche.
-    pub senior_tranche_collateral: u64,
+    /// Number of `TrancheState` accounts, indexed `0..tranche_count` by seniority.
+    pub tranche_count: u8,
+
+    pub premium_split_mode: PremiumSplitMode,
+    /// Bounds on the first-loss tranche's premium share in `PremiumSplitMode::Dynamic`, in
+    /// BPS.
+    pub min_junior_premium_share_bps: u64,
+    pub max_junior_premium_share_bps: u64,
+
+    /// Seconds a withdrawal request has to wait before it can be executed.
+    pub withdrawal_cooldown_seconds: i64,
+
+    pub last_locked_position_id: u64,
+    /// Extra premium weight a lock earns per year of lock duration, in BPS of the locked LP.
+    /// e.g., 5000 BPS = a one-year lock earns premiums on 1.5x its LP tokens
+    pub lock_boost_bps_per_year: u64,
+    pub max_lock_duration_seconds: i64,
+    /// How much unlocked collateral counts towards underwriting capacity, in BPS. Locked
+    /// collateral always counts in full since it can't leave before its unlock date.
+    pub unlocked_capital_weight_bps: u64,
+
+    pub premium_distribution_mode: PremiumDistributionMode,
+
+    /// Seconds newly minted LP tokens wait before they earn premiums.
+    pub lp_warmup_seconds: i64,
+
+    /// Most collateral the pool accepts from deposits across all tranches.
+    pub max_pool_collateral: u64,
+
+    /// Signer allowed to claim the protocol fees held in the treasury vault.
+    pub treasury_authority: Pubkey,
+    /// Share of each premium kept by the protocol, in BPS.
+    pub protocol_fee_bps: u64,
+    pub total_protocol_fees_accrued: u64,
+    pub total_protocol_fees_claimed: u64,
+
+    /// Share of each premium, net of the protocol fee, set aside in the reserve vault, in BPS.
+    pub reserve_premium_bps: u64,
+    /// Collateral in the reserve vault. Payouts draw on it before any tranche.
+    pub reserve_balance: u64,
+
+    /// Collateral in the reserve vault set aside by settlement rounds for the policies in
+    /// them. Neither the reserve nor the LPs can draw on it.
+    pub settlement_fund: u64,
+
+    /// Payout shortfalls owed to policyholders, one claim token per unit of collateral.
+    pub outstanding_claims: u64,
+    /// Collateral in the reserve vault set aside to redeem claim tokens. Claims are senior
+    /// to the reserve and to the LPs, so new premium and top-ups fund them first.
+    pub claims_fund: u64,
+
+    /// Max-payout liability of the active policies by expiry day. Unlike
+    /// `total_insured_value`, this is what the pool can actually lose.
+    pub liability_schedule: LiabilitySchedule,
+
+    /// Most a single buyer may have insured at once.
+    pub max_buyer_insured_amount: u64,
+    /// Most policies a single buyer may have active at once.
+    pub max_buyer_active_policies: u64,
+}
+
+impl InsurancePool {
+    /// Extra premium weight earned by locking `lp_amount` LP tokens for `lock_seconds`.
+    pub fn boost_weight_for_lock(&self, lp_amount: u64, lock_seconds: i64) -> Result<u64> {
+        let weight = (lp_amount as u128)
+            .checked_mul(self.lock_boost_bps_per_year as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_mul(lock_seconds as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000 * constants::SECONDS_PER_YEAR as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(u64::try_from(weight).map_err(|_| StableGuardError::CalculationError)?)
+    }
+
+    /// Payout owed if every active policy were triggered.
+    pub fn total_liability(&self) -> Result<u64> {
+        self.liability_schedule.total_liability()
+    }
+
+    /// A warm-up only withholds claimable premiums. Compounded premiums grow the share price
+    /// of every LP token, warming or not, so the pool must be in claim mode to use one. It
+    /// also lasts at least as long as the withdrawal cooldown, so new capital is held back at
+    /// least as long as capital already in the pool takes to leave.
+    pub fn validate_lp_warmup(&self) -> Result<()> {
+        if self.lp_warmup_seconds == 0 {
+            return Ok(());
+        }
+        require!(
+            self.premium_distribution_mode == PremiumDistributionMode::Claim
+                && self.lp_warmup_seconds >= self.withdrawal_cooldown_seconds,
+            StableGuardError::InvalidPoolConfig
+        );
+        Ok(())
+    }
+
+    /// The protocol's cut of `premium`.
+    pub fn protocol_fee(&self, premium: u64) -> Result<u64> {
+        Self::bps_of(premium, self.protocol_fee_bps)
+    }
+
+    /// The part of `premium` set aside in the reserve.
+    pub fn reserve_contribution(&self, premium: u64) -> Result<u64> {
+        Self::bps_of(premium, self.reserve_premium_bps)
+    }
+
+    /// Adds collateral held in the reserve vault. Any of it needed to fund outstanding
+    /// claims goes to them first.
+    pub fn add_to_reserve(&mut self, amount: u64) -> Result<()> {
+        let claims_funding = self.fund_claims(amount)?;
+        self.reserve_balance = self
+            .reserve_balance
+            .checked_add(amount - claims_funding)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn unfunded_claims(&self) -> u64 {
+        self.outstanding_claims.saturating_sub(self.claims_fund)
+    }
+
+    /// Sets aside up to `amount` for claims not yet funded. Returns the part set aside.
+    pub fn fund_claims(&mut self, amount: u64) -> Result<u64> {
+        let claims_funding = amount.min(self.unfunded_claims());
+        self.claims_fund = self
+            .claims_fund
+            .checked_add(claims_funding)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(claims_funding)
+    }
+
+    pub fn issue_claims(&mut self, amount: u64) -> Result<()> {
+        self.outstanding_claims = self
+            .outstanding_claims
+            .checked_add(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn redeem_claims(&mut self, amount: u64) -> Result<()> {
+        self.claims_fund = self
+            .claims_fund
+            .checked_sub(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.outstanding_claims = self
+            .outstanding_claims
+            .checked_sub(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    fn bps_of(amount: u64, bps: u64) -> Result<u64> {
+        Ok((amount as u128)
+            .checked_mul(bps as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000)
+            .ok_or(StableGuardError::CalculationError)? as u64)
+    }
+
+    /// Whether tranches accrue their coupon, i.e. the pool is in
+    /// `PremiumSplitMode::SeniorCoupon`.
+    pub fn coupon_applies(&self) -> bool {
+        self.premium_split_mode == PremiumSplitMode::SeniorCoupon
+    }
+}
+
+#[cfg(test)]
+impl InsurancePool {
+    /// A pool with no policies and no limits, for tests to fill in.
+    pub(crate) fn for_test() -> Self {
+        Self {
+            authority: Pubkey::default(),
+            collateral_mint: Pubkey::default(),
+            total_insured_value: 0,
+            depeg_threshold: constants::DEPEG_THRESHOLD_PRICE as u64,
+            last_policy_id: 0,
+            bump: 0,
+            tranche_count: 0,
+            premium_split_mode: PremiumSplitMode::SeniorCoupon,
+            min_junior_premium_share_bps: 0,
+            max_junior_premium_share_bps: 10000,
+            withdrawal_cooldown_seconds: 0,
+            last_locked_position_id: 0,
+            lock_boost_bps_per_year: 0,
+            max_lock_duration_seconds: 0,
+            unlocked_capital_weight_bps: 10000,
+            premium_distribution_mode: PremiumDistributionMode::Compound,
+            lp_warmup_seconds: 0,
+            max_pool_collateral: constants::NO_DEPOSIT_CAP,
+            treasury_authority: Pubkey::default(),
+            protocol_fee_bps: 0,
+            total_protocol_fees_accrued: 0,
+            total_protocol_fees_claimed: 0,
+            reserve_premium_bps: 0,
+            reserve_balance: 0,
+            settlement_fund: 0,
+            outstanding_claims: 0,
+            claims_fund: 0,
+            liability_schedule: LiabilitySchedule::default(),
+            max_buyer_insured_amount: constants::NO_BUYER_COVERAGE_CAP,
+            max_buyer_active_policies: constants::MAX_BUYER_ACTIVE_POLICIES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEAR: i64 = constants::SECONDS_PER_YEAR as i64;
+
+    #[test]
+    fn lock_boost_weight_scales_with_amount_and_duration() {
+        let pool = InsurancePool {
+            lock_boost_bps_per_year: 5000,
+            ..InsurancePool::for_test()
+        };
+        assert_eq!(
+            pool.boost_weight_for_lock(1_000_000, YEAR).unwrap(),
+            500_000
+        );
+        assert_eq!(
+            pool.boost_weight_for_lock(1_000_000, YEAR / 4).unwrap(),
+            125_000
+        );
+        assert_eq!(pool.boost_weight_for_lock(1_000_000, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn warmup_requires_claim_mode() {
+        let mut pool = InsurancePool {
+            lp_warmup_seconds: 3 * 24 * 60 * 60,
+            withdrawal_cooldown_seconds: constants::WITHDRAWAL_COOLDOWN_SECONDS,
+            ..InsurancePool::for_test()
+        };
+        assert!(pool.validate_lp_warmup().is_err());
+
+        pool.premium_distribution_mode = PremiumDistributionMode::Claim;
+        pool.validate_lp_warmup().unwrap();
+
+        pool.lp_warmup_seconds = 0;
+        pool.premium_distribution_mode = PremiumDistributionMode::Compound;
+        pool.validate_lp_warmup().unwrap();
+    }
+
+    #[test]
+    fn warmup_is_at_least_the_withdrawal_cooldown() {
+        let mut pool = InsurancePool {
+            premium_distribution_mode: PremiumDistributionMode::Claim,
+            withdrawal_cooldown_seconds: 100,
+            lp_warmup_seconds: 99,
+            ..InsurancePool::for_test()
+        };
+        assert!(pool.validate_lp_warmup().is_err());
+        pool.lp_warmup_seconds = 100;
+        pool.validate_lp_warmup().unwrap();
+    }
+
+    #[test]
+    fn protocol_fee_is_a_bps_cut_rounded_down() {
+        let mut pool = InsurancePool::for_test();
+        assert_eq!(pool.protocol_fee(1_000_000).unwrap(), 0);
+
+        pool.protocol_fee_bps = 1000;
+        assert_eq!(pool.protocol_fee(1_000_000).unwrap(), 100_000);
+        assert_eq!(pool.protocol_fee(9).unwrap(), 0);
+        assert_eq!(pool.protocol_fee(u64::MAX).unwrap(), u64::MAX / 10);
+    }
+
+    #[test]
+    fn reserve_cut_is_sized_on_the_premium_after_the_fee() {
+        let pool = InsurancePool {
+            protocol_fee_bps: 1000,
+            reserve_premium_bps: 2000,
+            ..InsurancePool::for_test()
+        };
+        let premium = 1_000_000;
+        let fee = pool.protocol_fee(premium).unwrap();
+        let reserve = pool.reserve_contribution(premium - fee).unwrap();
+        assert_eq!(fee, 100_000);
+        assert_eq!(reserve, 180_000);
+        // The tranches split what neither cut takes.
+        assert_eq!(premium - fee - reserve, 720_000);
+    }
+
+    #[test]
+    fn add_to_reserve_tracks_the_balance() {
+        let mut pool = InsurancePool::for_test();
+        pool.add_to_reserve(500).unwrap();
+        pool.add_to_reserve(250).unwrap();
+        assert_eq!(pool.reserve_balance, 750);
+
+        pool.reserve_balance = u64::MAX;
+        assert!(pool.add_to_reserve(1).is_err());
+    }
+
+    #[test]
+    fn fund_claims_sets_aside_no_more_than_is_unfunded() {
+        let mut pool = InsurancePool::for_test();
+        assert_eq!(pool.fund_claims(100).unwrap(), 0);
+
+        pool.issue_claims(300).unwrap();
+        assert_eq!(pool.fund_claims(100).unwrap(), 100);
+        assert_eq!(pool.unfunded_claims(), 200);
+        assert_eq!(pool.fund_claims(500).unwrap(), 200);
+        assert_eq!(pool.claims_fund, 300);
+        assert_eq!(pool.unfunded_claims(), 0);
+    }
+
+    #[test]
+    fn add_to_reserve_funds_outstanding_claims_first() {
+        let mut pool = InsurancePool::for_test();
+        pool.issue_claims(300).unwrap();
+
+        pool.add_to_reserve(200).unwrap();
+        assert_eq!(pool.claims_fund, 200);
+        assert_eq!(pool.reserve_balance, 0);
+
+        pool.add_to_reserve(250).unwrap();
+        assert_eq!(pool.claims_fund, 300);
+        assert_eq!(pool.reserve_balance, 150);
+    }
 
-    /// The percentage of premiums allocated to the junior tranche, in basis points (BPS).
-    /// e.g., 8000 BPS = 80%
-    pub junior_lp_mint: Pubkey,
-    /// The percentage of premiums allocated to the senior tranche, in basis points (BPS).
-    /// e.g., 2000 BPS = 20%
-    pub senior_lp_mint: Pubkey,
+    #[test]
+    fn redeeming_claims_draws_down_the_fund() {
+        let mut pool = InsurancePool::for_test();
+        pool.issue_claims(300).unwrap();
+        pool.fund_claims(200).unwrap();
 
-    pub junior_tranche_share: u64,
-    pub senior_tranche_share: u64,
+        pool.redeem_claims(150).unwrap();
+        assert_eq!(pool.outstanding_claims, 150);
+        assert_eq!(pool.claims_fund, 50);
+        assert_eq!(pool.unfunded_claims(), 100);
+        assert!(pool.redeem_claims(51).is_err());
+    }
 }
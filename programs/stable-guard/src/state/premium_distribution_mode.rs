@@ -0,0 +1,10 @@
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum PremiumDistributionMode {
+    /// Premiums grow the tranche collateral, so LPs earn through a rising share price.
+    Compound,
+    /// Premiums are set aside per LP share and underwriters claim them as cash, leaving
+    /// their principal staked.
+    Claim,
+}
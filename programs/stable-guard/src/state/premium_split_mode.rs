@@ -0,0 +1,10 @@
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum PremiumSplitMode {
+    /// Senior LPs are paid their target coupon first and junior LPs take the residual.
+    SeniorCoupon,
+    /// Junior LPs get a share proportional to the exposure they carry, clamped to the
+    /// configured bounds. Senior LPs take the rest.
+    Dynamic,
+}
@@ -0,0 +1,161 @@
+use anchor_lang::prelude::*;
+
+use crate::error::StableGuardError;
+
+/// A confirmed depeg of one insured stablecoin on one expiry day. Every policy on that
+/// stablecoin expiring that day is paid the same share of its payout from collateral set
+/// aside when the round opens, so when the pool can't cover them all, the first claimants
+/// don't drain it at the expense of the rest.
+#[account]
+#[derive(InitSpace)]
+pub struct SettlementRound {
+    pub insurance_pool: Pubkey,
+    pub insured_mint: Pubkey,
+    /// Days since the Unix epoch, as in the pool's liability schedule.
+    pub expiry_day: i64,
+    pub opened_timestamp: i64,
+    /// Price recorded for the day that confirmed the depeg, scaled to 8 decimals.
+    pub depeg_price: i64,
+    /// Payout owed to every policy in the round, in full.
+    pub total_liability: u64,
+    /// Reserve plus tranche collateral when the round opened.
+    pub available_collateral: u64,
+    /// Share of its payout each policy in the round receives, in BPS.
+    pub recovery_ratio_bps: u64,
+    /// Collateral moved into the pool's settlement fund for the round.
+    pub set_aside: u64,
+    pub settled_liability: u64,
+    pub paid_out: u64,
+    pub bump: u8,
+}
+
+impl SettlementRound {
+    /// Share of `total_liability` that `available_collateral` can cover, capped at 100%.
+    pub fn recovery_ratio_bps(total_liability: u64, available_collateral: u64) -> Result<u64> {
+        if total_liability <= available_collateral {
+            return Ok(10000);
+        }
+        Ok((available_collateral as u128)
+            .checked_mul(10000)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(total_liability as u128)
+            .ok_or(StableGuardError::CalculationError)? as u64)
+    }
+
+    /// What a policy with `payout_amount` is paid in this round.
+    pub fn recovery_amount(&self, payout_amount: u64) -> Result<u64> {
+        Ok((payout_amount as u128)
+            .checked_mul(self.recovery_ratio_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000)
+            .ok_or(StableGuardError::CalculationError)? as u64)
+    }
+
+    /// What is left of the set-aside collateral once every policy has been paid.
+    pub fn unpaid_set_aside(&self) -> Result<u64> {
+        Ok(self
+            .set_aside
+            .checked_sub(self.paid_out)
+            .ok_or(StableGuardError::CalculationError)?)
+    }
+
+    /// Records a settled policy. Returns whether every policy in the round is now settled.
+    pub fn record_settlement(&mut self, payout_amount: u64, paid: u64) -> Result<bool> {
+        self.settled_liability = self
+            .settled_liability
+            .checked_add(payout_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.paid_out = self
+            .paid_out
+            .checked_add(paid)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(self.settled_liability >= self.total_liability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(total_liability: u64, available_collateral: u64) -> SettlementRound {
+        SettlementRound {
+            insurance_pool: Pubkey::default(),
+            insured_mint: Pubkey::default(),
+            expiry_day: 1,
+            opened_timestamp: 0,
+            depeg_price: 0,
+            total_liability,
+            available_collateral,
+            recovery_ratio_bps: SettlementRound::recovery_ratio_bps(
+                total_liability,
+                available_collateral,
+            )
+            .unwrap(),
+            set_aside: 0,
+            settled_liability: 0,
+            paid_out: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn recovery_ratio_is_capped_at_full_payout() {
+        assert_eq!(
+            SettlementRound::recovery_ratio_bps(1000, 1000).unwrap(),
+            10000
+        );
+        assert_eq!(
+            SettlementRound::recovery_ratio_bps(1000, 5000).unwrap(),
+            10000
+        );
+        assert_eq!(
+            SettlementRound::recovery_ratio_bps(4000, 1000).unwrap(),
+            2500
+        );
+        assert_eq!(SettlementRound::recovery_ratio_bps(3000, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn recovery_amount_rounds_down() {
+        let round = round(3000, 1000);
+        assert_eq!(round.recovery_ratio_bps, 3333);
+        assert_eq!(round.recovery_amount(1000).unwrap(), 333);
+        assert_eq!(round.recovery_amount(1).unwrap(), 0);
+    }
+
+    #[test]
+    fn policies_are_paid_no_more_than_was_available() {
+        let mut round = round(3000, 1000);
+        let payouts = [1, 998, 1001, 1000];
+        for (settled, payout) in payouts.iter().enumerate() {
+            let paid = round.recovery_amount(*payout).unwrap();
+            let all_settled = round.record_settlement(*payout, paid).unwrap();
+            assert_eq!(all_settled, settled + 1 == payouts.len());
+        }
+        assert_eq!(round.settled_liability, 3000);
+        assert_eq!(round.paid_out, 998);
+        assert!(round.paid_out <= round.available_collateral);
+    }
+
+    #[test]
+    fn a_round_is_covered_against_its_own_liability() {
+        // The pool may owe far more on other days than it holds, but the round only owes
+        // the 1000 its policies are due, which the 4000 available covers in full.
+        let round = round(1000, 4000);
+        assert_eq!(round.recovery_ratio_bps, 10000);
+        assert_eq!(round.recovery_amount(1000).unwrap(), 1000);
+    }
+
+    #[test]
+    fn rounding_each_payout_leaves_some_of_the_set_aside_unpaid() {
+        let mut round = round(3000, 1000);
+        round.set_aside = round.recovery_amount(round.total_liability).unwrap();
+        assert_eq!(round.set_aside, 999);
+        for payout in [1500, 1500] {
+            let paid = round.recovery_amount(payout).unwrap();
+            round.record_settlement(payout, paid).unwrap();
+        }
+        assert_eq!(round.paid_out, 998);
+        assert_eq!(round.unpaid_set_aside().unwrap(), 1);
+    }
+}
@@ -1,7 +1,843 @@
 use anchor_lang::prelude::*;
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
-pub enum Tranche {
-    Senior,
-    Junior,
+use crate::{constants, error::StableGuardError, PremiumDistributionMode};
+
+/// One risk layer of a pool. Tranches are indexed by seniority: index 0 is the first-loss
+/// tranche and each higher index only takes losses once every tranche below it is wiped
+/// out. Each tranche has its own LP mint and collateral vault.
+#[account]
+#[derive(InitSpace)]
+pub struct TrancheState {
+    pub insurance_pool: Pubkey,
+    pub index: u8,
+    pub lp_mint: Pubkey,
+    pub collateral_vault: Pubkey,
+    /// Holds the LP tokens of locked positions in this tranche.
+    pub locked_lp_vault: Pubkey,
+    /// Holds the LP tokens staked by underwriter positions in this tranche.
+    pub staked_lp_vault: Pubkey,
+    pub bump: u8,
+
+    /// Premium weight per unit of liability carried, in BPS, used by
+    /// `PremiumSplitMode::Dynamic`. Riskier layers are usually given a higher weight.
+    pub premium_weight_bps: u64,
+    /// Share of the pool's collateral that must sit in the tranches below this one, in BPS.
+    pub attachment_point_bps: u64,
+    /// Most collateral this tranche accepts from deposits.
+    pub max_collateral: u64,
+
+    /// Collateral backing this tranche's LP tokens.
+    pub collateral: u64,
+    /// LP tokens of this tranche in circulation, including escrowed and locked ones.
+    pub lp_supply: u64,
+
+    /// Annual coupon this tranche is paid out of premiums before the tranches below it, in
+    /// BPS, in `PremiumSplitMode::SeniorCoupon`. Ignored for the first-loss tranche, which
+    /// takes whatever is left.
+    pub target_apr_bps: u64,
+    /// Coupon accrued but not yet covered by premiums. It is paid first out of later
+    /// premiums.
+    pub coupon_shortfall: u64,
+    /// Lifetime coupon accrued against this tranche.
+    pub coupon_accrued: u64,
+    /// Lifetime premium paid into this tranche as coupon.
+    pub coupon_paid: u64,
+    pub last_coupon_accrual_timestamp: i64,
+
+    /// LP tokens held on behalf of locked positions.
+    pub locked_lp: u64,
+    /// Extra premium weight of the locked positions, on top of their LP.
+    pub boost_weight: u64,
+    /// Boost premium earned per unit of boost weight, scaled by `REWARD_PRECISION`.
+    pub boost_reward_per_weight: u128,
+    /// Boost premiums sitting in the vault for locked positions. Not part of the collateral.
+    pub boost_rewards: u64,
+
+    /// LP tokens the program holds for underwriter positions, staked or locked. Only these
+    /// earn claimable premiums, since LP tokens in wallets can change hands unseen.
+    pub staked_lp: u64,
+    /// Claimable premium earned per staked LP token, scaled by `REWARD_PRECISION`.
+    pub premium_per_lp_share: u128,
+    /// Claimable premiums sitting in the vault. Not part of the collateral.
+    pub unclaimed_premiums: u64,
+
+    /// Premiums paid into the vault that have not been earned yet. They are released to LPs
+    /// linearly over each policy's term and back no LP shares until then, so they are never
+    /// part of the withdrawable collateral.
+    pub unearned_premium: u64,
+    /// Premium released per second across active policies, scaled by `REWARD_PRECISION`.
+    pub premium_release_rate: u128,
+    pub last_premium_release_timestamp: i64,
+}
+
+impl TrancheState {
+    /// Checks the tranche's own parameters. The first-loss tranche has nothing below it, so
+    /// any attachment point above zero could never be met.
+    pub fn validate_config(&self) -> Result<()> {
+        require!(
+            self.attachment_point_bps <= 10000
+                && (!self.is_first_loss() || self.attachment_point_bps == 0)
+                && self.target_apr_bps <= constants::MAX_TARGET_APR_BPS,
+            StableGuardError::InvalidPoolConfig
+        );
+        Ok(())
+    }
+
+    pub fn is_first_loss(&self) -> bool {
+        self.index == 0
+    }
+
+    pub fn credit(&mut self, amount: u64) -> Result<()> {
+        self.collateral = self
+            .collateral
+            .checked_add(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn debit(&mut self, amount: u64) -> Result<()> {
+        self.collateral = self
+            .collateral
+            .checked_sub(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn record_lp_minted(&mut self, lp_amount: u64) -> Result<()> {
+        self.lp_supply = self
+            .lp_supply
+            .checked_add(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn record_lp_burned(&mut self, lp_amount: u64) -> Result<()> {
+        self.lp_supply = self
+            .lp_supply
+            .checked_sub(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn record_lp_staked(&mut self, lp_amount: u64) -> Result<()> {
+        self.staked_lp = self
+            .staked_lp
+            .checked_add(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn record_lp_unstaked(&mut self, lp_amount: u64) -> Result<()> {
+        self.staked_lp = self
+            .staked_lp
+            .checked_sub(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Accrues the coupon on the current collateral since the last accrual. Must run before
+    /// the collateral changes so the coupon is charged on the balance that was actually
+    /// outstanding.
+    pub fn accrue_coupon(&mut self, now: i64, coupon_applies: bool) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_coupon_accrual_timestamp);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        self.last_coupon_accrual_timestamp = now;
+        if !coupon_applies || self.is_first_loss() {
+            // No coupon is owed while the pool splits premiums dynamically.
+            return Ok(());
+        }
+
+        let accrued = (self.collateral as u128)
+            .checked_mul(self.target_apr_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_mul(elapsed as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000 * constants::SECONDS_PER_YEAR as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        let accrued = u64::try_from(accrued).map_err(|_| StableGuardError::CalculationError)?;
+
+        self.coupon_shortfall = self
+            .coupon_shortfall
+            .checked_add(accrued)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.coupon_accrued = self
+            .coupon_accrued
+            .checked_add(accrued)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Pays as much of the outstanding coupon as `available` covers and returns the amount
+    /// paid.
+    pub fn pay_coupon(&mut self, available: u64) -> Result<u64> {
+        let paid = available.min(self.coupon_shortfall);
+        self.coupon_shortfall -= paid;
+        self.coupon_paid = self
+            .coupon_paid
+            .checked_add(paid)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(paid)
+    }
+
+    /// How much more collateral this tranche accepts before it hits its cap.
+    pub fn deposit_room(&self) -> u64 {
+        self.max_collateral.saturating_sub(self.collateral)
+    }
+
+    /// LP tokens minted for a deposit. Virtual LP shares and virtual collateral are added to
+    /// both sides of the price so an early depositor can't inflate the share price and round
+    /// later deposits down to nothing.
+    pub fn lp_tokens_for_deposit(&self, deposit_amount: u64) -> Result<u64> {
+        let lp_tokens = (deposit_amount as u128)
+            .checked_mul(self.lp_supply as u128 + constants::VIRTUAL_LP_SHARES as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(self.collateral as u128 + constants::VIRTUAL_COLLATERAL as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(u64::try_from(lp_tokens).map_err(|_| StableGuardError::CalculationError)?)
+    }
+
+    /// Collateral returned for burning LP tokens, priced the same way as
+    /// `lp_tokens_for_deposit`.
+    pub fn collateral_for_lp_tokens(&self, lp_amount: u64) -> Result<u64> {
+        let collateral = (lp_amount as u128)
+            .checked_mul(self.collateral as u128 + constants::VIRTUAL_COLLATERAL as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(self.lp_supply as u128 + constants::VIRTUAL_LP_SHARES as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        // Never pay out more than the tranche actually holds.
+        Ok(u64::try_from(collateral)
+            .map_err(|_| StableGuardError::CalculationError)?
+            .min(self.collateral))
+    }
+
+    /// Credits a premium to this tranche. Locked positions take a slice proportional to their
+    /// boost weight, which is set aside for them to collect on unlock. The rest grows the
+    /// collateral for all LPs, or in `PremiumDistributionMode::Claim` is set aside per staked
+    /// LP token for underwriters to claim. The share earned by unstaked LP tokens can't be
+    /// traced to a position, so it grows the collateral instead.
+    pub fn distribute_premium(
+        &mut self,
+        amount: u64,
+        distribution_mode: PremiumDistributionMode,
+    ) -> Result<()> {
+        let boost_amount = if self.boost_weight == 0 {
+            0
+        } else {
+            (amount as u128)
+                .checked_mul(self.boost_weight as u128)
+                .ok_or(StableGuardError::CalculationError)?
+                .checked_div(self.lp_supply as u128 + self.boost_weight as u128)
+                .ok_or(StableGuardError::CalculationError)? as u64
+        };
+
+        if boost_amount > 0 {
+            self.boost_reward_per_weight = self
+                .boost_reward_per_weight
+                .checked_add(
+                    (boost_amount as u128)
+                        .checked_mul(constants::REWARD_PRECISION)
+                        .ok_or(StableGuardError::CalculationError)?
+                        / self.boost_weight as u128,
+                )
+                .ok_or(StableGuardError::CalculationError)?;
+            self.boost_rewards = self
+                .boost_rewards
+                .checked_add(boost_amount)
+                .ok_or(StableGuardError::CalculationError)?;
+        }
+
+        let base_amount = amount - boost_amount;
+        if distribution_mode == PremiumDistributionMode::Compound || self.staked_lp == 0 {
+            return self.credit(base_amount);
+        }
+
+        let claimable_amount = (base_amount as u128)
+            .checked_mul(self.staked_lp as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(self.lp_supply as u128)
+            .ok_or(StableGuardError::CalculationError)? as u64;
+        self.credit(base_amount - claimable_amount)?;
+
+        self.premium_per_lp_share = self
+            .premium_per_lp_share
+            .checked_add(
+                (claimable_amount as u128)
+                    .checked_mul(constants::REWARD_PRECISION)
+                    .ok_or(StableGuardError::CalculationError)?
+                    / self.staked_lp as u128,
+            )
+            .ok_or(StableGuardError::CalculationError)?;
+        self.unclaimed_premiums = self
+            .unclaimed_premiums
+            .checked_add(claimable_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn release_claimed_premiums(&mut self, amount: u64) -> Result<()> {
+        self.unclaimed_premiums = self
+            .unclaimed_premiums
+            .checked_sub(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Hands premiums forfeited by warming LP tokens back to the collateral.
+    pub fn forfeit_premiums(&mut self, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        self.release_claimed_premiums(amount)?;
+        self.credit(amount)
+    }
+
+    /// Release rate for a premium earned evenly over `duration_seconds`.
+    pub fn premium_release_rate(amount: u64, duration_seconds: i64) -> Result<u128> {
+        Ok((amount as u128)
+            .checked_mul(constants::REWARD_PRECISION)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(duration_seconds as u128)
+            .ok_or(StableGuardError::CalculationError)?)
+    }
+
+    /// Books a premium paid into the vault as unearned, to be released over the policy's
+    /// term.
+    pub fn add_unearned_premium(&mut self, amount: u64, duration_seconds: i64) -> Result<()> {
+        let rate = Self::premium_release_rate(amount, duration_seconds)?;
+        self.unearned_premium = self
+            .unearned_premium
+            .checked_add(amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.premium_release_rate = self
+            .premium_release_rate
+            .checked_add(rate)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Stops releasing a settled policy's premium. Premiums are released to the last release
+    /// time, so a policy settled late has kept releasing past its expiry out of the other
+    /// policies' reserve; that overshoot is taken back from the collateral.
+    pub fn settle_policy_premium(
+        &mut self,
+        amount: u64,
+        duration_seconds: i64,
+        expiry_timestamp: i64,
+    ) -> Result<()> {
+        let rate = Self::premium_release_rate(amount, duration_seconds)?;
+        let overrun = self
+            .last_premium_release_timestamp
+            .saturating_sub(expiry_timestamp)
+            .max(0);
+        let overshoot = (rate
+            .checked_mul(overrun as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            / constants::REWARD_PRECISION)
+            .min(self.collateral as u128) as u64;
+        self.debit(overshoot)?;
+
+        self.unearned_premium = self
+            .unearned_premium
+            .checked_add(overshoot)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.premium_release_rate = self.premium_release_rate.saturating_sub(rate);
+        Ok(())
+    }
+
+    /// Stops releasing a cancelled policy's premium and takes its unearned part out of the
+    /// reserve. Returns the amount to refund to the buyer.
+    pub fn cancel_policy_premium(
+        &mut self,
+        amount: u64,
+        duration_seconds: i64,
+        remaining_seconds: i64,
+    ) -> Result<u64> {
+        let rate = Self::premium_release_rate(amount, duration_seconds)?;
+        let refund = (amount as u128)
+            .checked_mul(remaining_seconds as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(duration_seconds as u128)
+            .ok_or(StableGuardError::CalculationError)? as u64;
+
+        self.premium_release_rate = self.premium_release_rate.saturating_sub(rate);
+        let from_reserve = refund.min(self.unearned_premium);
+        self.unearned_premium -= from_reserve;
+
+        // Only reachable if a late settlement drained the reserve more than the tranche
+        // could give back.
+        self.debit(refund - from_reserve)?;
+        Ok(refund)
+    }
+
+    /// Releases the premium earned since the last release to this tranche's LPs. Runs before
+    /// anything reads or changes the share price so LPs only ever earn premium for the time
+    /// their capital was actually at risk.
+    pub fn release_premiums(
+        &mut self,
+        now: i64,
+        distribution_mode: PremiumDistributionMode,
+    ) -> Result<()> {
+        let elapsed = now.saturating_sub(self.last_premium_release_timestamp);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        self.last_premium_release_timestamp = now;
+
+        let released = self
+            .premium_release_rate
+            .checked_mul(elapsed as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            / constants::REWARD_PRECISION;
+        // Once no policy is releasing, what's left is rounding dust from earlier releases.
+        let released = if self.premium_release_rate == 0 {
+            self.unearned_premium
+        } else {
+            (self.unearned_premium as u128).min(released) as u64
+        };
+        if released == 0 {
+            return Ok(());
+        }
+        self.unearned_premium -= released;
+        self.distribute_premium(released, distribution_mode)
+    }
+
+    pub fn add_locked_position(&mut self, lp_amount: u64, boost_weight: u64) -> Result<()> {
+        self.locked_lp = self
+            .locked_lp
+            .checked_add(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.boost_weight = self
+            .boost_weight
+            .checked_add(boost_weight)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    pub fn remove_locked_position(
+        &mut self,
+        lp_amount: u64,
+        boost_weight: u64,
+        boost_reward: u64,
+    ) -> Result<()> {
+        self.locked_lp = self
+            .locked_lp
+            .checked_sub(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.boost_weight = self
+            .boost_weight
+            .checked_sub(boost_weight)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.boost_rewards = self
+            .boost_rewards
+            .checked_sub(boost_reward)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl TrancheState {
+    /// An empty tranche at `index`, for tests to fill in.
+    pub(crate) fn for_test(index: u8) -> Self {
+        Self {
+            insurance_pool: Pubkey::default(),
+            index,
+            lp_mint: Pubkey::default(),
+            collateral_vault: Pubkey::default(),
+            locked_lp_vault: Pubkey::default(),
+            staked_lp_vault: Pubkey::default(),
+            bump: 0,
+            premium_weight_bps: 0,
+            attachment_point_bps: 0,
+            max_collateral: constants::NO_DEPOSIT_CAP,
+            collateral: 0,
+            lp_supply: 0,
+            target_apr_bps: 0,
+            coupon_shortfall: 0,
+            coupon_accrued: 0,
+            coupon_paid: 0,
+            last_coupon_accrual_timestamp: 0,
+            locked_lp: 0,
+            boost_weight: 0,
+            boost_reward_per_weight: 0,
+            boost_rewards: 0,
+            staked_lp: 0,
+            premium_per_lp_share: 0,
+            unclaimed_premiums: 0,
+            unearned_premium: 0,
+            premium_release_rate: 0,
+            last_premium_release_timestamp: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEAR: i64 = constants::SECONDS_PER_YEAR as i64;
+    const COMPOUND: PremiumDistributionMode = PremiumDistributionMode::Compound;
+    const CLAIM: PremiumDistributionMode = PremiumDistributionMode::Claim;
+
+    fn senior_tranche(collateral: u64, target_apr_bps: u64) -> TrancheState {
+        TrancheState {
+            collateral,
+            target_apr_bps,
+            ..TrancheState::for_test(1)
+        }
+    }
+
+    fn tranche_with_supply(lp_supply: u64) -> TrancheState {
+        TrancheState {
+            lp_supply,
+            ..TrancheState::for_test(0)
+        }
+    }
+
+    #[test]
+    fn first_loss_tranche_cannot_have_an_attachment_point() {
+        let mut tranche = TrancheState {
+            attachment_point_bps: 1,
+            ..TrancheState::for_test(0)
+        };
+        assert!(tranche.validate_config().is_err());
+
+        tranche.index = 1;
+        tranche.validate_config().unwrap();
+        tranche.attachment_point_bps = 10001;
+        assert!(tranche.validate_config().is_err());
+    }
+
+    #[test]
+    fn coupon_accrues_on_collateral_over_elapsed_time() {
+        let mut tranche = senior_tranche(1_000_000, 1000);
+        tranche.accrue_coupon(YEAR / 2, true).unwrap();
+        assert_eq!(tranche.coupon_shortfall, 50_000);
+        assert_eq!(tranche.coupon_accrued, 50_000);
+        assert_eq!(tranche.last_coupon_accrual_timestamp, YEAR / 2);
+
+        tranche.accrue_coupon(YEAR, true).unwrap();
+        assert_eq!(tranche.coupon_shortfall, 100_000);
+        assert_eq!(tranche.coupon_accrued, 100_000);
+    }
+
+    #[test]
+    fn coupon_does_not_accrue_when_time_has_not_advanced() {
+        let mut tranche = senior_tranche(1_000_000, 1000);
+        tranche.accrue_coupon(YEAR, true).unwrap();
+        tranche.accrue_coupon(YEAR, true).unwrap();
+        tranche.accrue_coupon(YEAR - 1, true).unwrap();
+        assert_eq!(tranche.coupon_accrued, 100_000);
+        assert_eq!(tranche.last_coupon_accrual_timestamp, YEAR);
+    }
+
+    #[test]
+    fn first_loss_tranche_accrues_no_coupon() {
+        let mut tranche = TrancheState {
+            collateral: 1_000_000,
+            target_apr_bps: 1000,
+            ..TrancheState::for_test(0)
+        };
+        tranche.accrue_coupon(YEAR, true).unwrap();
+        assert_eq!(tranche.coupon_accrued, 0);
+        assert_eq!(tranche.last_coupon_accrual_timestamp, YEAR);
+    }
+
+    #[test]
+    fn coupon_is_not_backdated_to_when_it_did_not_apply() {
+        let mut tranche = senior_tranche(1_000_000, 1000);
+        tranche.accrue_coupon(YEAR, false).unwrap();
+        assert_eq!(tranche.coupon_accrued, 0);
+
+        tranche.accrue_coupon(YEAR + YEAR / 2, true).unwrap();
+        assert_eq!(tranche.coupon_accrued, 50_000);
+    }
+
+    #[test]
+    fn coupon_is_paid_up_to_the_shortfall() {
+        let mut tranche = senior_tranche(1_000_000, 1000);
+        tranche.accrue_coupon(YEAR, true).unwrap();
+
+        assert_eq!(tranche.pay_coupon(40_000).unwrap(), 40_000);
+        assert_eq!(tranche.coupon_shortfall, 60_000);
+        assert_eq!(tranche.pay_coupon(1_000_000).unwrap(), 60_000);
+        assert_eq!(tranche.coupon_shortfall, 0);
+        assert_eq!(tranche.coupon_paid, 100_000);
+        assert_eq!(tranche.pay_coupon(1_000_000).unwrap(), 0);
+    }
+
+    #[test]
+    fn first_deposit_is_priced_at_par() {
+        let tranche = TrancheState::for_test(0);
+        assert_eq!(tranche.lp_tokens_for_deposit(1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn inflated_share_price_does_not_round_a_deposit_away() {
+        // One LP token, then a large premium into the tranche before the next deposit.
+        let mut tranche = TrancheState {
+            collateral: 1_000_001,
+            lp_supply: 1,
+            ..TrancheState::for_test(0)
+        };
+        let minted = tranche.lp_tokens_for_deposit(1_000_000).unwrap();
+        assert_eq!(minted, 500_000);
+
+        tranche.credit(1_000_000).unwrap();
+        tranche.record_lp_minted(minted).unwrap();
+        // The depositor can take out all but rounding dust of what they put in.
+        assert_eq!(tranche.collateral_for_lp_tokens(minted).unwrap(), 999_999);
+    }
+
+    #[test]
+    fn deposit_and_redeem_never_returns_more_than_deposited() {
+        let mut tranche = TrancheState {
+            collateral: 7_777_777,
+            lp_supply: 3_333_333,
+            ..TrancheState::for_test(0)
+        };
+        for deposit in [1, 999, 123_456, 10_000_000] {
+            let minted = tranche.lp_tokens_for_deposit(deposit).unwrap();
+            tranche.credit(deposit).unwrap();
+            tranche.record_lp_minted(minted).unwrap();
+            assert!(tranche.collateral_for_lp_tokens(minted).unwrap() <= deposit);
+        }
+    }
+
+    #[test]
+    fn redemption_is_capped_at_the_tranche_collateral() {
+        // The virtual collateral is priced in, but never paid out.
+        let tranche = TrancheState {
+            collateral: 10,
+            lp_supply: 10,
+            ..TrancheState::for_test(0)
+        };
+        assert_eq!(tranche.collateral_for_lp_tokens(1_000_000).unwrap(), 10);
+    }
+
+    #[test]
+    fn deposit_room_is_what_is_left_under_the_cap() {
+        let mut tranche = TrancheState {
+            collateral: 600,
+            ..TrancheState::for_test(0)
+        };
+        assert_eq!(tranche.deposit_room(), u64::MAX - 600);
+        tranche.max_collateral = 1_000;
+        assert_eq!(tranche.deposit_room(), 400);
+        tranche.max_collateral = 500;
+        assert_eq!(tranche.deposit_room(), 0);
+    }
+
+    #[test]
+    fn locked_positions_take_a_premium_slice_by_boost_weight() {
+        let mut tranche = tranche_with_supply(3_000);
+        tranche.add_locked_position(1_000, 1_000).unwrap();
+
+        // 3000 LP plus 1000 boost weight: the boost earns a quarter of the premium.
+        tranche.distribute_premium(400, COMPOUND).unwrap();
+        assert_eq!(tranche.boost_rewards, 100);
+        assert_eq!(tranche.collateral, 300);
+        assert_eq!(
+            tranche.boost_reward_per_weight,
+            constants::REWARD_PRECISION / 10
+        );
+
+        // Without boost weight a tranche keeps its whole premium.
+        let mut unboosted = tranche_with_supply(3_000);
+        unboosted.distribute_premium(400, COMPOUND).unwrap();
+        assert_eq!(unboosted.boost_rewards, 0);
+        assert_eq!(unboosted.collateral, 400);
+    }
+
+    #[test]
+    fn removing_a_locked_position_releases_its_boost() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_locked_position(1_000, 500).unwrap();
+        tranche.distribute_premium(300, COMPOUND).unwrap();
+        assert_eq!(tranche.boost_rewards, 100);
+
+        tranche.remove_locked_position(1_000, 500, 100).unwrap();
+        assert_eq!(tranche.locked_lp, 0);
+        assert_eq!(tranche.boost_weight, 0);
+        assert_eq!(tranche.boost_rewards, 0);
+    }
+
+    #[test]
+    fn compound_mode_grows_the_collateral() {
+        let mut tranche = TrancheState {
+            staked_lp: 1_000,
+            ..tranche_with_supply(2_000)
+        };
+        tranche.distribute_premium(400, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 400);
+        assert_eq!(tranche.unclaimed_premiums, 0);
+        assert_eq!(tranche.premium_per_lp_share, 0);
+    }
+
+    #[test]
+    fn claim_mode_sets_aside_the_staked_share() {
+        let mut tranche = TrancheState {
+            staked_lp: 1_000,
+            ..tranche_with_supply(2_000)
+        };
+
+        // Half the supply is staked: half the premium is claimable, the rest compounds.
+        tranche.distribute_premium(400, CLAIM).unwrap();
+        assert_eq!(tranche.unclaimed_premiums, 200);
+        assert_eq!(tranche.collateral, 200);
+        assert_eq!(
+            tranche.premium_per_lp_share,
+            constants::REWARD_PRECISION / 5
+        );
+
+        tranche.release_claimed_premiums(200).unwrap();
+        assert_eq!(tranche.unclaimed_premiums, 0);
+        assert!(tranche.release_claimed_premiums(1).is_err());
+    }
+
+    #[test]
+    fn claim_mode_without_staked_lp_compounds() {
+        let mut tranche = tranche_with_supply(2_000);
+        tranche.distribute_premium(400, CLAIM).unwrap();
+        assert_eq!(tranche.collateral, 400);
+        assert_eq!(tranche.unclaimed_premiums, 0);
+    }
+
+    #[test]
+    fn staked_lp_cannot_go_negative() {
+        let mut tranche = TrancheState::for_test(1);
+        tranche.record_lp_staked(500).unwrap();
+        tranche.record_lp_unstaked(200).unwrap();
+        assert_eq!(tranche.staked_lp, 300);
+        assert!(tranche.record_lp_unstaked(301).is_err());
+    }
+
+    #[test]
+    fn premiums_are_released_evenly_over_the_term() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        assert_eq!(tranche.unearned_premium, 1_000);
+
+        tranche.release_premiums(25, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 250);
+        assert_eq!(tranche.unearned_premium, 750);
+
+        // Releasing never hands out more than was paid in.
+        tranche.release_premiums(500, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 1_000);
+        assert_eq!(tranche.unearned_premium, 0);
+    }
+
+    #[test]
+    fn release_does_nothing_until_time_moves_forward() {
+        let mut tranche = TrancheState {
+            last_premium_release_timestamp: 50,
+            ..tranche_with_supply(1_000)
+        };
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.release_premiums(40, COMPOUND).unwrap();
+        tranche.release_premiums(50, COMPOUND).unwrap();
+        assert_eq!(tranche.unearned_premium, 1_000);
+        assert_eq!(tranche.last_premium_release_timestamp, 50);
+    }
+
+    #[test]
+    fn removing_a_policy_stops_its_release() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.add_unearned_premium(400, 200).unwrap();
+        tranche.release_premiums(100, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 1_200);
+
+        tranche.settle_policy_premium(1_000, 100, 100).unwrap();
+        tranche.release_premiums(200, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 1_400);
+        assert_eq!(tranche.unearned_premium, 0);
+    }
+
+    #[test]
+    fn late_settlement_takes_back_the_release_past_expiry() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.add_unearned_premium(2_000, 200).unwrap();
+        // The first policy expired at 100 but kept releasing until it was settled at 150.
+        tranche.release_premiums(150, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 3_000);
+
+        tranche.settle_policy_premium(1_000, 100, 100).unwrap();
+        assert_eq!(tranche.collateral, 2_500);
+        assert_eq!(tranche.unearned_premium, 500);
+        assert_eq!(
+            tranche.premium_release_rate,
+            10 * constants::REWARD_PRECISION
+        );
+
+        // The second policy's premium is released in full by its own expiry.
+        tranche.release_premiums(200, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 3_000);
+        assert_eq!(tranche.unearned_premium, 0);
+    }
+
+    #[test]
+    fn cancellation_refunds_the_unexpired_part_of_the_premium() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.release_premiums(40, COMPOUND).unwrap();
+        assert_eq!(tranche.collateral, 400);
+
+        let refund = tranche.cancel_policy_premium(1_000, 100, 60).unwrap();
+        assert_eq!(refund, 600);
+        assert_eq!(tranche.unearned_premium, 0);
+        assert_eq!(tranche.premium_release_rate, 0);
+        // LPs keep what was earned while the policy was covered.
+        assert_eq!(tranche.collateral, 400);
+    }
+
+    #[test]
+    fn cancellation_takes_a_reserve_shortfall_from_the_tranche() {
+        let mut tranche = TrancheState {
+            collateral: 1_000,
+            ..tranche_with_supply(1_000)
+        };
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.unearned_premium = 500;
+
+        let refund = tranche.cancel_policy_premium(1_000, 100, 80).unwrap();
+        assert_eq!(refund, 800);
+        assert_eq!(tranche.unearned_premium, 0);
+        assert_eq!(tranche.collateral, 700);
+    }
+
+    #[test]
+    fn dust_is_released_once_no_policy_is_releasing() {
+        let mut tranche = tranche_with_supply(1_000);
+        tranche.add_unearned_premium(1_000, 3).unwrap();
+        tranche.release_premiums(3, COMPOUND).unwrap();
+        assert_eq!(tranche.unearned_premium, 1);
+
+        tranche.settle_policy_premium(1_000, 3, 3).unwrap();
+        tranche.release_premiums(4, COMPOUND).unwrap();
+        assert_eq!(tranche.unearned_premium, 0);
+        assert_eq!(tranche.collateral, 1_000);
+    }
+
+    #[test]
+    fn released_premiums_are_claimable_by_staked_lp_in_claim_mode() {
+        let mut tranche = TrancheState {
+            staked_lp: 500,
+            ..tranche_with_supply(1_000)
+        };
+        tranche.add_unearned_premium(1_000, 100).unwrap();
+        tranche.release_premiums(100, CLAIM).unwrap();
+
+        assert_eq!(tranche.unclaimed_premiums, 500);
+        assert_eq!(tranche.collateral, 500);
+
+        // Premiums forfeited by warming LP go back to the tranche.
+        tranche.forfeit_premiums(200).unwrap();
+        assert_eq!(tranche.unclaimed_premiums, 300);
+        assert_eq!(tranche.collateral, 700);
+    }
 }
@@ -0,0 +1,581 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::StableGuardError, InsurancePool, PremiumSplitMode, TrancheState};
+
+/// Every tranche of a pool, most junior first, loaded from an instruction's remaining
+/// accounts. Used wherever a decision depends on the whole capital stack: the loss
+/// waterfall, subordination, underwriting capacity and the premium split.
+pub struct TrancheSet<'info> {
+    tranches: Vec<Account<'info, TrancheState>>,
+    /// Collateral vault of each tranche, only loaded by `load_with_vaults`.
+    vaults: Vec<AccountInfo<'info>>,
+}
+
+impl<'info> TrancheSet<'info> {
+    /// Loads `pool.tranche_count` tranche accounts, in seniority order. Instructions that
+    /// check the whole capital stack take every tranche of the pool in `remaining_accounts`,
+    /// most junior first.
+    pub fn load(
+        pool: &Account<'info, InsurancePool>,
+        accounts: &'info [AccountInfo<'info>],
+    ) -> Result<Self> {
+        require!(
+            accounts.len() == pool.tranche_count as usize,
+            StableGuardError::InvalidTrancheAccounts
+        );
+        let tranches = accounts
+            .iter()
+            .enumerate()
+            .map(|(index, info)| Self::load_tranche(pool, index, info))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            tranches,
+            vaults: Vec::new(),
+        })
+    }
+
+    /// Loads each tranche followed by its collateral vault, in seniority order.
+    pub fn load_with_vaults(
+        pool: &Account<'info, InsurancePool>,
+        accounts: &'info [AccountInfo<'info>],
+    ) -> Result<Self> {
+        require!(
+            accounts.len() == 2 * pool.tranche_count as usize,
+            StableGuardError::InvalidTrancheAccounts
+        );
+        let mut tranches = Vec::with_capacity(pool.tranche_count as usize);
+        let mut vaults = Vec::with_capacity(pool.tranche_count as usize);
+        for (index, pair) in accounts.chunks(2).enumerate() {
+            let tranche = Self::load_tranche(pool, index, &pair[0])?;
+            require_keys_eq!(
+                pair[1].key(),
+                tranche.collateral_vault,
+                StableGuardError::InvalidTrancheAccounts
+            );
+            tranches.push(tranche);
+            vaults.push(pair[1].clone());
+        }
+        Ok(Self { tranches, vaults })
+    }
+
+    fn load_tranche(
+        pool: &Account<'info, InsurancePool>,
+        index: usize,
+        info: &'info AccountInfo<'info>,
+    ) -> Result<Account<'info, TrancheState>> {
+        let tranche = Account::<TrancheState>::try_from(info)?;
+        require!(
+            tranche.insurance_pool == pool.key() && tranche.index as usize == index,
+            StableGuardError::InvalidTrancheAccounts
+        );
+        Ok(tranche)
+    }
+
+    /// Writes back tranches changed through `get_mut`.
+    pub fn exit(&self) -> Result<()> {
+        for tranche in &self.tranches {
+            tranche.exit(&crate::ID)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the loaded copy of a tranche with one the instruction has already changed
+    /// through its own account.
+    pub fn sync(&mut self, tranche: &TrancheState) {
+        self.tranches[tranche.index as usize].set_inner(tranche.clone());
+    }
+
+    pub fn len(&self) -> usize {
+        self.tranches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tranches.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &TrancheState {
+        &self.tranches[index]
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut TrancheState {
+        &mut self.tranches[index]
+    }
+
+    pub fn vault(&self, index: usize) -> &AccountInfo<'info> {
+        &self.vaults[index]
+    }
+
+    pub fn total_collateral(&self) -> Result<u64> {
+        self.collateral_below(self.tranches.len())
+    }
+
+    /// Collateral in the tranches junior to `index`.
+    pub fn collateral_below(&self, index: usize) -> Result<u64> {
+        self.tranches[..index]
+            .iter()
+            .try_fold(0u64, |total, tranche| {
+                total
+                    .checked_add(tranche.collateral)
+                    .ok_or(StableGuardError::CalculationError.into())
+            })
+    }
+
+    /// The part of `total_liability` a tranche is on the hook for. Losses hit the junior
+    /// tranches first, so each tranche carries liability up to its own collateral once the
+    /// tranches below it are used up, and the most senior tranche carries whatever is left.
+    pub fn liability(&self, index: usize, total_liability: u64) -> Result<u64> {
+        let remaining = total_liability.saturating_sub(self.collateral_below(index)?);
+        if index + 1 == self.tranches.len() {
+            Ok(remaining)
+        } else {
+            Ok(remaining.min(self.tranches[index].collateral))
+        }
+    }
+
+    /// Whether the tranches below `index` hold at least its attachment point of the pool's
+    /// collateral. An empty pool counts as subordinated.
+    pub fn is_subordinated(&self, index: usize) -> Result<bool> {
+        let below_bps = (self.collateral_below(index)? as u128)
+            .checked_mul(10000)
+            .ok_or(StableGuardError::CalculationError)?;
+        let required_bps = (self.total_collateral()? as u128)
+            .checked_mul(self.tranches[index].attachment_point_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(below_bps >= required_bps)
+    }
+
+    /// Requires attachment points to rise, or stay level, with seniority. A senior tranche
+    /// attaching below a junior one could pass its own check while the junior one fails.
+    pub fn require_attachment_order(&self) -> Result<()> {
+        require!(
+            self.tranches
+                .windows(2)
+                .all(|pair| pair[0].attachment_point_bps <= pair[1].attachment_point_bps),
+            StableGuardError::InvalidPoolConfig
+        );
+        Ok(())
+    }
+
+    /// Requires every tranche in `indices` to meet its attachment point.
+    pub fn require_subordinated(&self, indices: std::ops::Range<usize>) -> Result<()> {
+        for index in indices {
+            require!(
+                self.is_subordinated(index)?,
+                StableGuardError::JuniorRatioBelowMinimum
+            );
+        }
+        Ok(())
+    }
+
+    /// How much more collateral a tranche accepts before it or the pool hits its cap.
+    pub fn deposit_capacity(&self, index: usize, max_pool_collateral: u64) -> Result<u64> {
+        let pool_room = max_pool_collateral.saturating_sub(self.total_collateral()?);
+        Ok(self.tranches[index].deposit_room().min(pool_room))
+    }
+
+    /// Collateral counted towards underwriting new policies. Locked capital is firm backing
+    /// and counts in full; unlocked capital is discounted by `unlocked_capital_weight_bps`
+    /// since it can be withdrawn after a cooldown.
+    pub fn underwriting_capacity(&self, unlocked_capital_weight_bps: u64) -> Result<u64> {
+        let locked_collateral =
+            self.tranches
+                .iter()
+                .try_fold(0u64, |total, tranche| -> Result<u64> {
+                    total
+                        .checked_add(tranche.collateral_for_lp_tokens(tranche.locked_lp)?)
+                        .ok_or(StableGuardError::CalculationError.into())
+                })?;
+        let total_collateral = self.total_collateral()?;
+        let unlocked_collateral = total_collateral.saturating_sub(locked_collateral);
+
+        let weighted_unlocked = (unlocked_collateral as u128)
+            .checked_mul(unlocked_capital_weight_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000)
+            .ok_or(StableGuardError::CalculationError)? as u64;
+
+        Ok(total_collateral.min(
+            locked_collateral
+                .checked_add(weighted_unlocked)
+                .ok_or(StableGuardError::CalculationError)?,
+        ))
+    }
+
+    /// Splits a premium across the tranches according to the pool's `premium_split_mode`.
+    /// Returns each tranche's part, most junior first.
+    pub fn split_premium(
+        &mut self,
+        pool: &InsurancePool,
+        premium: u64,
+        now: i64,
+    ) -> Result<Vec<u64>> {
+        match pool.premium_split_mode {
+            PremiumSplitMode::SeniorCoupon => self.split_premium_senior_coupon(premium, now),
+            PremiumSplitMode::Dynamic => self.split_premium_dynamic(pool, premium),
+        }
+    }
+
+    /// Each tranche above the first-loss one is paid its outstanding coupon, most senior
+    /// first, and the first-loss tranche takes whatever is left.
+    fn split_premium_senior_coupon(&mut self, premium: u64, now: i64) -> Result<Vec<u64>> {
+        let mut parts = vec![0; self.tranches.len()];
+        let mut remaining = premium;
+        for (index, tranche) in self.tranches.iter_mut().enumerate().skip(1).rev() {
+            tranche.accrue_coupon(now, true)?;
+            parts[index] = tranche.pay_coupon(remaining)?;
+            remaining -= parts[index];
+        }
+        if let Some(first_loss) = parts.first_mut() {
+            *first_loss = remaining;
+        }
+        Ok(parts)
+    }
+
+    /// Each tranche is paid for the share of the pool's exposure it carries, scaled by its
+    /// premium weight. The first-loss tranche's share is clamped to the authority's bounds
+    /// and the tranches above it split the rest the same way.
+    fn split_premium_dynamic(&self, pool: &InsurancePool, premium: u64) -> Result<Vec<u64>> {
+        let total_liability = pool.total_liability()?;
+        let mut weights = Vec::with_capacity(self.tranches.len());
+        for (index, tranche) in self.tranches.iter().enumerate() {
+            weights.push(
+                (self.liability(index, total_liability)? as u128)
+                    .checked_mul(tranche.premium_weight_bps as u128)
+                    .ok_or(StableGuardError::CalculationError)?,
+            );
+        }
+        // With no exposure yet, fall back to the premium weights alone.
+        if weights.iter().all(|weight| *weight == 0) {
+            weights = self
+                .tranches
+                .iter()
+                .map(|tranche| tranche.premium_weight_bps as u128)
+                .collect();
+        }
+        let total_weight: u128 = weights.iter().sum();
+
+        let mut parts = vec![0; self.tranches.len()];
+        if parts.is_empty() {
+            return Ok(parts);
+        }
+        let first_loss_share_bps = match weights[0]
+            .checked_mul(10000)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(total_weight)
+        {
+            Some(share_bps) => share_bps as u64,
+            None => pool.max_junior_premium_share_bps,
+        }
+        .clamp(
+            pool.min_junior_premium_share_bps,
+            pool.max_junior_premium_share_bps,
+        );
+        let first_loss_premium = (premium as u128)
+            .checked_mul(first_loss_share_bps as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(10000)
+            .ok_or(StableGuardError::CalculationError)? as u64;
+
+        let senior_premium = premium - first_loss_premium;
+        let senior_weight = total_weight - weights[0];
+        let mut senior_paid = 0u64;
+        for index in 1..parts.len() {
+            parts[index] = if senior_weight == 0 {
+                // Nothing to weigh by, so the most senior tranche takes it all.
+                if index + 1 == parts.len() {
+                    senior_premium
+                } else {
+                    0
+                }
+            } else {
+                (senior_premium as u128)
+                    .checked_mul(weights[index])
+                    .ok_or(StableGuardError::CalculationError)?
+                    .checked_div(senior_weight)
+                    .ok_or(StableGuardError::CalculationError)? as u64
+            };
+            senior_paid += parts[index];
+        }
+        // Rounding dust, and the whole premium in a single-tranche pool, goes to first loss.
+        parts[0] = premium - senior_paid;
+        Ok(parts)
+    }
+}
+
+#[cfg(test)]
+impl TrancheSet<'static> {
+    /// A set of `tranches` backed by in-memory accounts, for tests.
+    pub(crate) fn for_test(tranches: Vec<TrancheState>) -> Self {
+        let tranches = tranches
+            .into_iter()
+            .map(|tranche| {
+                let mut data = Vec::new();
+                tranche.try_serialize(&mut data).unwrap();
+                let info = Box::leak(Box::new(AccountInfo::new(
+                    Box::leak(Box::new(Pubkey::new_unique())),
+                    false,
+                    true,
+                    Box::leak(Box::new(0)),
+                    Box::leak(data.into_boxed_slice()),
+                    &crate::ID,
+                    false,
+                    0,
+                )));
+                Account::try_from(&*info).unwrap()
+            })
+            .collect();
+        Self {
+            tranches,
+            vaults: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YEAR: i64 = crate::constants::SECONDS_PER_YEAR as i64;
+
+    fn tranche(index: u8, collateral: u64, premium_weight_bps: u64) -> TrancheState {
+        TrancheState {
+            collateral,
+            premium_weight_bps,
+            ..TrancheState::for_test(index)
+        }
+    }
+
+    fn dynamic_pool(liability: u64, min_share_bps: u64, max_share_bps: u64) -> InsurancePool {
+        let mut pool = InsurancePool {
+            premium_split_mode: PremiumSplitMode::Dynamic,
+            min_junior_premium_share_bps: min_share_bps,
+            max_junior_premium_share_bps: max_share_bps,
+            ..InsurancePool::for_test()
+        };
+        pool.liability_schedule.add(0, liability).unwrap();
+        pool
+    }
+
+    fn split(tranches: Vec<TrancheState>, pool: &InsurancePool, premium: u64) -> Vec<u64> {
+        TrancheSet::for_test(tranches)
+            .split_premium(pool, premium, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn premium_pays_senior_coupons_before_first_loss() {
+        let pool = InsurancePool::for_test();
+        let mut tranches = TrancheSet::for_test(vec![
+            tranche(0, 1_000_000, 0),
+            TrancheState {
+                target_apr_bps: 1000,
+                ..tranche(1, 1_000_000, 0)
+            },
+            TrancheState {
+                target_apr_bps: 500,
+                ..tranche(2, 1_000_000, 0)
+            },
+        ]);
+
+        // The most senior tranche's 50_000 coupon is paid first, then the mezzanine's.
+        assert_eq!(
+            tranches.split_premium(&pool, 80_000, YEAR).unwrap(),
+            vec![0, 30_000, 50_000]
+        );
+        assert_eq!(tranches.get(1).coupon_shortfall, 70_000);
+        assert_eq!(
+            tranches.split_premium(&pool, 100_000, YEAR).unwrap(),
+            vec![30_000, 70_000, 0]
+        );
+        assert_eq!(tranches.get(1).coupon_paid, 100_000);
+    }
+
+    #[test]
+    fn dynamic_split_follows_liability_times_weight() {
+        // Junior carries 1000 at weight 2x, senior 1000 at 1x: junior earns two thirds,
+        // rounded down to a whole BPS.
+        let pool = dynamic_pool(2000, 0, 10000);
+        let parts = split(
+            vec![tranche(0, 1000, 20000), tranche(1, 5000, 10000)],
+            &pool,
+            9000,
+        );
+        assert_eq!(parts, vec![5999, 3001]);
+    }
+
+    #[test]
+    fn dynamic_split_clamps_first_loss_share_to_its_maximum() {
+        // Only the junior tranche carries liability, but it may take at most 80%.
+        let pool = dynamic_pool(500, 3000, 8000);
+        let parts = split(
+            vec![tranche(0, 1000, 10000), tranche(1, 10000, 10000)],
+            &pool,
+            10000,
+        );
+        assert_eq!(parts, vec![8000, 2000]);
+    }
+
+    #[test]
+    fn dynamic_split_clamps_first_loss_share_to_its_minimum() {
+        // Junior carries a fifth of the liability but is owed at least 30%.
+        let pool = dynamic_pool(5000, 3000, 8000);
+        let parts = split(
+            vec![tranche(0, 1000, 10000), tranche(1, 10000, 10000)],
+            &pool,
+            10000,
+        );
+        assert_eq!(parts, vec![3000, 7000]);
+    }
+
+    #[test]
+    fn dynamic_split_falls_back_to_weights_without_exposure() {
+        let pool = dynamic_pool(0, 0, 10000);
+        let parts = split(
+            vec![tranche(0, 1000, 30000), tranche(1, 1000, 10000)],
+            &pool,
+            1000,
+        );
+        assert_eq!(parts, vec![750, 250]);
+    }
+
+    #[test]
+    fn dynamic_split_shares_senior_premium_by_weight_and_gives_dust_to_first_loss() {
+        // Junior and mezzanine each carry 1000, senior 1000; the seniors split their 70%
+        // 1:2 by weight and the rounding dust goes to first loss.
+        let pool = dynamic_pool(3000, 3000, 3000);
+        let parts = split(
+            vec![
+                tranche(0, 1000, 10000),
+                tranche(1, 1000, 10000),
+                tranche(2, 1000, 20000),
+            ],
+            &pool,
+            1000,
+        );
+        assert_eq!(parts, vec![301, 233, 466]);
+        assert_eq!(parts.iter().sum::<u64>(), 1000);
+    }
+
+    #[test]
+    fn dynamic_split_gives_a_single_tranche_everything() {
+        let pool = dynamic_pool(1000, 3000, 8000);
+        let parts = split(vec![tranche(0, 1000, 10000)], &pool, 1000);
+        assert_eq!(parts, vec![1000]);
+    }
+
+    fn stack() -> TrancheSet<'static> {
+        TrancheSet::for_test(vec![
+            tranche(0, 1000, 0),
+            tranche(1, 2000, 0),
+            tranche(2, 3000, 0),
+        ])
+    }
+
+    #[test]
+    fn losses_hit_the_most_junior_tranche_first() {
+        let tranches = stack();
+        let liabilities = |total| {
+            (0..3)
+                .map(|index| tranches.liability(index, total).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(liabilities(0), vec![0, 0, 0]);
+        assert_eq!(liabilities(600), vec![600, 0, 0]);
+        assert_eq!(liabilities(1000), vec![1000, 0, 0]);
+        assert_eq!(liabilities(2500), vec![1000, 1500, 0]);
+        assert_eq!(liabilities(4000), vec![1000, 2000, 1000]);
+    }
+
+    #[test]
+    fn the_most_senior_tranche_carries_liability_beyond_the_pool() {
+        let tranches = stack();
+        assert_eq!(tranches.liability(1, 9000).unwrap(), 2000);
+        assert_eq!(tranches.liability(2, 9000).unwrap(), 6000);
+    }
+
+    #[test]
+    fn an_empty_pool_is_subordinated() {
+        let tranches = TrancheSet::for_test(vec![
+            tranche(0, 0, 0),
+            TrancheState {
+                attachment_point_bps: 2000,
+                ..tranche(1, 0, 0)
+            },
+        ]);
+        tranches.require_subordinated(0..2).unwrap();
+    }
+
+    #[test]
+    fn subordination_is_measured_against_the_tranches_below() {
+        let mut tranches = TrancheSet::for_test(vec![
+            tranche(0, 1000, 0),
+            TrancheState {
+                attachment_point_bps: 2500,
+                ..tranche(1, 3000, 0)
+            },
+        ]);
+        // Exactly at the attachment point.
+        assert!(tranches.is_subordinated(0).unwrap());
+        assert!(tranches.is_subordinated(1).unwrap());
+
+        tranches.get_mut(1).credit(1).unwrap();
+        assert!(!tranches.is_subordinated(1).unwrap());
+        assert!(tranches.require_subordinated(0..2).is_err());
+    }
+
+    #[test]
+    fn attachment_points_must_not_fall_with_seniority() {
+        let attached = |points: [u64; 3]| {
+            TrancheSet::for_test(
+                points
+                    .iter()
+                    .enumerate()
+                    .map(|(index, bps)| TrancheState {
+                        attachment_point_bps: *bps,
+                        ..tranche(index as u8, 1000, 0)
+                    })
+                    .collect(),
+            )
+        };
+        assert!(attached([0, 1000, 1000]).require_attachment_order().is_ok());
+        assert!(attached([0, 2000, 1000])
+            .require_attachment_order()
+            .is_err());
+    }
+
+    #[test]
+    fn deposit_capacity_is_the_tighter_of_the_tranche_and_pool_caps() {
+        let mut tranches = TrancheSet::for_test(vec![
+            TrancheState {
+                max_collateral: 1_000,
+                ..tranche(0, 600, 0)
+            },
+            tranche(1, 300, 0),
+        ]);
+        assert_eq!(tranches.deposit_capacity(0, 1_200).unwrap(), 300);
+        assert_eq!(tranches.deposit_capacity(1, 1_200).unwrap(), 300);
+
+        tranches.get_mut(1).max_collateral = 200;
+        assert_eq!(tranches.deposit_capacity(1, 1_200).unwrap(), 0);
+        assert_eq!(tranches.deposit_capacity(0, u64::MAX).unwrap(), 400);
+    }
+
+    #[test]
+    fn locked_capital_counts_in_full_towards_capacity() {
+        let mut tranches = TrancheSet::for_test(vec![
+            TrancheState {
+                lp_supply: 1_000_000,
+                ..tranche(0, 1_000_000, 0)
+            },
+            TrancheState {
+                lp_supply: 1_000_000,
+                ..tranche(1, 1_000_000, 0)
+            },
+        ]);
+        assert_eq!(tranches.underwriting_capacity(8000).unwrap(), 1_600_000);
+
+        // Lock half the junior LP: its 500_000 of collateral now counts in full.
+        tranches.get_mut(0).add_locked_position(500_000, 0).unwrap();
+        assert_eq!(tranches.underwriting_capacity(8000).unwrap(), 1_700_000);
+    }
+}
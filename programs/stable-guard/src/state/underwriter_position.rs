@@ -0,0 +1,482 @@
+use anchor_lang::prelude::*;
+
+use crate::{constants, error::StableGuardError};
+
+/// An underwriter's book in one tranche of a pool: what they put in, what they took out and
+/// the average share price they bought LP tokens at. Only LP minted and burned through the
+/// program is tracked here; LP tokens transferred in from another wallet carry no cost basis.
+#[account]
+#[derive(InitSpace)]
+pub struct UnderwriterPosition {
+    pub owner: Pubkey,
+    pub insurance_pool: Pubkey,
+    pub tranche_index: u8,
+    /// LP tokens minted to this underwriter and not yet burned, including staked and locked
+    /// LP.
+    pub lp_balance: u64,
+    /// Lifetime collateral deposited.
+    pub deposited_principal: u64,
+    /// Lifetime collateral withdrawn.
+    pub withdrawn_amount: u64,
+    /// Average collateral paid per LP token, scaled by `SHARE_PRICE_PRECISION`.
+    pub avg_entry_share_price: u128,
+    /// Profit or loss locked in by withdrawals, against the average entry price.
+    pub realized_pnl: i64,
+    /// LP tokens held for this position in the tranche's staked vault or in locked
+    /// positions. Claimable premiums accrue on these only.
+    pub staked_lp: u64,
+    /// Part of `staked_lp` held in locked positions.
+    pub locked_lp: u64,
+    /// `staked_lp * premium_per_lp_share` at the last settlement, scaled by
+    /// `REWARD_PRECISION`.
+    pub premium_debt: u128,
+    pub last_premium_settlement_timestamp: i64,
+    /// Claimable premiums settled to this position and not yet claimed.
+    pub premiums_owed: u64,
+    /// Lifetime premiums claimed.
+    pub premiums_claimed: u64,
+    /// Staked LP tokens still in their warm-up period. They don't earn premiums and can't be
+    /// unstaked until `warmup_end_timestamp`; a new deposit or stake restarts the warm-up for
+    /// all of them.
+    pub warmup_lp: u64,
+    pub warmup_end_timestamp: i64,
+    pub bump: u8,
+}
+
+impl UnderwriterPosition {
+    /// An empty position. Positions opened to withdraw LP tokens that arrived by transfer
+    /// stay at a zero cost basis, so the whole withdrawal is booked as realized profit.
+    pub fn new(owner: Pubkey, insurance_pool: Pubkey, tranche_index: u8, bump: u8) -> Self {
+        Self {
+            owner,
+            insurance_pool,
+            tranche_index,
+            lp_balance: 0,
+            deposited_principal: 0,
+            withdrawn_amount: 0,
+            avg_entry_share_price: 0,
+            realized_pnl: 0,
+            staked_lp: 0,
+            locked_lp: 0,
+            premium_debt: 0,
+            last_premium_settlement_timestamp: 0,
+            premiums_owed: 0,
+            premiums_claimed: 0,
+            warmup_lp: 0,
+            warmup_end_timestamp: 0,
+            bump,
+        }
+    }
+
+    /// Cost basis of `lp_amount` tracked LP tokens at the average entry price.
+    pub fn cost_basis(&self, lp_amount: u64) -> Result<u64> {
+        let cost = (lp_amount as u128)
+            .checked_mul(self.avg_entry_share_price)
+            .ok_or(StableGuardError::CalculationError)?
+            / constants::SHARE_PRICE_PRECISION;
+        Ok(u64::try_from(cost).map_err(|_| StableGuardError::CalculationError)?)
+    }
+
+    pub fn is_warming_up(&self, now: i64) -> bool {
+        self.warmup_lp > 0 && now < self.warmup_end_timestamp
+    }
+
+    /// Staked LP tokens that may leave the vault: neither locked nor still warming up. While
+    /// locked LP tokens are warming up as well this holds back more than strictly needed,
+    /// until the warm-up ends.
+    pub fn unstakeable_lp(&self, now: i64) -> u64 {
+        let warming_lp = if self.is_warming_up(now) {
+            self.warmup_lp
+        } else {
+            0
+        };
+        self.staked_lp
+            .saturating_sub(self.locked_lp)
+            .saturating_sub(warming_lp)
+    }
+
+    /// Moves the premiums earned since the last settlement into `premiums_owed`. Must run
+    /// before `staked_lp` changes. The share earned by warming LP tokens is not credited and
+    /// is returned so the caller can hand it back to the tranche.
+    pub fn settle_premiums(&mut self, premium_per_lp_share: u128, now: i64) -> Result<u64> {
+        let accumulated = (self.staked_lp as u128)
+            .checked_mul(premium_per_lp_share)
+            .ok_or(StableGuardError::CalculationError)?;
+        let pending = accumulated.saturating_sub(self.premium_debt) / constants::REWARD_PRECISION;
+        let pending = u64::try_from(pending).map_err(|_| StableGuardError::CalculationError)?;
+        let forfeited = self.warming_share(pending, now)?;
+
+        self.premiums_owed = self
+            .premiums_owed
+            .checked_add(pending - forfeited)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.premium_debt = accumulated;
+        self.last_premium_settlement_timestamp = now;
+        Ok(forfeited)
+    }
+
+    /// The part of `pending`, earned since the last settlement, that fell to warming LP
+    /// tokens. Premiums are released evenly over time, so a warm-up that ended since the
+    /// last settlement forfeits only the time it was still running.
+    fn warming_share(&self, pending: u64, now: i64) -> Result<u64> {
+        let since = self.last_premium_settlement_timestamp;
+        let warming_seconds = now.min(self.warmup_end_timestamp).saturating_sub(since);
+        if pending == 0 || self.warmup_lp == 0 || warming_seconds <= 0 {
+            return Ok(0);
+        }
+        Ok((pending as u128)
+            .checked_mul(self.warmup_lp as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_mul(warming_seconds as u128)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_div(self.staked_lp as u128 * now.saturating_sub(since) as u128)
+            .ok_or(StableGuardError::CalculationError)? as u64)
+    }
+
+    /// Puts `lp_amount` newly staked LP tokens into warm-up, restarting it for any still
+    /// warming.
+    fn start_warmup(&mut self, lp_amount: u64, now: i64, warmup_seconds: i64) -> Result<()> {
+        if warmup_seconds <= 0 {
+            return Ok(());
+        }
+        if !self.is_warming_up(now) {
+            self.warmup_lp = 0;
+        }
+        self.warmup_lp = self
+            .warmup_lp
+            .checked_add(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.warmup_end_timestamp = now
+            .checked_add(warmup_seconds)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    fn sync_premium_debt(&mut self, premium_per_lp_share: u128) -> Result<()> {
+        self.premium_debt = (self.staked_lp as u128)
+            .checked_mul(premium_per_lp_share)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Records LP tokens minted for a deposit. They are staked, or locked if `locked`.
+    pub fn record_deposit(
+        &mut self,
+        deposit_amount: u64,
+        lp_minted: u64,
+        locked: bool,
+        premium_per_lp_share: u128,
+        now: i64,
+        warmup_seconds: i64,
+    ) -> Result<u64> {
+        let forfeited = self.settle_premiums(premium_per_lp_share, now)?;
+        self.start_warmup(lp_minted, now, warmup_seconds)?;
+
+        let new_lp_balance = self
+            .lp_balance
+            .checked_add(lp_minted)
+            .ok_or(StableGuardError::CalculationError)?;
+        let total_cost = (self.lp_balance as u128)
+            .checked_mul(self.avg_entry_share_price)
+            .ok_or(StableGuardError::CalculationError)?
+            .checked_add(
+                (deposit_amount as u128)
+                    .checked_mul(constants::SHARE_PRICE_PRECISION)
+                    .ok_or(StableGuardError::CalculationError)?,
+            )
+            .ok_or(StableGuardError::CalculationError)?;
+
+        self.avg_entry_share_price = total_cost
+            .checked_div(new_lp_balance as u128)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.lp_balance = new_lp_balance;
+        self.deposited_principal = self
+            .deposited_principal
+            .checked_add(deposit_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.staked_lp = self
+            .staked_lp
+            .checked_add(lp_minted)
+            .ok_or(StableGuardError::CalculationError)?;
+        if locked {
+            self.locked_lp = self
+                .locked_lp
+                .checked_add(lp_minted)
+                .ok_or(StableGuardError::CalculationError)?;
+        }
+        self.sync_premium_debt(premium_per_lp_share)?;
+        Ok(forfeited)
+    }
+
+    /// Records LP tokens moved from the owner's wallet into the staked vault. They warm up
+    /// like freshly minted LP tokens, since the warm-up of whoever held them before stays
+    /// with that holder's position.
+    pub fn stake(
+        &mut self,
+        lp_amount: u64,
+        premium_per_lp_share: u128,
+        now: i64,
+        warmup_seconds: i64,
+    ) -> Result<u64> {
+        let forfeited = self.settle_premiums(premium_per_lp_share, now)?;
+        self.start_warmup(lp_amount, now, warmup_seconds)?;
+        self.staked_lp = self
+            .staked_lp
+            .checked_add(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.sync_premium_debt(premium_per_lp_share)?;
+        Ok(forfeited)
+    }
+
+    /// Records LP tokens moved from the staked vault back to the owner's wallet, where they
+    /// stop earning claimable premiums.
+    pub fn unstake(&mut self, lp_amount: u64, premium_per_lp_share: u128, now: i64) -> Result<u64> {
+        require!(
+            lp_amount <= self.unstakeable_lp(now),
+            StableGuardError::InsufficientStakedLp
+        );
+        let forfeited = self.settle_premiums(premium_per_lp_share, now)?;
+        self.staked_lp -= lp_amount;
+        self.sync_premium_debt(premium_per_lp_share)?;
+        Ok(forfeited)
+    }
+
+    /// Records a locked position's LP tokens moving into the staked vault on unlock. They
+    /// stay staked, so nothing needs settling.
+    pub fn record_unlock(&mut self, lp_amount: u64) -> Result<()> {
+        self.locked_lp = self
+            .locked_lp
+            .checked_sub(lp_amount)
+            .ok_or(StableGuardError::CalculationError)?;
+        Ok(())
+    }
+
+    /// Records LP tokens burned by a withdrawal. They were unstaked before they were
+    /// escrowed, so premiums are unaffected.
+    pub fn record_withdrawal(&mut self, lp_burned: u64, collateral_out: u64) -> Result<()> {
+        let tracked_lp = lp_burned.min(self.lp_balance);
+        let cost_basis = self.cost_basis(tracked_lp)?;
+
+        self.realized_pnl = self
+            .realized_pnl
+            .checked_add(collateral_out as i64 - cost_basis as i64)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.withdrawn_amount = self
+            .withdrawn_amount
+            .checked_add(collateral_out)
+            .ok_or(StableGuardError::CalculationError)?;
+        self.lp_balance -= tracked_lp;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WARMUP: i64 = 100;
+
+    fn position() -> UnderwriterPosition {
+        UnderwriterPosition::new(Pubkey::default(), Pubkey::default(), 0, 0)
+    }
+
+    fn per_share(premium_per_lp: u128) -> u128 {
+        premium_per_lp * constants::REWARD_PRECISION
+    }
+
+    fn position_with_deposit(lp_amount: u64, now: i64) -> UnderwriterPosition {
+        let mut position = position();
+        position
+            .record_deposit(lp_amount, lp_amount, false, 0, now, WARMUP)
+            .unwrap();
+        position
+    }
+
+    #[test]
+    fn deposits_average_the_entry_price() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        // Second deposit buys at 2.0 per LP token.
+        position
+            .record_deposit(2_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+
+        assert_eq!(position.lp_balance, 2_000);
+        assert_eq!(position.deposited_principal, 3_000);
+        assert_eq!(
+            position.avg_entry_share_price,
+            constants::SHARE_PRICE_PRECISION * 3 / 2
+        );
+        assert_eq!(position.cost_basis(1_000).unwrap(), 1_500);
+    }
+
+    #[test]
+    fn withdrawal_realizes_pnl_against_cost_basis() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        position.record_withdrawal(400, 500).unwrap();
+
+        assert_eq!(position.lp_balance, 600);
+        assert_eq!(position.withdrawn_amount, 500);
+        assert_eq!(position.realized_pnl, 100);
+
+        position.record_withdrawal(600, 450).unwrap();
+        assert_eq!(position.lp_balance, 0);
+        assert_eq!(position.realized_pnl, -50);
+    }
+
+    #[test]
+    fn transferred_lp_is_realized_as_profit() {
+        // A position opened on withdrawal has no cost basis.
+        let mut position = position();
+        position.record_withdrawal(1_000, 1_000).unwrap();
+
+        assert_eq!(position.lp_balance, 0);
+        assert_eq!(position.realized_pnl, 1_000);
+    }
+
+    #[test]
+    fn untracked_lp_beyond_the_balance_has_no_cost_basis() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        position.record_withdrawal(1_500, 1_500).unwrap();
+
+        assert_eq!(position.lp_balance, 0);
+        assert_eq!(position.realized_pnl, 500);
+    }
+
+    #[test]
+    fn staked_lp_accrues_claimable_premiums() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        assert_eq!(position.staked_lp, 1_000);
+
+        // 0.5 premium per LP token since the deposit.
+        let premium_per_lp_share = constants::REWARD_PRECISION / 2;
+        position.settle_premiums(premium_per_lp_share, 0).unwrap();
+        assert_eq!(position.premiums_owed, 500);
+
+        // Settling again at the same accumulator owes nothing more.
+        position.settle_premiums(premium_per_lp_share, 0).unwrap();
+        assert_eq!(position.premiums_owed, 500);
+    }
+
+    #[test]
+    fn lp_staked_later_only_earns_from_then_on() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        position.unstake(1_000, 0, 0).unwrap();
+
+        let premium_per_lp_share = constants::REWARD_PRECISION;
+        position.stake(1_000, premium_per_lp_share, 0, 0).unwrap();
+        position.settle_premiums(premium_per_lp_share, 0).unwrap();
+        assert_eq!(position.premiums_owed, 0);
+
+        position
+            .settle_premiums(premium_per_lp_share * 2, 0)
+            .unwrap();
+        assert_eq!(position.premiums_owed, 1_000);
+    }
+
+    #[test]
+    fn unstaking_settles_what_was_earned() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        position
+            .unstake(400, constants::REWARD_PRECISION, 0)
+            .unwrap();
+
+        assert_eq!(position.staked_lp, 600);
+        assert_eq!(position.premiums_owed, 1_000);
+        // The tokens stay on the books for PnL while they sit in the wallet.
+        assert_eq!(position.lp_balance, 1_000);
+    }
+
+    #[test]
+    fn locked_lp_cannot_be_unstaked_until_unlocked() {
+        let mut position = position();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 0, 0)
+            .unwrap();
+        position.record_deposit(500, 500, true, 0, 0, 0).unwrap();
+        assert_eq!(position.staked_lp, 1_500);
+        assert_eq!(position.unstakeable_lp(0), 1_000);
+        assert!(position.unstake(1_001, 0, 0).is_err());
+
+        position.record_unlock(500).unwrap();
+        assert_eq!(position.locked_lp, 0);
+        position.unstake(1_500, 0, 0).unwrap();
+        assert_eq!(position.staked_lp, 0);
+    }
+
+    #[test]
+    fn premiums_earned_during_warmup_are_forfeited() {
+        let mut position = position_with_deposit(1_000, 0);
+        assert_eq!(position.settle_premiums(per_share(10), 50).unwrap(), 10_000);
+        assert_eq!(position.premiums_owed, 0);
+    }
+
+    #[test]
+    fn premiums_earned_after_warmup_are_credited() {
+        let mut position = position_with_deposit(1_000, 0);
+        position.settle_premiums(0, WARMUP).unwrap();
+        assert_eq!(position.settle_premiums(per_share(10), 150).unwrap(), 0);
+        assert_eq!(position.premiums_owed, 10_000);
+    }
+
+    #[test]
+    fn warmup_ending_between_settlements_forfeits_only_its_share_of_the_time() {
+        let mut position = position_with_deposit(1_000, 0);
+        // Settled for the first time a quarter of the way past the end of the warm-up.
+        assert_eq!(position.settle_premiums(per_share(10), 125).unwrap(), 8_000);
+        assert_eq!(position.premiums_owed, 2_000);
+    }
+
+    #[test]
+    fn only_the_warming_lp_forfeits() {
+        let mut position = position_with_deposit(1_000, 0);
+        position.settle_premiums(0, 200).unwrap();
+        position
+            .record_deposit(1_000, 1_000, false, 0, 200, WARMUP)
+            .unwrap();
+        assert_eq!(position.settle_premiums(per_share(1), 250).unwrap(), 1_000);
+        assert_eq!(position.premiums_owed, 1_000);
+    }
+
+    #[test]
+    fn staking_restarts_the_warmup_for_every_warming_lp() {
+        let mut position = position_with_deposit(1_000, 0);
+        position.stake(500, 0, 50, WARMUP).unwrap();
+        assert_eq!(position.warmup_lp, 1_500);
+        assert_eq!(position.warmup_end_timestamp, 50 + WARMUP);
+        assert!(position.is_warming_up(120));
+        assert_eq!(position.unstakeable_lp(120), 0);
+        assert_eq!(position.unstakeable_lp(50 + WARMUP), 1_500);
+    }
+
+    #[test]
+    fn warming_and_locked_lp_cannot_be_unstaked() {
+        let mut position = position_with_deposit(1_000, 0);
+        assert!(position.unstake(1, 0, 50).is_err());
+
+        position
+            .record_deposit(400, 400, true, 0, 200, WARMUP)
+            .unwrap();
+        assert_eq!(position.unstakeable_lp(200 + WARMUP), 1_000);
+        assert!(position.unstake(1_001, 0, 200 + WARMUP).is_err());
+        position.unstake(1_000, 0, 200 + WARMUP).unwrap();
+        assert_eq!(position.staked_lp, 400);
+    }
+}
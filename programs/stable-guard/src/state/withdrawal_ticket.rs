@@ -0,0 +1,16 @@
+use anchor_lang::prelude::*;
+
+/// A pending withdrawal. The LP tokens sit in an escrow account owned by the pool authority
+/// until the cooldown has passed and the ticket is executed. An underwriter holds at most one
+/// ticket per tranche.
+#[account]
+#[derive(InitSpace)]
+pub struct WithdrawalTicket {
+    pub owner: Pubkey,
+    pub insurance_pool: Pubkey,
+    pub tranche_index: u8,
+    pub lp_amount: u64,
+    pub requested_timestamp: i64,
+    pub unlock_timestamp: i64,
+    pub bump: u8,
+}
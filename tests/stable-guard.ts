@@ -1,1104 +1,2039 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN } from "@coral-xyz/anchor";
 import { StableGuard } from "../target/types/stable_guard"; // Adjust if your type path is different
+import IDL from "../target/idl/stable_guard.json";
+import { LiteSVM, FailedTransactionMetadata } from "litesvm";
 import {
   TOKEN_PROGRAM_ID,
-  getMint,
-  getAccount,
-  createMint as splCreateMint, // We'll use this for unsupported mint test only
-  getOrCreateAssociatedTokenAccount,
-  mintTo as splMintTo,
-  ASSOCIATED_TOKEN_PROGRAM_ID,
+  AccountLayout,
+  MintLayout,
+  MINT_SIZE,
+  getAssociatedTokenAddressSync,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createMintToInstruction,
 } from "@solana/spl-token";
 import {
+  AccountMeta,
+  Connection,
   Keypair,
   PublicKey,
+  Transaction,
+  TransactionInstruction,
   SystemProgram,
   LAMPORTS_PER_SOL,
 } from "@solana/web3.js";
 import { assert } from "chai";
 
-// Helper Function 
-async function airdropSol(
-  provider: anchor.AnchorProvider,
-  targetPublicKey: PublicKey,
-  lamports: number = 10 * LAMPORTS_PER_SOL // Increased default for potentially more transactions
-): Promise<string> {
-  try {
-    const signature = await provider.connection.requestAirdrop(targetPublicKey, lamports);
-    const latestBlockHash = await provider.connection.getLatestBlockhash();
-    await provider.connection.confirmTransaction({
-      blockhash: latestBlockHash.blockhash,
-      lastValidBlockHeight: latestBlockHash.lastValidBlockHeight,
-      signature: signature,
-    }, "confirmed");
-    return signature;
-  } catch (error) {
-    console.warn(`Airdrop to ${targetPublicKey.toBase58()} failed. They might already have funds or be rate-limited. Error: ${error.message}`);
-    return "";
-  }
-}
+// The suite runs the program inside LiteSVM instead of a local validator so that the
+// hard-coded USDC/USDT mints and their Pyth price updates can be injected directly.
 
-// Constants 
-const LP_MINT_SEED_BUF = Buffer.from("lp_mint");
-const POOL_SEED_BUF = Buffer.from("collateral_pool");
+// Constants
 const AUTHORITY_SEED_BUF = Buffer.from("pool_authority");
+const INSURANCE_POOL_SEED_BUF = Buffer.from("insurance_pool");
+const PROTOCOL_TREASURY_SEED_BUF = Buffer.from("protocol_treasury");
+const RESERVE_VAULT_SEED_BUF = Buffer.from("reserve_vault");
+const ASSET_EXPOSURE_SEED_BUF = Buffer.from("asset_exposure");
+const SETTLEMENT_ROUND_SEED_BUF = Buffer.from("settlement_round");
+const CLAIM_MINT_SEED_BUF = Buffer.from("claim_mint");
+const BUYER_PROFILE_SEED_BUF = Buffer.from("buyer_profile");
+const DEPEG_EVENT_SEED_BUF = Buffer.from("depeg_event");
+const TRANCHE_SEED_BUF = Buffer.from("tranche");
+const TRANCHE_LP_MINT_SEED_BUF = Buffer.from("tranche_lp_mint");
+const TRANCHE_VAULT_SEED_BUF = Buffer.from("tranche_vault");
 const POLICY_SEED_BUF = Buffer.from("policy");
+const WITHDRAWAL_TICKET_SEED_BUF = Buffer.from("withdrawal_ticket");
+const WITHDRAWAL_ESCROW_SEED_BUF = Buffer.from("withdrawal_escrow");
+const LOCKED_POSITION_SEED_BUF = Buffer.from("locked_position");
+const LOCKED_LP_VAULT_SEED_BUF = Buffer.from("locked_lp_vault");
+const STAKED_LP_VAULT_SEED_BUF = Buffer.from("staked_lp_vault");
+const UNDERWRITER_POSITION_SEED_BUF = Buffer.from("underwriter_position");
 
 const TOKEN_DECIMALS = 6;
-const PREMIUM_RATE_BPS_VAL = 50;
-const BINARY_PAYOUT_BPS_VAL = 1000;
-const POLICY_TERM_SECONDS = 7 * 24 * 60 * 60;
+const ONE_TOKEN = 10 ** TOKEN_DECIMALS;
+const DEPEG_THRESHOLD_PRICE = new BN(98_500_000); // $0.985 at 8 decimals
+const PEG_PRICE = 100_000_000; // $1 at 8 decimals
+const PREMIUM_RATE_BPS = 50;
+const BINARY_PAYOUT_BPS = 1000;
+const SENIOR_TARGET_APR_BPS = 500;
+const MAX_TARGET_APR_BPS = 5000;
+const VIRTUAL_LP_SHARES = 1_000_000;
+const VIRTUAL_COLLATERAL = 1_000_000;
+const SECONDS_PER_DAY = 24 * 60 * 60;
+const SECONDS_PER_YEAR = 365 * SECONDS_PER_DAY;
+const WITHDRAWAL_COOLDOWN_SECONDS = 3 * SECONDS_PER_DAY;
+const MAX_WITHDRAWAL_COOLDOWN_SECONDS = 30 * SECONDS_PER_DAY;
+const LOCK_BOOST_BPS_PER_YEAR = 5000;
+const MAX_LOCK_DURATION_SECONDS = 365 * SECONDS_PER_DAY;
+// Tranche indices, most junior first
+const JUNIOR = 0;
+const SENIOR = 1;
+const JUNIOR_PREMIUM_WEIGHT_BPS = 7000;
+const SENIOR_PREMIUM_WEIGHT_BPS = 3000;
+const SENIOR_ATTACHMENT_POINT_BPS = 2000;
+const NO_DEPOSIT_CAP = new BN("18446744073709551615");
+const MAX_PROTOCOL_FEE_BPS = 2000;
+const RESERVE_PREMIUM_BPS = 1000;
+const MAX_RESERVE_PREMIUM_BPS = 5000;
+const MAX_ASSET_LIABILITY_BPS = 5000;
+const NO_ASSET_LIABILITY_CAP = NO_DEPOSIT_CAP;
+const MAX_BUYER_ACTIVE_POLICIES = 16;
+const NO_BUYER_COVERAGE_CAP = NO_DEPOSIT_CAP;
+const REWARD_PRECISION = new BN("1000000000000");
+const SHARE_PRICE_PRECISION = new BN("1000000000000");
+const POLICY_TERM_SECONDS = 7 * SECONDS_PER_DAY;
+const START_TIMESTAMP = 1_750_000_000;
+
+const USDC_MINT_PUBKEY = new PublicKey("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+const USDT_MINT_PUBKEY = new PublicKey("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+const PYTH_USDC_USD_FEED_ID = "eaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
+const PYTH_USDT_USD_FEED_ID = "2b89b9dc8fdf9f34709a5b106b472f0f39bb6ca9ce04b0fd7f2e971688e2e53b";
+const PYTH_RECEIVER_PROGRAM_ID = new PublicKey("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+const PRICE_UPDATE_V2_DISCRIMINATOR = Buffer.from("22f123639d7ef4cd", "hex");
+const PRICE_UPDATE_V2_SIZE = 134;
 
+describe("StableGuard Protocol Tests", () => {
+  const svm = new LiteSVM();
+  const authority = Keypair.generate();
+  const buyer = Keypair.generate();
+  const underwriter = Keypair.generate();
+  // Mint authority of the injected USDC/USDT mints
+  const mintAuthority = Keypair.generate();
+
+  // Only used to build instructions and decode accounts; it never talks to a cluster.
+  const program = new Program<StableGuard>(IDL as StableGuard, {
+    connection: new Connection("http://127.0.0.1:8899"),
+    publicKey: authority.publicKey,
+  } as unknown as anchor.Provider);
+
+  // Helper Functions
+  function findPda(seeds: Buffer[]): PublicKey {
+    return PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  }
 
-const MAINNET_USDC_MINT_PUBKEY = new PublicKey("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
-const MAINNET_USDT_MINT_PUBKEY = new PublicKey("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
-const PYTH_PRICE_UPDATE_V2_ACCOUNT_PUBKEY = new PublicKey("Dpw1EAVrSB1ibxiDQyTAW6Zip3J4Btk2x4SgApQCeFbX");
+  function send(instructions: TransactionInstruction[], signers: Keypair[]) {
+    const tx = new Transaction();
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = signers[0].publicKey;
+    tx.add(...instructions);
+    tx.sign(...signers);
+    const result = svm.sendTransaction(tx);
+    // Allow an identical transaction to be sent again later in the suite.
+    svm.expireBlockhash();
+    if (result instanceof FailedTransactionMetadata) {
+      throw new Error(`${result.err()}\n${result.meta().logs().join("\n")}`);
+    }
+    return result;
+  }
 
+  async function run(builder: { instruction(): Promise<TransactionInstruction> }, signers: Keypair[]) {
+    return send([await builder.instruction()], signers);
+  }
 
+  // Simulates a view instruction and decodes its return data as `typeName`.
+  async function view(builder: { instruction(): Promise<TransactionInstruction> }, typeName: string) {
+    const tx = new Transaction();
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = authority.publicKey;
+    tx.add(await builder.instruction());
+    tx.sign(authority);
+    const result = svm.simulateTransaction(tx);
+    if (result instanceof FailedTransactionMetadata) {
+      throw new Error(`${result.err()}\n${result.meta().logs().join("\n")}`);
+    }
+    return program.coder.types.decode(typeName, Buffer.from(result.meta().returnData().data()));
+  }
 
-describe("StableGuard Protocol Tests", () => {
-  anchor.setProvider(anchor.AnchorProvider.env());
-  const provider = anchor.getProvider() as anchor.AnchorProvider;
-  const connection = provider.connection;
-  const program = anchor.workspace.StableGuard as Program<StableGuard>;
-
-  let authoritySigner: Keypair;
-  let buyer: Keypair;
-  let underwriter: Keypair;
-
-  // Test mints for collateral pools and premium payments
-  let testUsdcMintKeypair: Keypair; // For the initialize-specific tests (Mint 1)
-  let testUsdcMintPublicKey: PublicKey; // Renamed from testCollateralMint1PublicKey for clarity
-
-  let testUsdtMintKeypair: Keypair; // For the initialize-specific tests (Mint 2)
-  let testUsdtMintPublicKey: PublicKey;
-
-  // PDAs for pools and LP mints initialized with these test mints
-  // These are used by createPolicy and depositCollateral tests
-  let usdcPoolLpMintPda: PublicKey;       // LP Mint for the pool initialized with testUsdcMintPublicKey
-  let usdcPoolCollateralPda: PublicKey; // Collateral Pool for testUsdcMintPublicKey
-  let usdtPoolLpMintPda: PublicKey;       // LP Mint for the pool initialized with testUsdtMintPublicKey
-  let usdtPoolCollateralPda: PublicKey; // Collateral Pool for testUsdtMintPublicKey
-
-
-  let poolAuthorityPda: PublicKey; // Global
-
-  // Buyer's ATAs for paying premiums with test mints
-  let buyerTestUsdcAta: PublicKey;
-  let buyerTestUsdtAta: PublicKey;
-
-  // Underwriter's ATA for depositing test USDC collateral
-  let underwriterTestUsdcAta: PublicKey; // For depositing testUsdcMintPublicKey
-  let underwriterTestUsdtAta: PublicKey; // For depositing testUsdtMintPublicKey
-
-  let policyIdCounter = 0;
-  let usdcPolicyIdForCheck: BN;
-  let usdtPolicyIdForCheck: BN;
-
-  let underwriterLpUsdcAta: PublicKey;
-  let underwriterLpUsdtAta: PublicKey;
-
-  const POLICY_TERM_SECONDS_FOR_TESTING = 2;
-  before(async () => {
-    authoritySigner = (provider.wallet as anchor.Wallet).payer;
-    buyer = Keypair.generate();
-    underwriter = Keypair.generate();
-
-    await airdropSol(provider, buyer.publicKey);
-    await airdropSol(provider, underwriter.publicKey);
-
-    // usdcCollateralMintForPool = MAINNET_USDC_MINT_PUBKEY;
-    // usdtCollateralMintForPool = MAINNET_USDT_MINT_PUBKEY;
-    // console.log(`Using Mainnet USDC Mint for USDC pool setup: ${usdcCollateralMintForPool.toBase58()}`);
-    // console.log(`Using Mainnet USDT Mint for USDT pool setup: ${usdtCollateralMintForPool.toBase58()}`);
-
-    testUsdcMintKeypair = Keypair.generate();
-    testUsdcMintPublicKey = await splCreateMint(
-      provider.connection,
-      authoritySigner,
-      authoritySigner.publicKey,
-      null,
-      TOKEN_DECIMALS,
-      testUsdcMintKeypair
+  // Asserts that `promise` fails with the named program error.
+  async function expectError(promise: Promise<unknown>, errorName: string) {
+    try {
+      await promise;
+    } catch (error) {
+      assert.include(error.message, errorName);
+      return;
+    }
+    assert.fail(`Should have failed with ${errorName}.`);
+  }
+
+  function fetch(accountName: string, address: PublicKey) {
+    const account = svm.getAccount(address);
+    assert.isNotNull(account, `${accountName} ${address.toBase58()} does not exist`);
+    return program.coder.accounts.decode(accountName, Buffer.from(account.data));
+  }
+
+  function tokenBalance(address: PublicKey): bigint {
+    return AccountLayout.decode(svm.getAccount(address).data).amount;
+  }
+
+  function now(): number {
+    return Number(svm.getClock().unixTimestamp);
+  }
+
+  function setNow(timestamp: number) {
+    const clock = svm.getClock();
+    clock.unixTimestamp = BigInt(timestamp);
+    svm.setClock(clock);
+  }
+
+  // Writes an SPL mint at `address`, letting the tests mint the real USDC/USDT addresses.
+  function injectMint(address: PublicKey) {
+    const data = Buffer.alloc(MINT_SIZE);
+    MintLayout.encode(
+      {
+        mintAuthorityOption: 1,
+        mintAuthority: mintAuthority.publicKey,
+        supply: BigInt(0),
+        decimals: TOKEN_DECIMALS,
+        isInitialized: true,
+        freezeAuthorityOption: 0,
+        freezeAuthority: PublicKey.default,
+      },
+      data
+    );
+    svm.setAccount(address, {
+      lamports: LAMPORTS_PER_SOL,
+      data,
+      owner: TOKEN_PROGRAM_ID,
+      executable: false,
+    });
+  }
+
+  // Writes a fully verified Pyth price update for `feedId` with an 8-decimal price.
+  function setPriceUpdate(address: PublicKey, feedId: string, price: number, publishTime = now(), conf = 0) {
+    const data = Buffer.alloc(PRICE_UPDATE_V2_SIZE);
+    PRICE_UPDATE_V2_DISCRIMINATOR.copy(data, 0);
+    data.writeUInt8(1, 40); // VerificationLevel::Full
+    Buffer.from(feedId, "hex").copy(data, 41);
+    data.writeBigInt64LE(BigInt(price), 73);
+    data.writeBigUInt64LE(BigInt(conf), 81);
+    data.writeInt32LE(-8, 89);
+    data.writeBigInt64LE(BigInt(publishTime), 93);
+    data.writeBigInt64LE(BigInt(publishTime), 101);
+    data.writeBigInt64LE(BigInt(price), 109);
+    data.writeBigUInt64LE(BigInt(conf), 117);
+    svm.setAccount(address, {
+      lamports: LAMPORTS_PER_SOL,
+      data,
+      owner: PYTH_RECEIVER_PROGRAM_ID,
+      executable: false,
+    });
+  }
+
+  // Refreshes the collateral price at the peg, for instructions that value the pool's liability.
+  function collateralPriceAtPeg(mint = USDC_MINT_PUBKEY): PublicKey {
+    const feedId = mint.equals(USDT_MINT_PUBKEY) ? PYTH_USDT_USD_FEED_ID : PYTH_USDC_USD_FEED_ID;
+    setPriceUpdate(collateralPriceUpdate, feedId, PEG_PRICE);
+    return collateralPriceUpdate;
+  }
+
+  function createAta(mint: PublicKey, owner: PublicKey): PublicKey {
+    const ata = getAssociatedTokenAddressSync(mint, owner, true);
+    send([createAssociatedTokenAccountIdempotentInstruction(authority.publicKey, ata, owner, mint)], [authority]);
+    return ata;
+  }
+
+  function mintTokens(mint: PublicKey, to: PublicKey, amount: number) {
+    send([createMintToInstruction(mint, to, mintAuthority.publicKey, amount)], [authority, mintAuthority]);
+  }
+
+  // Accounts
+  const poolAuthorityPda = findPda([AUTHORITY_SEED_BUF]);
+
+  function poolPdas(mint: PublicKey) {
+    const insurancePool = findPda([INSURANCE_POOL_SEED_BUF, mint.toBuffer()]);
+    return {
+      insurancePool,
+      treasuryVault: findPda([PROTOCOL_TREASURY_SEED_BUF, mint.toBuffer()]),
+      reserveVault: findPda([RESERVE_VAULT_SEED_BUF, mint.toBuffer()]),
+      claimMint: findPda([CLAIM_MINT_SEED_BUF, insurancePool.toBuffer()]),
+    };
+  }
+
+  // A tranche's state account, LP mint and vaults, derived the way the program does.
+  function tranchePdas(insurancePool: PublicKey, index: number) {
+    const tranche = findPda([TRANCHE_SEED_BUF, insurancePool.toBuffer(), Buffer.from([index])]);
+    const lpMint = findPda([TRANCHE_LP_MINT_SEED_BUF, tranche.toBuffer()]);
+    return {
+      tranche,
+      lpMint,
+      collateralVault: findPda([TRANCHE_VAULT_SEED_BUF, tranche.toBuffer()]),
+      lockedLpVault: findPda([LOCKED_LP_VAULT_SEED_BUF, lpMint.toBuffer()]),
+      stakedLpVault: findPda([STAKED_LP_VAULT_SEED_BUF, lpMint.toBuffer()]),
+    };
+  }
+
+  const usdcPool = poolPdas(USDC_MINT_PUBKEY);
+  const tranches = [tranchePdas(usdcPool.insurancePool, JUNIOR), tranchePdas(usdcPool.insurancePool, SENIOR)];
+  const usdcPriceUpdate = Keypair.generate().publicKey;
+  const usdtPriceUpdate = Keypair.generate().publicKey;
+  const collateralPriceUpdate = Keypair.generate().publicKey;
+
+  let buyerUsdcAta: PublicKey;
+  let underwriterUsdcAta: PublicKey;
+  let authorityUsdcAta: PublicKey;
+  // Underwriter LP token accounts, by tranche index
+  const underwriterLpAtas: PublicKey[] = [];
+  let lockedPositionId: BN;
+
+  // Tranches the pool has so far, most junior first.
+  function poolTranches(insurancePool = usdcPool.insurancePool) {
+    const { trancheCount } = fetch("insurancePool", insurancePool);
+    return Array.from({ length: trancheCount }, (_, index) => tranchePdas(insurancePool, index));
+  }
+
+  // Instructions that check the whole capital stack take every tranche, most junior first.
+  function trancheMetas(insurancePool = usdcPool.insurancePool): AccountMeta[] {
+    return poolTranches(insurancePool).map(({ tranche }) => ({ pubkey: tranche, isSigner: false, isWritable: true }));
+  }
+
+  // Instructions that move collateral between tranches take each tranche followed by its vault.
+  function trancheAndVaultMetas(insurancePool = usdcPool.insurancePool): AccountMeta[] {
+    return poolTranches(insurancePool).flatMap(({ tranche, collateralVault }) => [
+      { pubkey: tranche, isSigner: false, isWritable: true },
+      { pubkey: collateralVault, isSigner: false, isWritable: true },
+    ]);
+  }
+
+  function fetchTranche(index: number) {
+    return fetch("trancheState", tranches[index].tranche);
+  }
+
+  function lpSupply(index: number): BN {
+    return new BN(MintLayout.decode(svm.getAccount(tranches[index].lpMint).data).supply.toString());
+  }
+
+  function initialize(mint: PublicKey) {
+    return run(
+      program.methods.initialize(DEPEG_THRESHOLD_PRICE).accountsPartial({
+        authority: authority.publicKey,
+        ...poolPdas(mint),
+        poolAuthority: poolAuthorityPda,
+        collateralMint: mint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      }),
+      [authority]
     );
-    // console.log(`Test Collateral Mint 1 (for initialize tests) created: ${testCollateralMint1PublicKey.toBase58()}`);
-
-    testUsdtMintKeypair = Keypair.generate();
-    testUsdtMintPublicKey = await splCreateMint(
-      provider.connection,
-      authoritySigner,
-      authoritySigner.publicKey,
-      null,
-      TOKEN_DECIMALS,
-      testUsdtMintKeypair
+  }
+
+  function addTranche(attachmentPointBps: number, premiumWeightBps: number, signer = authority) {
+    const index = fetch("insurancePool", usdcPool.insurancePool).trancheCount;
+    const accounts = tranchePdas(usdcPool.insurancePool, index);
+    return run(
+      program.methods
+        .addTranche({
+          premiumWeightBps: new BN(premiumWeightBps),
+          attachmentPointBps: new BN(attachmentPointBps),
+          targetAprBps: new BN(SENIOR_TARGET_APR_BPS),
+          maxCollateral: NO_DEPOSIT_CAP,
+        })
+        .accountsPartial({
+          authority: signer.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          ...accounts,
+          poolAuthority: poolAuthorityPda,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheMetas()),
+      [signer]
     );
+  }
 
-    [poolAuthorityPda] = PublicKey.findProgramAddressSync(
-      [AUTHORITY_SEED_BUF], program.programId
+  function lockedPositionPda(positionId: BN): PublicKey {
+    return findPda([
+      LOCKED_POSITION_SEED_BUF,
+      usdcPool.insurancePool.toBuffer(),
+      underwriter.publicKey.toBuffer(),
+      positionId.toArrayLike(Buffer, "le", 8),
+    ]);
+  }
+
+  function nextLockedPositionPda(): PublicKey {
+    return lockedPositionPda(fetch("insurancePool", usdcPool.insurancePool).lastLockedPositionId.addn(1));
+  }
+
+  function underwriterPositionPda(index: number): PublicKey {
+    return findPda([
+      UNDERWRITER_POSITION_SEED_BUF,
+      usdcPool.insurancePool.toBuffer(),
+      underwriter.publicKey.toBuffer(),
+      Buffer.from([index]),
+    ]);
+  }
+
+  function depositCollateral(
+    amount: number,
+    index: number,
+    minLpOut = 0,
+    lockDurationSeconds: number | null = null,
+    lockedPosition: PublicKey | null = lockDurationSeconds === null ? null : nextLockedPositionPda()
+  ) {
+    const lockDuration = lockDurationSeconds === null ? null : new BN(lockDurationSeconds);
+    return run(
+      program.methods
+        .depositCollateral(new BN(amount), new BN(minLpOut), index, lockDuration)
+        .accountsPartial({
+          underwriter: underwriter.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          underwriterTokenAccount: underwriterUsdcAta,
+          ...tranches[index],
+          underwriterPosition: underwriterPositionPda(index),
+          lockedPosition,
+          poolAuthority: poolAuthorityPda,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheMetas()),
+      [underwriter]
     );
-    // console.log(`Global Pool Authority PDA: ${poolAuthorityPda.toBase58()}`);
+  }
 
-    // console.log("\nInitializing protocol for USDC collateral (using Mainnet USDC Mint)...");
-    [usdcPoolLpMintPda] = PublicKey.findProgramAddressSync(
-      [LP_MINT_SEED_BUF, testUsdcMintPublicKey.toBuffer()], program.programId
+  function withdrawalPdas(index: number) {
+    const withdrawalTicket = findPda([
+      WITHDRAWAL_TICKET_SEED_BUF,
+      usdcPool.insurancePool.toBuffer(),
+      underwriter.publicKey.toBuffer(),
+      Buffer.from([index]),
+    ]);
+    const withdrawalEscrow = findPda([WITHDRAWAL_ESCROW_SEED_BUF, withdrawalTicket.toBuffer()]);
+    return { withdrawalTicket, withdrawalEscrow };
+  }
+
+  function stakeLp(lpAmount: BN, index: number, lpIndex = index) {
+    return run(
+      program.methods.stakeLp(lpAmount, index).accountsPartial({
+        owner: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        underwriterPosition: underwriterPositionPda(index),
+        tranche: tranches[index].tranche,
+        stakedLpVault: tranches[index].stakedLpVault,
+        ownerLpAccount: underwriterLpAtas[lpIndex],
+        lpMint: tranches[lpIndex].lpMint,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
     );
-    [usdcPoolCollateralPda] = PublicKey.findProgramAddressSync(
-      [POOL_SEED_BUF, testUsdcMintPublicKey.toBuffer()], program.programId
+  }
+
+  function unstakeLp(lpAmount: BN, index: number) {
+    return run(
+      program.methods.unstakeLp(lpAmount).accountsPartial({
+        owner: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        underwriterPosition: underwriterPositionPda(index),
+        tranche: tranches[index].tranche,
+        stakedLpVault: tranches[index].stakedLpVault,
+        ownerLpAccount: underwriterLpAtas[index],
+        lpMint: tranches[index].lpMint,
+        poolAuthority: poolAuthorityPda,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
     );
-    await program.methods.initialize()
-      .accounts({
-        authority: authoritySigner.publicKey,
-        lpMint: usdcPoolLpMintPda,
-        collateralTokenPool: usdcPoolCollateralPda,
+  }
+
+  function claimPremiums(index: number) {
+    return run(
+      program.methods.claimPremiums().accountsPartial({
+        owner: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        underwriterPosition: underwriterPositionPda(index),
+        ownerTokenAccount: underwriterUsdcAta,
+        tranche: tranches[index].tranche,
+        collateralVault: tranches[index].collateralVault,
         poolAuthority: poolAuthorityPda,
-        mint: testUsdcMintPublicKey,
-        systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
-      })
-      .rpc();
-    // console.log(`Protocol initialized for USDC. LP Mint: ${usdcLpMintPda.toBase58()}, Pool: ${usdcCollateralTokenPoolPda.toBase58()}`);
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
+    );
+  }
 
-    // console.log("\nInitializing protocol for USDT collateral (using Mainnet USDT Mint)...");
-    [usdtPoolLpMintPda] = PublicKey.findProgramAddressSync(
-      [LP_MINT_SEED_BUF, testUsdtMintPublicKey.toBuffer()], program.programId
+  // Claimable premiums a position has earned, settled or not.
+  function claimablePremiums(index: number): BN {
+    const tranche = fetchTranche(index);
+    const position = fetch("underwriterPosition", underwriterPositionPda(index));
+    return position.premiumsOwed.add(
+      position.stakedLp.mul(tranche.premiumPerLpShare).sub(position.premiumDebt).div(REWARD_PRECISION)
     );
-    [usdtPoolCollateralPda] = PublicKey.findProgramAddressSync(
-      [POOL_SEED_BUF, testUsdtMintPublicKey.toBuffer()], program.programId
+  }
+
+  function requestWithdrawal(lpAmount: BN, index: number, lpIndex = index) {
+    return run(
+      program.methods.requestWithdrawal(lpAmount, index).accountsPartial({
+        underwriter: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        ...withdrawalPdas(index),
+        underwriterLpAccount: underwriterLpAtas[lpIndex],
+        tranche: tranches[index].tranche,
+        lpMint: tranches[lpIndex].lpMint,
+        poolAuthority: poolAuthorityPda,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
     );
-    await program.methods.initialize()
-      .accounts({
-        authority: authoritySigner.publicKey,
-        lpMint: usdtPoolLpMintPda,
-        collateralTokenPool: usdtPoolCollateralPda,
+  }
+
+  function executeWithdrawal(index: number, minCollateralOut = new BN(0), priceUpdate = collateralPriceAtPeg()) {
+    return run(
+      program.methods
+        .executeWithdrawal(minCollateralOut)
+        .accountsPartial({
+          underwriter: underwriter.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          ...withdrawalPdas(index),
+          underwriterPosition: underwriterPositionPda(index),
+          underwriterTokenAccount: underwriterUsdcAta,
+          tranche: tranches[index].tranche,
+          collateralVault: tranches[index].collateralVault,
+          lpMint: tranches[index].lpMint,
+          poolAuthority: poolAuthorityPda,
+          collateralMint: USDC_MINT_PUBKEY,
+          collateralPriceUpdate: priceUpdate,
+        })
+        .remainingAccounts(trancheMetas()),
+      [underwriter]
+    );
+  }
+
+  function switchTranche(
+    sourceIndex: number,
+    targetIndex: number,
+    minLpOut = new BN(0),
+    priceUpdate = collateralPriceAtPeg()
+  ) {
+    return run(
+      program.methods
+        .switchTranche(targetIndex, minLpOut)
+        .accountsPartial({
+          underwriter: underwriter.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          ...withdrawalPdas(sourceIndex),
+          sourceTranche: tranches[sourceIndex].tranche,
+          sourceCollateralVault: tranches[sourceIndex].collateralVault,
+          sourceLpMint: tranches[sourceIndex].lpMint,
+          sourcePosition: underwriterPositionPda(sourceIndex),
+          targetTranche: tranches[targetIndex].tranche,
+          targetCollateralVault: tranches[targetIndex].collateralVault,
+          targetLpMint: tranches[targetIndex].lpMint,
+          targetPosition: underwriterPositionPda(targetIndex),
+          targetStakedLpVault: tranches[targetIndex].stakedLpVault,
+          poolAuthority: poolAuthorityPda,
+          collateralMint: USDC_MINT_PUBKEY,
+          collateralPriceUpdate: priceUpdate,
+        })
+        .remainingAccounts(trancheMetas()),
+      [underwriter]
+    );
+  }
+
+  function cancelWithdrawal(index: number) {
+    return run(
+      program.methods.cancelWithdrawal().accountsPartial({
+        underwriter: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        ...withdrawalPdas(index),
+        underwriterLpAccount: underwriterLpAtas[index],
+        tranche: tranches[index].tranche,
+        lpMint: tranches[index].lpMint,
         poolAuthority: poolAuthorityPda,
-        mint: testUsdtMintPublicKey,
-        systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
-      })
-      .rpc();
-    // console.log(`Protocol initialized for USDT. LP Mint: ${usdtLpMintPda.toBase58()}, Pool: ${usdtCollateralTokenPoolPda.toBase58()}`);
-
-    buyerTestUsdcAta = (await getOrCreateAssociatedTokenAccount(
-      connection, authoritySigner, testUsdcMintPublicKey, buyer.publicKey
-    )).address;
-    await splMintTo(
-      connection, authoritySigner, testUsdcMintPublicKey, buyerTestUsdcAta, authoritySigner,
-      5000 * (10 ** TOKEN_DECIMALS)
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
+    );
+  }
+
+  function updatePoolConfig(args: object, signer = authority) {
+    return run(
+      program.methods
+        .updatePoolConfig({
+          premiumSplitMode: null,
+          minJuniorPremiumShareBps: null,
+          maxJuniorPremiumShareBps: null,
+          withdrawalCooldownSeconds: null,
+          lockBoostBpsPerYear: null,
+          maxLockDurationSeconds: null,
+          unlockedCapitalWeightBps: null,
+          premiumDistributionMode: null,
+          lpWarmupSeconds: null,
+          maxPoolCollateral: null,
+          protocolFeeBps: null,
+          treasuryAuthority: null,
+          reservePremiumBps: null,
+          maxBuyerInsuredAmount: null,
+          maxBuyerActivePolicies: null,
+          ...args,
+        } as any)
+        .accountsPartial({
+          authority: signer.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheMetas()),
+      [signer]
     );
+  }
+
+  function updateTrancheConfig(index: number, args: object, signer = authority) {
+    return run(
+      program.methods
+        .updateTrancheConfig({
+          premiumWeightBps: null,
+          attachmentPointBps: null,
+          targetAprBps: null,
+          maxCollateral: null,
+          ...args,
+        } as any)
+        .accountsPartial({
+          authority: signer.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          tranche: tranches[index].tranche,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheMetas()),
+      [signer]
+    );
+  }
+
+  // Setting the distribution mode releases the premium earned so far, even if the mode
+  // stays the same.
+  function releasePremiums() {
+    const pool = fetch("insurancePool", usdcPool.insurancePool);
+    return updatePoolConfig({ premiumDistributionMode: pool.premiumDistributionMode });
+  }
+
+  function unlockPosition(positionId: BN, index: number) {
+    return run(
+      program.methods.unlockPosition().accountsPartial({
+        owner: underwriter.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        lockedPosition: lockedPositionPda(positionId),
+        tranche: tranches[index].tranche,
+        lockedLpVault: tranches[index].lockedLpVault,
+        underwriterPosition: underwriterPositionPda(index),
+        stakedLpVault: tranches[index].stakedLpVault,
+        ownerTokenAccount: underwriterUsdcAta,
+        collateralVault: tranches[index].collateralVault,
+        lpMint: tranches[index].lpMint,
+        poolAuthority: poolAuthorityPda,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [underwriter]
+    );
+  }
+
+  function getDepositCapacity() {
+    return view(
+      program.methods
+        .getDepositCapacity()
+        .accountsPartial({
+          insurancePool: usdcPool.insurancePool,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheMetas()),
+      "depositCapacity"
+    );
+  }
+
+  function getCapitalReleaseSchedule() {
+    return view(
+      program.methods.getCapitalReleaseSchedule().accountsPartial({
+        insurancePool: usdcPool.insurancePool,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      "capitalReleaseSchedule"
+    );
+  }
+
+  // Collateral counted towards new policies, as the program weighs it: locked LP in full,
+  // the rest at the pool's unlocked capital weight.
+  function underwritingCapacity(): BN {
+    const { unlockedCapitalWeightBps } = fetch("insurancePool", usdcPool.insurancePool);
+    let total = new BN(0);
+    let locked = new BN(0);
+    tranches.forEach((_, index) => {
+      const tranche = fetchTranche(index);
+      total = total.add(tranche.collateral);
+      const lockedCollateral = tranche.lockedLp
+        .mul(tranche.collateral.addn(VIRTUAL_COLLATERAL))
+        .div(tranche.lpSupply.addn(VIRTUAL_LP_SHARES));
+      locked = locked.add(BN.min(lockedCollateral, tranche.collateral));
+    });
+    return BN.min(total, locked.add(total.sub(locked).mul(unlockedCapitalWeightBps).divn(10000)));
+  }
+
+  function getUnderwriterPnl(index: number) {
+    return view(
+      program.methods.getUnderwriterPnl().accountsPartial({
+        insurancePool: usdcPool.insurancePool,
+        underwriterPosition: underwriterPositionPda(index),
+        tranche: tranches[index].tranche,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      "underwriterPnl"
+    );
+  }
+
+  function assetExposurePda(insuredMint: PublicKey, insurancePool = usdcPool.insurancePool): PublicKey {
+    return findPda([ASSET_EXPOSURE_SEED_BUF, insurancePool.toBuffer(), insuredMint.toBuffer()]);
+  }
+
+  function buyerProfilePda(insurancePool = usdcPool.insurancePool): PublicKey {
+    return findPda([BUYER_PROFILE_SEED_BUF, insurancePool.toBuffer(), buyer.publicKey.toBuffer()]);
+  }
+
+  function depegEventPda(insuredMint: PublicKey, day: number): PublicKey {
+    return findPda([
+      DEPEG_EVENT_SEED_BUF,
+      usdcPool.insurancePool.toBuffer(),
+      insuredMint.toBuffer(),
+      new BN(day).toArrayLike(Buffer, "le", 8),
+    ]);
+  }
+
+  function expiryDay(policyId: BN): number {
+    return Math.floor(fetch("policyAccount", policyPda(policyId)).expiryTimestamp.toNumber() / SECONDS_PER_DAY);
+  }
+
+  // When the last policy expiring on `day` has expired, and its price is recorded.
+  function windowEnd(day: number): number {
+    return (day + 1) * SECONDS_PER_DAY;
+  }
+
+  function settlementRoundPda(insuredMint: PublicKey, day: number): PublicKey {
+    return findPda([
+      SETTLEMENT_ROUND_SEED_BUF,
+      usdcPool.insurancePool.toBuffer(),
+      insuredMint.toBuffer(),
+      new BN(day).toArrayLike(Buffer, "le", 8),
+    ]);
+  }
+
+  function policyPda(policyId: BN, insurancePool = usdcPool.insurancePool): PublicKey {
+    return findPda([
+      POLICY_SEED_BUF,
+      insurancePool.toBuffer(),
+      buyer.publicKey.toBuffer(),
+      policyId.toArrayLike(Buffer, "le", 8),
+    ]);
+  }
+
+  async function createPolicy(insuredAmount: number, insuredMint: PublicKey, premiumMint = USDC_MINT_PUBKEY) {
+    const pool = poolPdas(premiumMint);
+    const policyId = fetch("insurancePool", pool.insurancePool).lastPolicyId.addn(1);
+    await run(
+      program.methods
+        .createPolicy(new BN(insuredAmount), new BN(POLICY_TERM_SECONDS))
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          insurancePool: pool.insurancePool,
+          policyAccount: policyPda(policyId, pool.insurancePool),
+          buyerTokenAccount: getAssociatedTokenAddressSync(premiumMint, buyer.publicKey),
+          treasuryVault: pool.treasuryVault,
+          reserveVault: pool.reserveVault,
+          mint: premiumMint,
+          insuredStablecoinMint: insuredMint,
+          assetExposure: assetExposurePda(insuredMint, pool.insurancePool),
+          buyerProfile: buyerProfilePda(pool.insurancePool),
+          collateralPriceUpdate: collateralPriceAtPeg(premiumMint),
+        })
+        .remainingAccounts(trancheAndVaultMetas(pool.insurancePool)),
+      [buyer]
+    );
+    return policyId;
+  }
+
+  function claimProtocolFees(signer = authority) {
+    return run(
+      program.methods.claimProtocolFees().accountsPartial({
+        treasuryAuthority: signer.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        treasuryVault: usdcPool.treasuryVault,
+        destinationTokenAccount: authorityUsdcAta,
+        poolAuthority: poolAuthorityPda,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [signer]
+    );
+  }
+
+  function topUpReserve(amount: number, signer = authority) {
+    return run(
+      program.methods.topUpReserve(new BN(amount)).accountsPartial({
+        authority: signer.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        reserveVault: usdcPool.reserveVault,
+        fundingTokenAccount: getAssociatedTokenAddressSync(USDC_MINT_PUBKEY, signer.publicKey),
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [signer]
+    );
+  }
 
-    buyerTestUsdtAta = (await getOrCreateAssociatedTokenAccount(
-      connection, authoritySigner, testUsdtMintPublicKey, buyer.publicKey
-    )).address;
-    await splMintTo(
-      connection, authoritySigner, testUsdtMintPublicKey, buyerTestUsdtAta, authoritySigner,
-      5000 * (10 ** TOKEN_DECIMALS)
+  function cancelPolicy(policyId: BN) {
+    const policy = fetch("policyAccount", policyPda(policyId));
+    return run(
+      program.methods
+        .cancelPolicy()
+        .accountsPartial({
+          buyer: buyer.publicKey,
+          policyAccount: policyPda(policyId),
+          insurancePool: usdcPool.insurancePool,
+          assetExposure: assetExposurePda(policy.insuredStablecoinMint),
+          buyerProfile: buyerProfilePda(),
+          buyerTokenAccount: buyerUsdcAta,
+          poolAuthority: poolAuthorityPda,
+          mint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheAndVaultMetas()),
+      [buyer]
     );
+  }
 
-    underwriterTestUsdcAta = (await getOrCreateAssociatedTokenAccount(
-      connection, authoritySigner, testUsdcMintPublicKey, underwriter.publicKey
-    )).address;
-    await splMintTo(
-      connection, authoritySigner, testUsdcMintPublicKey, underwriterTestUsdcAta, authoritySigner,
-      10000 * (10 ** TOKEN_DECIMALS) // Mint 10,000 test USDC for underwriter
+  function checkAndPayout(policyId: BN, settlementRound: PublicKey | null = null) {
+    const policy = fetch("policyAccount", policyPda(policyId));
+    const depegEvent = depegEventPda(policy.insuredStablecoinMint, expiryDay(policyId));
+    // Settlement is permissionless, so the pool authority pays the fee here.
+    return run(
+      program.methods
+        .checkAndPayout(policyId)
+        .accountsPartial({
+          policyOwner: buyer.publicKey,
+          policyAccount: policyPda(policyId),
+          insurancePool: usdcPool.insurancePool,
+          assetExposure: assetExposurePda(policy.insuredStablecoinMint),
+          buyerProfile: buyerProfilePda(),
+          settlementRound,
+          depegEvent: svm.getAccount(depegEvent) ? depegEvent : null,
+          claimMint: usdcPool.claimMint,
+          ownerClaimAccount: null,
+          reserveVault: usdcPool.reserveVault,
+          poolAuthority: poolAuthorityPda,
+          payoutTokenAccount: buyerUsdcAta,
+          mint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheAndVaultMetas()),
+      [authority]
     );
+  }
 
-    underwriterTestUsdtAta = (await getOrCreateAssociatedTokenAccount(
-      connection, authoritySigner, testUsdtMintPublicKey, underwriter.publicKey
-    )).address;
-    await splMintTo(
-      connection, authoritySigner, testUsdtMintPublicKey, underwriterTestUsdtAta, authoritySigner,
-      10000 * (10 ** TOKEN_DECIMALS) // Mint 10,000 test USDC for underwriter
+  function updateAssetExposureLimits(
+    insuredMint: PublicKey,
+    maxLiability: BN,
+    maxLiabilityBps: number,
+    signer = authority
+  ) {
+    return run(
+      program.methods
+        .updateAssetExposureLimits({ maxLiability, maxLiabilityBps: new BN(maxLiabilityBps) })
+        .accountsPartial({
+          authority: signer.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          insuredStablecoinMint: insuredMint,
+          assetExposure: assetExposurePda(insuredMint),
+          collateralMint: USDC_MINT_PUBKEY,
+        }),
+      [signer]
     );
+  }
+
+  function redeemClaims(amount: BN) {
+    return run(
+      program.methods.redeemClaims(amount).accountsPartial({
+        holder: buyer.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        claimMint: usdcPool.claimMint,
+        holderClaimAccount: getAssociatedTokenAddressSync(usdcPool.claimMint, buyer.publicKey),
+        reserveVault: usdcPool.reserveVault,
+        holderTokenAccount: buyerUsdcAta,
+        poolAuthority: poolAuthorityPda,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [buyer]
+    );
+  }
 
-    underwriterLpUsdcAta = (await getOrCreateAssociatedTokenAccount(
-      connection,
-      underwriter, // Payer for ATA creation if needed
-      usdcPoolLpMintPda, // The LP mint for the USDC pool
-      underwriter.publicKey
-    )).address;
-    underwriterLpUsdtAta = (await getOrCreateAssociatedTokenAccount(
-      connection,
-      underwriter, // Payer for ATA creation if needed
-      usdtPoolLpMintPda, // The LP mint for the USDC pool
-      underwriter.publicKey
-    )).address;
+  function recordPriceObservation(insuredMint: PublicKey, day: number, priceUpdate: PublicKey) {
+    // Recording is permissionless, so the pool authority pays for the account here.
+    return run(
+      program.methods.recordPriceObservation(new BN(day)).accountsPartial({
+        keeper: authority.publicKey,
+        insurancePool: usdcPool.insurancePool,
+        depegEvent: depegEventPda(insuredMint, day),
+        insuredStablecoinMint: insuredMint,
+        pythPriceUpdate: priceUpdate,
+        collateralMint: USDC_MINT_PUBKEY,
+      }),
+      [authority]
+    );
+  }
+
+  function openSettlementRound(insuredMint: PublicKey, day: number) {
+    return run(
+      program.methods
+        .openSettlementRound(new BN(day))
+        .accountsPartial({
+          keeper: authority.publicKey,
+          insurancePool: usdcPool.insurancePool,
+          assetExposure: assetExposurePda(insuredMint),
+          depegEvent: depegEventPda(insuredMint, day),
+          settlementRound: settlementRoundPda(insuredMint, day),
+          reserveVault: usdcPool.reserveVault,
+          poolAuthority: poolAuthorityPda,
+          insuredStablecoinMint: insuredMint,
+          collateralMint: USDC_MINT_PUBKEY,
+        })
+        .remainingAccounts(trancheAndVaultMetas()),
+      [authority]
+    );
+  }
+
+
+  before(() => {
+    svm.addProgramFromFile(program.programId, "target/deploy/stable_guard.so");
+    setNow(START_TIMESTAMP);
+    for (const signer of [authority, buyer, underwriter, mintAuthority]) {
+      svm.airdrop(signer.publicKey, BigInt(10 * LAMPORTS_PER_SOL));
+    }
+    injectMint(USDC_MINT_PUBKEY);
+    injectMint(USDT_MINT_PUBKEY);
+
+    buyerUsdcAta = createAta(USDC_MINT_PUBKEY, buyer.publicKey);
+    mintTokens(USDC_MINT_PUBKEY, buyerUsdcAta, 5000 * ONE_TOKEN);
+    underwriterUsdcAta = createAta(USDC_MINT_PUBKEY, underwriter.publicKey);
+    mintTokens(USDC_MINT_PUBKEY, underwriterUsdcAta, 10000 * ONE_TOKEN);
+    authorityUsdcAta = createAta(USDC_MINT_PUBKEY, authority.publicKey);
+    mintTokens(USDC_MINT_PUBKEY, authorityUsdcAta, 100 * ONE_TOKEN);
   });
 
   describe("Initialize Instruction Tests", () => {
-    let distinctTestMintKeypair: Keypair;
-    let distinctTestMintPublicKey: PublicKey;
+    it("initializes the USDC pool without any tranches", async () => {
+      await initialize(USDC_MINT_PUBKEY);
+
+      const pool = fetch("insurancePool", usdcPool.insurancePool);
+      assert.strictEqual(pool.authority.toBase58(), authority.publicKey.toBase58());
+      assert.strictEqual(pool.collateralMint.toBase58(), USDC_MINT_PUBKEY.toBase58());
+      assert.ok(pool.depegThreshold.eq(DEPEG_THRESHOLD_PRICE));
+      assert.strictEqual(pool.trancheCount, 0);
+      assert.ok(pool.withdrawalCooldownSeconds.eqn(WITHDRAWAL_COOLDOWN_SECONDS));
+      assert.ok(pool.lockBoostBpsPerYear.eqn(LOCK_BOOST_BPS_PER_YEAR));
+      assert.ok(pool.maxLockDurationSeconds.eqn(MAX_LOCK_DURATION_SECONDS));
+      assert.ok(pool.maxPoolCollateral.eq(NO_DEPOSIT_CAP));
+      assert.strictEqual(pool.treasuryAuthority.toBase58(), authority.publicKey.toBase58());
+      assert.ok(pool.protocolFeeBps.isZero());
+      assert.ok(pool.reservePremiumBps.eq(new BN(RESERVE_PREMIUM_BPS)));
+      assert.ok(pool.reserveBalance.isZero());
+      assert.ok(pool.outstandingClaims.isZero());
+      assert.ok(pool.claimsFund.isZero());
+      assert.ok(pool.settlementFund.isZero());
+      assert.ok(pool.maxBuyerInsuredAmount.eq(NO_BUYER_COVERAGE_CAP));
+      assert.ok(pool.maxBuyerActivePolicies.eq(new BN(MAX_BUYER_ACTIVE_POLICIES)));
+
+      const claimMint = MintLayout.decode(svm.getAccount(usdcPool.claimMint).data);
+      assert.strictEqual(claimMint.mintAuthority.toBase58(), poolAuthorityPda.toBase58());
+      assert.strictEqual(claimMint.decimals, TOKEN_DECIMALS);
+
+      for (const vault of [usdcPool.treasuryVault, usdcPool.reserveVault]) {
+        const vaultInfo = AccountLayout.decode(svm.getAccount(vault).data);
+        assert.strictEqual(vaultInfo.mint.toBase58(), USDC_MINT_PUBKEY.toBase58());
+        assert.strictEqual(vaultInfo.owner.toBase58(), poolAuthorityPda.toBase58());
+        assert.strictEqual(vaultInfo.amount, BigInt(0));
+      }
+    });
 
-    before(async () => {
-      distinctTestMintKeypair = Keypair.generate();
-      distinctTestMintPublicKey = await splCreateMint(
-        provider.connection,
-        authoritySigner,
-        authoritySigner.publicKey,
-        null,
-        TOKEN_DECIMALS,
-        distinctTestMintKeypair
+    it("fails to re-initialize the same collateral mint", async () => {
+      // The system program refuses to create the pool account a second time.
+      await expectError(initialize(USDC_MINT_PUBKEY), "already in use");
+    });
+
+    it("initializes a separate pool for another collateral mint", async () => {
+      await initialize(USDT_MINT_PUBKEY);
+
+      const usdtPool = poolPdas(USDT_MINT_PUBKEY);
+      assert.notEqual(usdtPool.insurancePool.toBase58(), usdcPool.insurancePool.toBase58());
+      const pool = fetch("insurancePool", usdtPool.insurancePool);
+      assert.strictEqual(pool.collateralMint.toBase58(), USDT_MINT_PUBKEY.toBase58());
+    });
+  });
+
+  describe("Add Tranche Tests", () => {
+    it("fails to give the first-loss tranche an attachment point", async () => {
+      await expectError(addTranche(SENIOR_ATTACHMENT_POINT_BPS, JUNIOR_PREMIUM_WEIGHT_BPS), "InvalidPoolConfig");
+    });
+
+    it("adds the first-loss tranche with its LP mint and vaults", async () => {
+      await addTranche(0, JUNIOR_PREMIUM_WEIGHT_BPS);
+
+      const tranche = fetchTranche(JUNIOR);
+      assert.strictEqual(tranche.index, JUNIOR);
+      assert.strictEqual(tranche.insurancePool.toBase58(), usdcPool.insurancePool.toBase58());
+      assert.strictEqual(tranche.lpMint.toBase58(), tranches[JUNIOR].lpMint.toBase58());
+      assert.strictEqual(tranche.collateralVault.toBase58(), tranches[JUNIOR].collateralVault.toBase58());
+      assert.strictEqual(tranche.stakedLpVault.toBase58(), tranches[JUNIOR].stakedLpVault.toBase58());
+      assert.ok(tranche.premiumWeightBps.eq(new BN(JUNIOR_PREMIUM_WEIGHT_BPS)));
+      assert.ok(tranche.attachmentPointBps.isZero());
+      assert.ok(tranche.lastCouponAccrualTimestamp.eq(new BN(now())));
+      assert.strictEqual(fetch("insurancePool", usdcPool.insurancePool).trancheCount, 1);
+
+      const vaultInfo = AccountLayout.decode(svm.getAccount(tranches[JUNIOR].collateralVault).data);
+      assert.strictEqual(vaultInfo.mint.toBase58(), USDC_MINT_PUBKEY.toBase58());
+      assert.strictEqual(vaultInfo.owner.toBase58(), poolAuthorityPda.toBase58());
+      assert.strictEqual(vaultInfo.amount, BigInt(0));
+      const mintInfo = MintLayout.decode(svm.getAccount(tranches[JUNIOR].lpMint).data);
+      assert.strictEqual(mintInfo.mintAuthority.toBase58(), poolAuthorityPda.toBase58());
+      assert.strictEqual(mintInfo.decimals, TOKEN_DECIMALS);
+      assert.strictEqual(mintInfo.supply, BigInt(0));
+    });
+
+    it("fails to add a tranche for anyone but the pool authority", async () => {
+      await expectError(
+        addTranche(SENIOR_ATTACHMENT_POINT_BPS, SENIOR_PREMIUM_WEIGHT_BPS, underwriter),
+        "UnauthorizedPoolAuthority"
       );
-      // console.log(`Distinct Test Mint created: ${distinctTestMintPublicKey.toBase58()}`);
     });
-    it("intialize collateral for Mint 1", async () => {
 
-      const [lpMintPda] = PublicKey.findProgramAddressSync(
-        [LP_MINT_SEED_BUF, distinctTestMintPublicKey.toBuffer()], program.programId
+    it("adds a senior tranche above the first-loss one", async () => {
+      await addTranche(SENIOR_ATTACHMENT_POINT_BPS, SENIOR_PREMIUM_WEIGHT_BPS);
+
+      const tranche = fetchTranche(SENIOR);
+      assert.strictEqual(tranche.index, SENIOR);
+      assert.ok(tranche.attachmentPointBps.eq(new BN(SENIOR_ATTACHMENT_POINT_BPS)));
+      assert.ok(tranche.targetAprBps.eqn(SENIOR_TARGET_APR_BPS));
+      assert.strictEqual(fetch("insurancePool", usdcPool.insurancePool).trancheCount, 2);
+
+      for (const index of [JUNIOR, SENIOR]) {
+        underwriterLpAtas[index] = createAta(tranches[index].lpMint, underwriter.publicKey);
+      }
+    });
+
+    it("fails to add a tranche attaching below the one beneath it", async () => {
+      await expectError(addTranche(SENIOR_ATTACHMENT_POINT_BPS - 1, 0), "InvalidPoolConfig");
+      assert.strictEqual(fetch("insurancePool", usdcPool.insurancePool).trancheCount, 2);
+    });
+  });
+
+  describe("Update Pool Config Tests", () => {
+    it("fails when called by anyone but the pool authority", async () => {
+      await expectError(
+        updatePoolConfig({ withdrawalCooldownSeconds: new BN(0) }, underwriter),
+        "UnauthorizedPoolAuthority"
+      );
+      await expectError(
+        updateTrancheConfig(SENIOR, { targetAprBps: new BN(100) }, underwriter),
+        "UnauthorizedPoolAuthority"
       );
-      const [collateralTokenPoolPda] = PublicKey.findProgramAddressSync(
-        [POOL_SEED_BUF, distinctTestMintPublicKey.toBuffer()], program.programId
+    });
+
+    it("fails to set a tranche APR above the maximum", async () => {
+      await expectError(
+        updateTrancheConfig(SENIOR, { targetAprBps: new BN(MAX_TARGET_APR_BPS + 1) }),
+        "InvalidPoolConfig"
       );
+    });
 
-      await program.methods.initialize()
-        .accounts({
-          authority: authoritySigner.publicKey,
-          lpMint: lpMintPda,
-          collateralTokenPool: collateralTokenPoolPda,
-          poolAuthority: poolAuthorityPda,
-          mint: distinctTestMintPublicKey,
-          systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
-        })
-        .rpc();
+    it("fails to set junior share bounds that cross", async () => {
+      await expectError(
+        updatePoolConfig({ minJuniorPremiumShareBps: new BN(9000), maxJuniorPremiumShareBps: new BN(5000) }),
+        "InvalidPoolConfig"
+      );
+    });
 
-      const lpMintInfo = await getMint(provider.connection, lpMintPda);
-      assert.strictEqual(lpMintInfo.mintAuthority.toBase58(), poolAuthorityPda.toBase58());
-      assert.strictEqual(lpMintInfo.decimals, TOKEN_DECIMALS);
-      assert.strictEqual(lpMintInfo.supply, BigInt(0));
+    it("switches the premium split mode and its bounds", async () => {
+      await updatePoolConfig({
+        premiumSplitMode: { dynamic: {} },
+        minJuniorPremiumShareBps: new BN(4000),
+        maxJuniorPremiumShareBps: new BN(8500),
+      });
+      let pool = fetch("insurancePool", usdcPool.insurancePool);
+      assert.deepEqual(pool.premiumSplitMode, { dynamic: {} });
+      assert.ok(pool.minJuniorPremiumShareBps.eqn(4000));
+      assert.ok(pool.maxJuniorPremiumShareBps.eqn(8500));
+
+      await updatePoolConfig({ premiumSplitMode: { seniorCoupon: {} } });
+      pool = fetch("insurancePool", usdcPool.insurancePool);
+      assert.deepEqual(pool.premiumSplitMode, { seniorCoupon: {} });
+    });
 
-      const collateralPoolInfo = await getAccount(provider.connection, collateralTokenPoolPda);
-      assert.strictEqual(collateralPoolInfo.mint.toBase58(), distinctTestMintPublicKey.toBase58());
-      assert.strictEqual(collateralPoolInfo.owner.toBase58(), poolAuthorityPda.toBase58());
-      assert.strictEqual(collateralPoolInfo.amount, BigInt(0));
-      // console.log("Initialize for Test Mint 1: Assertions passed.");
+    it("fails to count unlocked capital above its full value", async () => {
+      await expectError(updatePoolConfig({ unlockedCapitalWeightBps: new BN(10001) }), "InvalidPoolConfig");
     });
 
-    it("Fail to re-initialize for mint 1", async () => {
-      // console.log(`\nAttempting to re-initialize for testCollateralMint1PublicKey (should fail)...`);
-      const [lpMintPda] = PublicKey.findProgramAddressSync(
-        [LP_MINT_SEED_BUF, distinctTestMintPublicKey.toBuffer()], program.programId
-      );
-      const [collateralTokenPoolPda] = PublicKey.findProgramAddressSync(
-        [POOL_SEED_BUF, distinctTestMintPublicKey.toBuffer()], program.programId
+    it("fails to set a withdrawal cooldown above the maximum", async () => {
+      await expectError(
+        updatePoolConfig({ withdrawalCooldownSeconds: new BN(MAX_WITHDRAWAL_COOLDOWN_SECONDS + 1) }),
+        "InvalidPoolConfig"
       );
+    });
 
-      try {
-        await program.methods.initialize()
-          .accounts({
-            authority: authoritySigner.publicKey,
-            lpMint: lpMintPda,
-            collateralTokenPool: collateralTokenPoolPda,
-            poolAuthority: poolAuthorityPda,
-            mint: distinctTestMintPublicKey,
-            systemProgram: SystemProgram.programId,
-            tokenProgram: TOKEN_PROGRAM_ID,
-          })
-          .rpc();
-        assert.fail("Should have failed to re-initialize for the same collateral type.");
-      } catch (error) {
-        assert.isOk(error.message, "Already initialized");
-        // console.log("Re-initialize for Test Mint 1 failed as expected.");
-      }
+    it("fails to set an LP warm-up in compound mode", async () => {
+      // Compounded premiums reach warming LP tokens through the share price.
+      await expectError(
+        updatePoolConfig({ lpWarmupSeconds: new BN(WITHDRAWAL_COOLDOWN_SECONDS) }),
+        "InvalidPoolConfig"
+      );
     });
 
-    it("intialize for mint 2", async () => {
-      // console.log("\nInitializing for a new Test Mint 2...");
-      const testCollateralMint2Keypair = Keypair.generate();
-      const testCollateralMint2PublicKey = await splCreateMint(
-        provider.connection,
-        authoritySigner,
-        authoritySigner.publicKey,
-        null,
-        TOKEN_DECIMALS,
-        testCollateralMint2Keypair
+    it("fails to set an LP warm-up shorter than the withdrawal cooldown", async () => {
+      await expectError(
+        updatePoolConfig({
+          premiumDistributionMode: { claim: {} },
+          lpWarmupSeconds: new BN(WITHDRAWAL_COOLDOWN_SECONDS - 1),
+        }),
+        "InvalidPoolConfig"
       );
-      // console.log(`Test Collateral Mint 2 created: ${testCollateralMint2PublicKey.toBase58()}`);
+    });
+
+    it("fails to set a protocol fee above the maximum", async () => {
+      await expectError(updatePoolConfig({ protocolFeeBps: new BN(MAX_PROTOCOL_FEE_BPS + 1) }), "InvalidPoolConfig");
+    });
 
-      const [lpMintPdaForMint2] = PublicKey.findProgramAddressSync(
-        [LP_MINT_SEED_BUF, testCollateralMint2PublicKey.toBuffer()], program.programId
+    it("fails to set aside more than the maximum reserve share", async () => {
+      await expectError(
+        updatePoolConfig({ reservePremiumBps: new BN(MAX_RESERVE_PREMIUM_BPS + 1) }),
+        "InvalidPoolConfig"
       );
-      const [collateralTokenPoolPdaForMint2] = PublicKey.findProgramAddressSync(
-        [POOL_SEED_BUF, testCollateralMint2PublicKey.toBuffer()], program.programId
+    });
+
+    it("fails to allow a buyer more active policies than the maximum", async () => {
+      await expectError(
+        updatePoolConfig({ maxBuyerActivePolicies: new BN(MAX_BUYER_ACTIVE_POLICIES + 1) }),
+        "InvalidPoolConfig"
       );
+    });
 
-      await program.methods.initialize()
-        .accounts({
-          authority: authoritySigner.publicKey,
-          lpMint: lpMintPdaForMint2,
-          collateralTokenPool: collateralTokenPoolPdaForMint2,
-          poolAuthority: poolAuthorityPda,
-          mint: testCollateralMint2PublicKey,
-          systemProgram: SystemProgram.programId,
-          tokenProgram: TOKEN_PROGRAM_ID,
-        })
-        .rpc();
+    it("fails to attach a tranche above the whole pool", async () => {
+      await expectError(updateTrancheConfig(SENIOR, { attachmentPointBps: new BN(10001) }), "InvalidPoolConfig");
+    });
+
+    it("fails to attach the first-loss tranche above the senior one", async () => {
+      await expectError(updateTrancheConfig(JUNIOR, { attachmentPointBps: new BN(1000) }), "InvalidPoolConfig");
+    });
+
+    it("updates a tranche's target APR", async () => {
+      await updateTrancheConfig(SENIOR, { targetAprBps: new BN(1000) });
+      assert.ok(fetchTranche(SENIOR).targetAprBps.eqn(1000));
+
+      await updateTrancheConfig(SENIOR, { targetAprBps: new BN(SENIOR_TARGET_APR_BPS) });
+      assert.ok(fetchTranche(SENIOR).targetAprBps.eqn(SENIOR_TARGET_APR_BPS));
+    });
+  });
+
+  describe("Deposit Collateral Tests", () => {
+    it("mints junior LP 1:1 for the first junior deposit", async () => {
+      await depositCollateral(4000 * ONE_TOKEN, JUNIOR);
+
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].collateralVault), BigInt(4000 * ONE_TOKEN));
+      // Unlocked deposits are staked for the underwriter.
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].stakedLpVault), BigInt(4000 * ONE_TOKEN));
+      assert.strictEqual(tokenBalance(underwriterLpAtas[JUNIOR]), BigInt(0));
+      const junior = fetchTranche(JUNIOR);
+      assert.ok(junior.collateral.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(junior.lpSupply.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(junior.stakedLp.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(fetchTranche(SENIOR).collateral.isZero());
+      const position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(position.owner.equals(underwriter.publicKey));
+      assert.strictEqual(position.trancheIndex, JUNIOR);
+      assert.ok(position.lpBalance.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(position.depositedPrincipal.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(position.stakedLp.eq(new BN(4000 * ONE_TOKEN)));
+      assert.ok(position.avgEntrySharePrice.eq(SHARE_PRICE_PRECISION));
+    });
+
+    it("deposits into the senior tranche separately", async () => {
+      await depositCollateral(1000 * ONE_TOKEN, SENIOR);
 
-      const newLpMintInfo = await getMint(provider.connection, lpMintPdaForMint2);
-      assert.strictEqual(newLpMintInfo.mintAuthority.toBase58(), poolAuthorityPda.toBase58());
-      assert.strictEqual(newLpMintInfo.decimals, TOKEN_DECIMALS);
-      assert.strictEqual(newLpMintInfo.supply, BigInt(0));
+      assert.strictEqual(tokenBalance(tranches[SENIOR].collateralVault), BigInt(1000 * ONE_TOKEN));
+      assert.strictEqual(tokenBalance(tranches[SENIOR].stakedLpVault), BigInt(1000 * ONE_TOKEN));
+      const senior = fetchTranche(SENIOR);
+      assert.ok(senior.collateral.eq(new BN(1000 * ONE_TOKEN)));
+      assert.ok(senior.stakedLp.eq(new BN(1000 * ONE_TOKEN)));
+    });
+
+    it("fails to deposit zero", async () => {
+      await expectError(depositCollateral(0, JUNIOR), "DepositTooSmallToMintLp");
+    });
 
-      const newCollateralPoolInfo = await getAccount(provider.connection, collateralTokenPoolPdaForMint2);
-      assert.strictEqual(newCollateralPoolInfo.mint.toBase58(), testCollateralMint2PublicKey.toBase58());
-      assert.strictEqual(newCollateralPoolInfo.owner.toBase58(), poolAuthorityPda.toBase58());
-      assert.strictEqual(newCollateralPoolInfo.amount, BigInt(0));
-      // console.log("Initialize for Test Mint 2: Assertions passed.");
+    it("fails when the deposit mints fewer LP tokens than the minimum", async () => {
+      // The tranche is still priced at par, so one token mints one LP token.
+      await expectError(depositCollateral(ONE_TOKEN, SENIOR, ONE_TOKEN + 1), "SlippageExceeded");
+    });
 
-      const [lpMintPdaForMint1] = PublicKey.findProgramAddressSync(
-        [LP_MINT_SEED_BUF, distinctTestMintPublicKey.toBuffer()], program.programId
+    it("fails without every tranche of the pool", async () => {
+      await expectError(
+        run(
+          program.methods
+            .depositCollateral(new BN(ONE_TOKEN), new BN(0), SENIOR, null)
+            .accountsPartial({
+              underwriter: underwriter.publicKey,
+              insurancePool: usdcPool.insurancePool,
+              underwriterTokenAccount: underwriterUsdcAta,
+              ...tranches[SENIOR],
+              underwriterPosition: underwriterPositionPda(SENIOR),
+              lockedPosition: null,
+              poolAuthority: poolAuthorityPda,
+              collateralMint: USDC_MINT_PUBKEY,
+            })
+            .remainingAccounts(trancheMetas().slice(1)),
+          [underwriter]
+        ),
+        "InvalidTrancheAccounts"
       );
-      assert.notEqual(lpMintPdaForMint1.toBase58(), lpMintPdaForMint2.toBase58());
-      // console.log("Verification: PDAs for Test Mint 1 and Test Mint 2 are distinct.");
+    });
+
+    it("fails to deposit past the tranche or pool cap", async () => {
+      await updateTrancheConfig(SENIOR, { maxCollateral: new BN(1500 * ONE_TOKEN) });
+      await updatePoolConfig({ maxPoolCollateral: new BN(5200 * ONE_TOKEN) });
+
+      const capacity = await getDepositCapacity();
+      assert.ok(capacity.maxPoolCollateral.eq(new BN(5200 * ONE_TOKEN)));
+      const [juniorCapacity, seniorCapacity] = capacity.tranches;
+      assert.ok(seniorCapacity.collateral.eq(new BN(1000 * ONE_TOKEN)));
+      assert.ok(seniorCapacity.maxCollateral.eq(new BN(1500 * ONE_TOKEN)));
+      // The pool cap leaves less room than the senior cap.
+      assert.ok(seniorCapacity.depositCapacity.eq(new BN(200 * ONE_TOKEN)));
+      assert.ok(juniorCapacity.depositCapacity.eq(new BN(200 * ONE_TOKEN)));
+
+      await expectError(depositCollateral(200 * ONE_TOKEN + 1, JUNIOR), "DepositCapExceeded");
+      await updatePoolConfig({ maxPoolCollateral: NO_DEPOSIT_CAP });
+      await expectError(depositCollateral(500 * ONE_TOKEN + 1, SENIOR), "DepositCapExceeded");
+
+      await updateTrancheConfig(SENIOR, { maxCollateral: NO_DEPOSIT_CAP });
+      const reopened = await getDepositCapacity();
+      assert.ok(reopened.tranches[SENIOR].depositCapacity.gt(new BN(500 * ONE_TOKEN)));
+    });
+
+    it("fails a senior deposit that leaves too little junior capital beneath it", async () => {
+      // 4000 junior against 1000 senior is 80% junior; a 90% attachment point is already breached.
+      await updateTrancheConfig(SENIOR, { attachmentPointBps: new BN(9000) });
+      await expectError(depositCollateral(ONE_TOKEN, SENIOR), "JuniorRatioBelowMinimum");
+      await updateTrancheConfig(SENIOR, { attachmentPointBps: new BN(SENIOR_ATTACHMENT_POINT_BPS) });
     });
   });
 
-  describe("Create Policy Test", () => {
-    async function testCreatePolicyScenario({
-      testName,
-      premiumPaymentMint,
-      buyerPremiumAta,
-      targetCollateralPool,
-      insuredStablecoinOnPolicy,
-      insuredAmountLamports = new BN(200 * (10 ** TOKEN_DECIMALS)), // Default to ensure premium > 0
-      expectSuccess = false,
-    }: {
-      testName: string;
-      premiumPaymentMint: PublicKey;
-      buyerPremiumAta: PublicKey;
-      targetCollateralPool: PublicKey;
-      insuredStablecoinOnPolicy: PublicKey;
-      insuredAmountLamports?: BN;
-      expectSuccess?: boolean;
-    }) {
-      policyIdCounter++;
-      const currentPolicyId = new BN(policyIdCounter);
-      const expectedPremiumLamports = insuredAmountLamports.mul(new BN(PREMIUM_RATE_BPS_VAL)).div(new BN(10000));
-      const [policyAccountPda, policyAccountBump] = PublicKey.findProgramAddressSync(
-        [POLICY_SEED_BUF, buyer.publicKey.toBuffer(), currentPolicyId.toBuffer("le", 8)],
-        program.programId
+  describe("Locked Deposit Tests", () => {
+    const lockDuration = 30 * SECONDS_PER_DAY;
+
+    it("fails to lock for longer than the maximum duration", async () => {
+      await expectError(
+        depositCollateral(ONE_TOKEN, JUNIOR, 0, MAX_LOCK_DURATION_SECONDS + 1),
+        "InvalidLockDuration"
       );
+    });
 
-      // console.log(`\nRunning test: "${testName}" (Policy ID: ${currentPolicyId})...`);
-      // console.log(`  Insured Amount: ${insuredAmountLamports.toString()}, Expected Premium: ${expectedPremiumLamports.toString()}`);
-      // console.log(`  Premium Payment Mint: ${premiumPaymentMint.toBase58()}`);
-      // console.log(`  Buyer Premium ATA: ${buyerPremiumAta.toBase58()}`);
-      // console.log(`  Target Collateral Pool: ${targetCollateralPool.toBase58()}`);
-      // console.log(`  Insured Stablecoin on Policy: ${insuredStablecoinOnPolicy.toBase58()}`);
-
-      let buyerInitialBalance = BigInt(0);
-      let poolInitialBalance = BigInt(0);
-      try {
-        buyerInitialBalance = (await getAccount(connection, buyerPremiumAta)).amount;
-        poolInitialBalance = (await getAccount(connection, targetCollateralPool)).amount;
-        // console.log(`  Buyer balance before: ${buyerInitialBalance.toString()}, Pool balance before: ${poolInitialBalance.toString()}`);
-      } catch (e) {
-        console.warn(`  Could not fetch initial balances: ${e.message}`);
-      }
+    it("fails to lock without a position account", async () => {
+      await expectError(depositCollateral(ONE_TOKEN, JUNIOR, 0, lockDuration, null), "LockedPositionMismatch");
+    });
 
-      try {
-        await program.methods.createPolicy(insuredAmountLamports, currentPolicyId)
-          .accounts({
-            buyer: buyer.publicKey,
-            policyAccount: policyAccountPda,
-            buyerTokenAccount: buyerPremiumAta,
-            collateralTokenPool: targetCollateralPool,
-            mint: premiumPaymentMint,
-            insuredStablecoinMint: insuredStablecoinOnPolicy,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
-          .signers([buyer])
-          .rpc();
-        // console.log(`  RPC successful for "${testName}".`);
-
-        const policyAccountInfo = await program.account.policyAccount.fetch(policyAccountPda);
-        assert.strictEqual(policyAccountInfo.buyer.toBase58(), buyer.publicKey.toBase58(), "Buyer mismatch");
-        assert.ok(policyAccountInfo.policyId.eq(currentPolicyId), "Policy ID mismatch");
-        assert.strictEqual(policyAccountInfo.insuredStablecoinMint.toBase58(), insuredStablecoinOnPolicy.toBase58(), "Insured stablecoin mint mismatch");
-        assert.ok(policyAccountInfo.insuredAmount.eq(insuredAmountLamports), "Insured amount mismatch");
-        assert.ok(policyAccountInfo.premiumPaid.eq(expectedPremiumLamports), "Premium paid mismatch");
-        assert.strictEqual(policyAccountInfo.mint.toBase58(), premiumPaymentMint.toBase58(), "Policy.mint (premium currency) mismatch");
-
-        const expectedPayoutLamports = insuredAmountLamports.mul(new BN(BINARY_PAYOUT_BPS_VAL)).div(new BN(10000));
-        assert.ok(policyAccountInfo.payoutAmount.eq(expectedPayoutLamports), "Payout amount mismatch");
-        assert.isTrue(policyAccountInfo.startTimestamp.gtn(0), "Start timestamp invalid");
-        assert.isTrue(policyAccountInfo.expiryTimestamp.eq(policyAccountInfo.startTimestamp.add(new BN(POLICY_TERM_SECONDS))), "Expiry timestamp incorrect");
-        assert.deepStrictEqual(policyAccountInfo.status, { active: {} }, "Policy status should be Active");
-        assert.strictEqual(policyAccountInfo.bump, policyAccountBump, "Policy account bump mismatch");
-
-        if (expectedPremiumLamports.gtn(0)) { // Only check balance changes if premium was non-zero
-          const buyerFinalBalance = (await getAccount(connection, buyerPremiumAta)).amount;
-          const poolFinalBalance = (await getAccount(connection, targetCollateralPool)).amount;
-          assert.strictEqual(buyerInitialBalance - buyerFinalBalance, BigInt(expectedPremiumLamports.toString()), "Buyer premium deduction incorrect");
-          assert.strictEqual(poolFinalBalance - poolInitialBalance, BigInt(expectedPremiumLamports.toString()), "Pool premium addition incorrect");
-        }
+    it("locks a junior deposit with a boosted premium weight", async () => {
+      const stakedBefore = tokenBalance(tranches[JUNIOR].stakedLpVault);
+      lockedPositionId = fetch("insurancePool", usdcPool.insurancePool).lastLockedPositionId.addn(1);
+
+      await depositCollateral(1000 * ONE_TOKEN, JUNIOR, 0, lockDuration);
+
+      // The junior tranche is still at par, so the deposit mints 1000 LP tokens.
+      const lpAmount = 1000 * ONE_TOKEN;
+      const boostWeight = new BN(lpAmount)
+        .muln(LOCK_BOOST_BPS_PER_YEAR)
+        .muln(lockDuration)
+        .div(new BN(10000 * SECONDS_PER_YEAR));
+      const position = fetch("lockedPosition", lockedPositionPda(lockedPositionId));
+      assert.strictEqual(position.owner.toBase58(), underwriter.publicKey.toBase58());
+      assert.strictEqual(position.trancheIndex, JUNIOR);
+      assert.ok(position.lpAmount.eq(new BN(lpAmount)));
+      assert.ok(position.boostWeight.eq(boostWeight));
+      assert.ok(position.unlockTimestamp.eq(new BN(now() + lockDuration)));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].lockedLpVault), BigInt(lpAmount));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].stakedLpVault), stakedBefore);
+
+      const junior = fetchTranche(JUNIOR);
+      assert.ok(junior.lockedLp.eq(new BN(lpAmount)));
+      assert.ok(junior.boostWeight.eq(boostWeight));
+      // Locked LP tokens count as staked and earn claimable premiums too.
+      assert.ok(junior.stakedLp.eq(new BN(5000 * ONE_TOKEN)));
+      const underwriterPosition = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(underwriterPosition.stakedLp.eq(new BN(5000 * ONE_TOKEN)));
+      assert.ok(underwriterPosition.lockedLp.eq(new BN(lpAmount)));
+    });
 
-        // console.log(`  Assertions passed for "${testName}".`);
+    it("fails to unlock a position before its unlock time", async () => {
+      await expectError(unlockPosition(lockedPositionId, JUNIOR), "PositionStillLocked");
+    });
+  });
 
-      } catch (error) {
-        // console.error(`  Error in "${testName}":`, error.toString());
-        if (error.logs) {
-          // console.error("  Logs:", error.logs.join("\n"));
-        }
-        // const errorString = error.toString().toLowerCase();
-        // const logsString = error.logs ? error.logs.join(' ').toLowerCase() : "";
-
-        // if (expectSuccess && expectedPremiumLamports.eqn(0)) {
-        //   // If success was expected AND premium was 0, any error is unexpected.
-        //   assert.fail(`Test "${testName}" (expected full success with zero premium) failed unexpectedly: ${error.toString()}`);
-        // } else if (!expectSuccess && !errorString.includes("constraintraw") &&
-        //   (errorString.includes("0x1") || logsString.includes("insufficient funds") || (logsString.includes("spl_token") && logsString.includes("custom program error: 0x1")))) {
-        //   console.log(`  Test "${testName}" behaved as expected: Passed 'ConstraintRaw' on mint, and failed due to insufficient funds as buyer's Mainnet ATA was not funded for a non-zero premium.`);
-        // } else if (errorString.includes("constraintraw")) {
-        //   assert.fail(`Test "${testName}" failed with 'ConstraintRaw' on mint account. Check Rust constants and Anchor.toml cloning for ${premiumPaymentMint.toBase58()}. Error: ${error.toString()}`);
-        // } else {
-        //   assert.fail(`Test "${testName}" failed for an unexpected reason: ${error.toString()}`);
-        // }
-      }
-    }
+  describe("Stake LP Tests", () => {
+    it("unstakes LP tokens to the underwriter's wallet", async () => {
+      await unstakeLp(new BN(1000 * ONE_TOKEN), SENIOR);
+
+      assert.strictEqual(tokenBalance(underwriterLpAtas[SENIOR]), BigInt(1000 * ONE_TOKEN));
+      assert.strictEqual(tokenBalance(tranches[SENIOR].stakedLpVault), BigInt(0));
+      assert.ok(fetchTranche(SENIOR).stakedLp.isZero());
+      const position = fetch("underwriterPosition", underwriterPositionPda(SENIOR));
+      assert.ok(position.stakedLp.isZero());
+      // Unstaked LP tokens keep their cost basis.
+      assert.ok(position.lpBalance.eq(new BN(1000 * ONE_TOKEN)));
+    });
 
-    it("Create a policy insuring USDC", async () => {
-      await testCreatePolicyScenario({
-        testName: "USDC Insurance, Test USDC Premium",
-        premiumPaymentMint: testUsdcMintPublicKey,
-        buyerPremiumAta: buyerTestUsdcAta,
-        targetCollateralPool: usdcPoolCollateralPda,
-        insuredStablecoinOnPolicy: MAINNET_USDC_MINT_PUBKEY, // Insuring actual USDC
-        expectSuccess: true,
-      });
+    it("fails to unstake LP tokens held in a locked position", async () => {
+      await expectError(unstakeLp(new BN(4000 * ONE_TOKEN + 1), JUNIOR), "InsufficientStakedLp");
     });
 
-    it("Create a policy insuring USDT,Premium in USDC", async () => {
-      await testCreatePolicyScenario({
-        testName: "USDT Insurance, Test USDC Premium",
-        premiumPaymentMint: testUsdcMintPublicKey,
-        buyerPremiumAta: buyerTestUsdcAta,
-        targetCollateralPool: usdcPoolCollateralPda,
-        insuredStablecoinOnPolicy: MAINNET_USDT_MINT_PUBKEY,
-        expectSuccess: true,
-      });
+    it("stakes LP tokens back from the wallet", async () => {
+      await unstakeLp(new BN(10 * ONE_TOKEN), JUNIOR);
+      await stakeLp(new BN(9 * ONE_TOKEN), JUNIOR);
+
+      // One junior LP token stays in the wallet for the withdrawal tests.
+      assert.strictEqual(tokenBalance(underwriterLpAtas[JUNIOR]), BigInt(ONE_TOKEN));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].stakedLpVault), BigInt(3999 * ONE_TOKEN));
+      assert.ok(fetchTranche(JUNIOR).stakedLp.eq(new BN(4999 * ONE_TOKEN)));
     });
 
-    it("Create a policy insuring USDT", async () => {
-      await testCreatePolicyScenario({
-        testName: "USDT Insurance, Test USDT Premium",
-        premiumPaymentMint: testUsdtMintPublicKey,
-        buyerPremiumAta: buyerTestUsdtAta,
-        targetCollateralPool: usdcPoolCollateralPda,
-        insuredStablecoinOnPolicy: MAINNET_USDT_MINT_PUBKEY,
-        expectSuccess: true,
-      });
+    it("fails to stake LP tokens of another tranche", async () => {
+      await expectError(stakeLp(new BN(ONE_TOKEN), SENIOR, JUNIOR), "InvalidLpMint");
+    });
+  });
+
+  describe("Create Policy Tests", () => {
+    let firstPolicyId: BN;
+    let secondPolicyId: BN;
+
+    it("pays the accrued senior coupon first and the rest to junior", async () => {
+      const insuredAmount = 1000 * ONE_TOKEN;
+      const elapsed = 30 * SECONDS_PER_DAY;
+      setNow(now() + elapsed);
+      const juniorBefore = tokenBalance(tranches[JUNIOR].collateralVault);
+      const seniorBefore = tokenBalance(tranches[SENIOR].collateralVault);
+      const buyerBefore = tokenBalance(buyerUsdcAta);
+      const capacity = underwritingCapacity();
+
+      firstPolicyId = await createPolicy(insuredAmount, USDT_MINT_PUBKEY);
+
+      // Nothing else is insured, so the rate is the base rate plus this policy's own payout
+      // as a share of the pool's underwriting capacity.
+      const payout = (insuredAmount * BINARY_PAYOUT_BPS) / 10000;
+      const utilizationBps = new BN(payout).muln(10000).div(capacity).toNumber();
+      const premium = Math.floor((insuredAmount * (PREMIUM_RATE_BPS + utilizationBps)) / 10000);
+      const reserveCut = Math.floor((premium * RESERVE_PREMIUM_BPS) / 10000);
+      const seniorPremium = new BN(1000 * ONE_TOKEN)
+        .muln(SENIOR_TARGET_APR_BPS)
+        .muln(elapsed)
+        .div(new BN(10000 * SECONDS_PER_YEAR))
+        .toNumber();
+      const juniorPremium = premium - reserveCut - seniorPremium;
+      assert.strictEqual(buyerBefore - tokenBalance(buyerUsdcAta), BigInt(premium));
+      assert.strictEqual(tokenBalance(usdcPool.reserveVault), BigInt(reserveCut));
+      assert.ok(fetch("insurancePool", usdcPool.insurancePool).reserveBalance.eq(new BN(reserveCut)));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].collateralVault) - juniorBefore, BigInt(juniorPremium));
+      assert.strictEqual(tokenBalance(tranches[SENIOR].collateralVault) - seniorBefore, BigInt(seniorPremium));
+
+      // The premium is earned over the policy term, so none of it is in the tranches yet.
+      const junior = fetchTranche(JUNIOR);
+      const senior = fetchTranche(SENIOR);
+      assert.ok(junior.collateral.eq(new BN(5000 * ONE_TOKEN)));
+      assert.ok(senior.collateral.eq(new BN(1000 * ONE_TOKEN)));
+      assert.ok(junior.unearnedPremium.eq(new BN(juniorPremium)));
+      assert.ok(senior.unearnedPremium.eq(new BN(seniorPremium)));
+      assert.ok(senior.couponAccrued.eq(new BN(seniorPremium)));
+      assert.ok(senior.couponPaid.eq(new BN(seniorPremium)));
+      assert.ok(senior.couponShortfall.isZero());
+      assert.ok(fetch("insurancePool", usdcPool.insurancePool).totalInsuredValue.eq(new BN(insuredAmount)));
+
+      const policy = fetch("policyAccount", policyPda(firstPolicyId));
+      assert.strictEqual(policy.buyer.toBase58(), buyer.publicKey.toBase58());
+      assert.strictEqual(policy.insuredStablecoinMint.toBase58(), USDT_MINT_PUBKEY.toBase58());
+      assert.ok(policy.premiumPaid.eq(new BN(premium)));
+      assert.deepEqual(
+        policy.tranchePremiums.map((part: BN) => part.toNumber()),
+        [juniorPremium, seniorPremium]
+      );
+      assert.ok(policy.payoutAmount.eq(new BN((insuredAmount * BINARY_PAYOUT_BPS) / 10000)));
+      assert.ok(policy.expiryTimestamp.eq(new BN(now() + POLICY_TERM_SECONDS)));
+      assert.deepEqual(policy.status, { active: {} });
     });
 
-    //unhappy paths
-    it("Fail while Creating policy(mismatch in token Account", async () => {
-      policyIdCounter++;
-      const currentPolicyId = new BN(policyIdCounter);
-      const insuredAmountLamports = new BN(200 * (10 ** TOKEN_DECIMALS));
-      const premiumPaymentMint = testUsdtMintPublicKey; // Test USDT for premium
-      const buyerPremiumAta = buyerTestUsdtAta;
-      const targetCollateralPool = usdcPoolCollateralPda; // USDC Pool (Mismatched)
-      const insuredStablecoinOnPolicy = MAINNET_USDC_MINT_PUBKEY;
+    it("tracks the payout liability of every policy on the stablecoin", async () => {
+      // A day later, so the two policies expire on different days.
+      setNow(now() + SECONDS_PER_DAY);
+      secondPolicyId = await createPolicy(1000 * ONE_TOKEN, USDT_MINT_PUBKEY);
+
+      const policies = [firstPolicyId, secondPolicyId].map((id) => fetch("policyAccount", policyPda(id)));
+      assert.strictEqual(policies[1].mint.toBase58(), USDC_MINT_PUBKEY.toBase58());
 
-      const [policyAccountPda] = PublicKey.findProgramAddressSync(
-        [POLICY_SEED_BUF, buyer.publicKey.toBuffer(), currentPolicyId.toBuffer("le", 8)],
-        program.programId
+      const exposure = fetch("assetExposure", assetExposurePda(USDT_MINT_PUBKEY));
+      assert.ok(exposure.activePolicies.eqn(2));
+      assert.ok(exposure.activeLiability.eq(policies[0].payoutAmount.add(policies[1].payoutAmount)));
+      assert.ok(exposure.maxLiability.eq(NO_ASSET_LIABILITY_CAP));
+      assert.ok(exposure.maxLiabilityBps.eq(new BN(MAX_ASSET_LIABILITY_BPS)));
+    });
+
+    it("fails to insure the stablecoin the pool pays out in", async () => {
+      await expectError(createPolicy(1000 * ONE_TOKEN, USDC_MINT_PUBKEY), "WrongWayRisk");
+    });
+
+    it("fails to set asset limits for anyone but the pool authority", async () => {
+      await expectError(
+        updateAssetExposureLimits(USDT_MINT_PUBKEY, NO_ASSET_LIABILITY_CAP, 10000, underwriter),
+        "UnauthorizedPoolAuthority"
+      );
+      await expectError(
+        updateAssetExposureLimits(USDT_MINT_PUBKEY, NO_ASSET_LIABILITY_CAP, 10001),
+        "InvalidPoolConfig"
       );
+    });
 
-      // console.log(`\nRunning test: "Mismatched Premium Mint and Pool (Test USDT premium to Test USDC pool)" (should fail)...`);
-      try {
-        await program.methods.createPolicy(insuredAmountLamports, currentPolicyId)
-          .accounts({
-            buyer: buyer.publicKey,
-            policyAccount: policyAccountPda,
-            buyerTokenAccount: buyerPremiumAta,
-            collateralTokenPool: targetCollateralPool,
-            mint: premiumPaymentMint,
-            insuredStablecoinMint: insuredStablecoinOnPolicy,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
-          .signers([buyer])
-          .rpc();
-        assert.fail("Should have failed due to premium mint not matching collateral pool's mint.");
-      } catch (error) {
-        assert.isOk(error.message, "ConstraintRaw error");
-      }
+    it("caps the payout liability the pool takes on one stablecoin", async () => {
+      const exposure = fetch("assetExposure", assetExposurePda(USDT_MINT_PUBKEY));
+      const maxLiability = exposure.activeLiability.add(new BN(50 * ONE_TOKEN));
+      await updateAssetExposureLimits(USDT_MINT_PUBKEY, maxLiability, MAX_ASSET_LIABILITY_BPS);
+
+      // A 500 USDT policy pays out 50, which just fits; a hair more cover doesn't.
+      await expectError(createPolicy(500 * ONE_TOKEN + 10, USDT_MINT_PUBKEY), "AssetExposureLimitExceeded");
+      const policyId = await createPolicy(500 * ONE_TOKEN, USDT_MINT_PUBKEY);
+      assert.ok(fetch("assetExposure", assetExposurePda(USDT_MINT_PUBKEY)).activeLiability.eq(maxLiability));
+
+      await cancelPolicy(policyId);
+      await updateAssetExposureLimits(USDT_MINT_PUBKEY, NO_ASSET_LIABILITY_CAP, MAX_ASSET_LIABILITY_BPS);
     });
 
-    it("Fail for unsupported Mint to insure", async () => {
-      policyIdCounter++;
-      const currentPolicyId = new BN(policyIdCounter);
-      const insuredAmountLamports = new BN(200 * (10 ** TOKEN_DECIMALS));
-      const premiumPaymentMint = testUsdcMintPublicKey;
-      const buyerPremiumAta = buyerTestUsdcAta;
-      const targetCollateralPool = usdcPoolCollateralPda;
+    it("caps the cover and the active policies of a single buyer", async () => {
+      const profile = fetch("buyerProfile", buyerProfilePda());
+      assert.strictEqual(profile.buyer.toBase58(), buyer.publicKey.toBase58());
+      assert.deepEqual(
+        profile.activePolicyIds.map((id: BN) => id.toNumber()),
+        [firstPolicyId.toNumber(), secondPolicyId.toNumber()]
+      );
+      assert.ok(profile.activeInsuredAmount.eq(new BN(2000 * ONE_TOKEN)));
+
+      await updatePoolConfig({ maxBuyerInsuredAmount: profile.activeInsuredAmount.add(new BN(100 * ONE_TOKEN)) });
+      await expectError(createPolicy(100 * ONE_TOKEN + 1, USDT_MINT_PUBKEY), "BuyerLimitExceeded");
+      await updatePoolConfig({ maxBuyerInsuredAmount: NO_BUYER_COVERAGE_CAP, maxBuyerActivePolicies: new BN(2) });
+      await expectError(createPolicy(100 * ONE_TOKEN, USDT_MINT_PUBKEY), "BuyerLimitExceeded");
+
+      await updatePoolConfig({ maxBuyerActivePolicies: new BN(MAX_BUYER_ACTIVE_POLICIES) });
+    });
+
+    it("reports when the capital backing the policies is released", async () => {
+      const policies = [firstPolicyId, secondPolicyId].map((id) => fetch("policyAccount", policyPda(id)));
+      const totalLiability = policies.reduce((total, policy) => total.add(policy.payoutAmount), new BN(0));
+
+      const schedule = await getCapitalReleaseSchedule();
 
-      const unsupportedStablecoinMintKeypair = Keypair.generate();
-      const unsupportedStablecoinMint = await splCreateMint(
-        provider.connection, authoritySigner, authoritySigner.publicKey, null, TOKEN_DECIMALS, unsupportedStablecoinMintKeypair
+      assert.ok(schedule.totalLiability.eq(totalLiability));
+      assert.ok(schedule.overdueLiability.isZero());
+      // Each policy's capital frees up once its expiry day is over.
+      assert.strictEqual(schedule.releases.length, 2);
+      [firstPolicyId, secondPolicyId].forEach((policyId, index) => {
+        const releaseTimestamp = new BN((expiryDay(policyId) + 1) * SECONDS_PER_DAY);
+        assert.ok(schedule.releases[index].releaseTimestamp.eq(releaseTimestamp));
+        assert.ok(schedule.releases[index].liability.eq(policies[index].payoutAmount));
+      });
+    });
+
+    it("cancels a policy and refunds the premium for the rest of its term", async () => {
+      const insuredAmount = new BN(1000 * ONE_TOKEN);
+      const policyId = await createPolicy(insuredAmount.toNumber(), USDT_MINT_PUBKEY);
+      const policy = fetch("policyAccount", policyPda(policyId));
+      const poolBefore = fetch("insurancePool", usdcPool.insurancePool);
+      setNow(now() + POLICY_TERM_SECONDS / 4);
+      const buyerBefore = tokenBalance(buyerUsdcAta);
+
+      await cancelPolicy(policyId);
+
+      // LPs keep the quarter of the premium earned while the policy was covered.
+      const refund = policy.tranchePremiums.reduce((total: BN, part: BN) => total.add(part.muln(3).divn(4)), new BN(0));
+      assert.strictEqual(tokenBalance(buyerUsdcAta) - buyerBefore, BigInt(refund.toString()));
+      assert.deepEqual(fetch("policyAccount", policyPda(policyId)).status, { cancelled: {} });
+      const pool = fetch("insurancePool", usdcPool.insurancePool);
+      assert.ok(pool.totalInsuredValue.eq(poolBefore.totalInsuredValue.sub(insuredAmount)));
+      const profile = fetch("buyerProfile", buyerProfilePda());
+      assert.notInclude(
+        profile.activePolicyIds.map((id: BN) => id.toNumber()),
+        policyId.toNumber()
       );
-      const [policyAccountPda] = PublicKey.findProgramAddressSync(
-        [POLICY_SEED_BUF, buyer.publicKey.toBuffer(), currentPolicyId.toBuffer("le", 8)],
-        program.programId
+      assert.ok(profile.activeInsuredAmount.eq(new BN(2000 * ONE_TOKEN)));
+
+      await expectError(cancelPolicy(policyId), "PolicyAlreadyProcessed");
+    });
+
+    it("fails to insure an unsupported stablecoin", async () => {
+      const otherMint = Keypair.generate().publicKey;
+      injectMint(otherMint);
+      await expectError(createPolicy(1000 * ONE_TOKEN, otherMint), "UnsupportedStablecoinMint");
+    });
+
+    it("fails to take a premium in anything but USDC", async () => {
+      createAta(USDT_MINT_PUBKEY, buyer.publicKey);
+      await expectError(
+        createPolicy(1000 * ONE_TOKEN, USDC_MINT_PUBKEY, USDT_MINT_PUBKEY),
+        "UnsupportedStablecoinMint"
       );
+    });
 
-      // console.log(`\nRunning test: "Unsupported Insured Stablecoin" (should fail if Rust validation exists)...`);
-      try {
-        await program.methods.createPolicy(insuredAmountLamports, currentPolicyId)
-          .accounts({
-            buyer: buyer.publicKey,
-            policyAccount: policyAccountPda,
-            buyerTokenAccount: buyerPremiumAta,
-            collateralTokenPool: targetCollateralPool,
-            mint: premiumPaymentMint,
-            insuredStablecoinMint: unsupportedStablecoinMint,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-          })
-          .signers([buyer])
-          .rpc();
-        assert.fail("Should have failed due to unsupported insured_stablecoin_mint.");
-      } catch (error) {
-        assert.isOk(error.message, "ConstraintRaw error");
-      }
+    describe("Check And Payout Tests", () => {
+      it("fails to settle a policy before it expires", async () => {
+        await expectError(checkAndPayout(firstPolicyId), "PolicyNotExpired");
+      });
+
+      it("fails to cancel a policy once it has expired", async () => {
+        setNow(now() + POLICY_TERM_SECONDS);
+        await expectError(cancelPolicy(firstPolicyId), "PolicyExpired");
+      });
+
+      it("fails to record a price before the expiry day is over", async () => {
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000);
+        const today = Math.floor(now() / SECONDS_PER_DAY);
+        await expectError(
+          recordPriceObservation(USDT_MINT_PUBKEY, today, usdtPriceUpdate),
+          "ObservationWindowNotClosed"
+        );
+      });
+
+      it("fails to settle a policy before its day's price is recorded", async () => {
+        await expectError(checkAndPayout(firstPolicyId), "PriceObservationRequired");
+      });
+
+      it("fails to record a price published outside the window's close", async () => {
+        const day = expiryDay(firstPolicyId);
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000, windowEnd(day) - 1);
+        await expectError(
+          recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate),
+          "OraclePriceOutsideWindow"
+        );
+        // Nor a price published more than a day after the window closed.
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000, windowEnd(day) + SECONDS_PER_DAY + 1);
+        await expectError(
+          recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate),
+          "OraclePriceOutsideWindow"
+        );
+      });
+
+      it("fails to record a price with a confidence interval wider than 2%", async () => {
+        const day = expiryDay(firstPolicyId);
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000, windowEnd(day), 2_000_001);
+        await expectError(
+          recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate),
+          "OracleConfidenceTooWide"
+        );
+      });
+
+      it("closes a policy without payout while its stablecoin holds the peg", async () => {
+        // The last update the window accepts, with a confidence interval too wide for a
+        // policy quote but within what a recorded price allows.
+        const day = expiryDay(firstPolicyId);
+        const publishTime = windowEnd(day) + SECONDS_PER_DAY;
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000, publishTime, 2_000_000);
+        await recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate);
+        const depegEvent = fetch("depegEvent", depegEventPda(USDT_MINT_PUBKEY, day));
+        assert.ok(depegEvent.expiryDay.eq(new BN(day)));
+        assert.ok(depegEvent.observedPrice.eq(new BN(100_000_000)));
+        assert.ok(depegEvent.observedTimestamp.eq(new BN(publishTime)));
+        assert.isFalse(depegEvent.depegged);
+        await expectError(recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate), "already in use");
+        const buyerBefore = tokenBalance(buyerUsdcAta);
+
+        await checkAndPayout(firstPolicyId);
+
+        assert.strictEqual(tokenBalance(buyerUsdcAta), buyerBefore);
+        const policy = fetch("policyAccount", policyPda(firstPolicyId));
+        assert.deepEqual(policy.status, { expiredNotPaid: {} });
+        // Both policies have run their whole term, so their premiums have been released to
+        // the tranches, less rounding dust, and the locked position has its boost slice.
+        const junior = fetchTranche(JUNIOR);
+        const senior = fetchTranche(SENIOR);
+        assert.ok(junior.unearnedPremium.lten(2));
+        assert.ok(senior.unearnedPremium.lten(2));
+        assert.ok(senior.collateral.gt(new BN(1000 * ONE_TOKEN)));
+        assert.ok(junior.boostRewards.gtn(0));
+      });
+
+      it("fails to pay a depegged policy before its settlement round opens", async () => {
+        const day = expiryDay(secondPolicyId);
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 95_000_000, windowEnd(day));
+        await recordPriceObservation(USDT_MINT_PUBKEY, day, usdtPriceUpdate);
+        await expectError(checkAndPayout(secondPolicyId), "SettlementRoundRequired");
+      });
+
+      it("pays a depegged policy through its round, out of the reserve, then the junior tranche", async () => {
+        const day = expiryDay(secondPolicyId);
+        // The price has recovered by the time the keeper settles, but the day's recorded
+        // price stands.
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000);
+        const payout = new BN((1000 * ONE_TOKEN * BINARY_PAYOUT_BPS) / 10000);
+        const reserveBefore = fetch("insurancePool", usdcPool.insurancePool).reserveBalance;
+        assert.ok(reserveBefore.gtn(0) && reserveBefore.lt(payout));
+        const buyerBefore = tokenBalance(buyerUsdcAta);
+        const juniorBefore = tokenBalance(tranches[JUNIOR].collateralVault);
+        const seniorBefore = tokenBalance(tranches[SENIOR].collateralVault);
+
+        await openSettlementRound(USDT_MINT_PUBKEY, day);
+        await checkAndPayout(secondPolicyId, settlementRoundPda(USDT_MINT_PUBKEY, day));
+
+        assert.strictEqual(tokenBalance(buyerUsdcAta) - buyerBefore, BigInt(payout.toString()));
+        assert.strictEqual(tokenBalance(usdcPool.reserveVault), BigInt(0));
+        assert.strictEqual(
+          juniorBefore - tokenBalance(tranches[JUNIOR].collateralVault),
+          BigInt(payout.sub(reserveBefore).toString())
+        );
+        assert.strictEqual(tokenBalance(tranches[SENIOR].collateralVault), seniorBefore);
+        const policy = fetch("policyAccount", policyPda(secondPolicyId));
+        assert.deepEqual(policy.status, { expiredPaid: {} });
+        const pool = fetch("insurancePool", usdcPool.insurancePool);
+        assert.ok(pool.totalInsuredValue.isZero());
+        assert.ok(pool.reserveBalance.isZero());
+        assert.ok(pool.settlementFund.isZero());
+        const profile = fetch("buyerProfile", buyerProfilePda());
+        assert.isEmpty(profile.activePolicyIds);
+        assert.ok(profile.activeInsuredAmount.isZero());
+      });
+
+      it("fails to settle a policy twice", async () => {
+        await expectError(checkAndPayout(secondPolicyId), "PolicyAlreadyProcessed");
+      });
+    });
+
+    describe("Settlement Round Tests", () => {
+      const roundPolicyIds: BN[] = [];
+      let roundDay: number;
+      let roundPda: PublicKey;
+      let laterPolicyId: BN;
+
+      it("fails to open a round for a day with no policies on the stablecoin", async () => {
+        // Move to the end of today, so its price can be recorded right as its window closes.
+        const today = Math.floor(now() / SECONDS_PER_DAY);
+        setNow(windowEnd(today));
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 95_000_000);
+        await recordPriceObservation(USDT_MINT_PUBKEY, today, usdtPriceUpdate);
+        await expectError(openSettlementRound(USDT_MINT_PUBKEY, today), "NoLiabilityToSettle");
+      });
+
+      it("fails to open a round for a day without a recorded price", async () => {
+        roundPolicyIds.push(await createPolicy(1000 * ONE_TOKEN, USDT_MINT_PUBKEY));
+        roundPolicyIds.push(await createPolicy(500 * ONE_TOKEN, USDT_MINT_PUBKEY));
+        roundDay = expiryDay(roundPolicyIds[0]);
+        roundPda = settlementRoundPda(USDT_MINT_PUBKEY, roundDay);
+        // Expires two days after the round's policies, so it stays out of the round.
+        setNow(now() + 2 * SECONDS_PER_DAY);
+        laterPolicyId = await createPolicy(100 * ONE_TOKEN, USDT_MINT_PUBKEY);
+
+        setNow(windowEnd(roundDay));
+        await expectError(openSettlementRound(USDT_MINT_PUBKEY, roundDay), "AccountNotInitialized");
+      });
+
+      it("fails to open a round for a day its stablecoin held the peg", async () => {
+        await expectError(openSettlementRound(USDT_MINT_PUBKEY, expiryDay(firstPolicyId)), "NoDepegDetected");
+      });
+
+      it("opens a round owing every policy expiring on the depegged day", async () => {
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 95_000_000);
+        await recordPriceObservation(USDT_MINT_PUBKEY, roundDay, usdtPriceUpdate);
+        const poolLiability = (await getCapitalReleaseSchedule()).totalLiability;
+        const reserveBefore = fetch("insurancePool", usdcPool.insurancePool).reserveBalance;
+
+        await openSettlementRound(USDT_MINT_PUBKEY, roundDay);
+
+        const payouts = roundPolicyIds.map((id) => fetch("policyAccount", policyPda(id)).payoutAmount);
+        const round = fetch("settlementRound", roundPda);
+        assert.ok(round.expiryDay.eq(new BN(roundDay)));
+        assert.ok(round.depegPrice.eq(new BN(95_000_000)));
+        assert.ok(round.totalLiability.eq(payouts[0].add(payouts[1])));
+        // The pool also owes the later policy, but the round is only measured against what
+        // it owes itself, which the pool covers many times over.
+        assert.ok(poolLiability.gt(round.totalLiability));
+        assert.ok(round.availableCollateral.gt(round.totalLiability));
+        assert.ok(round.recoveryRatioBps.eqn(10000));
+        // The payouts are set aside, out of the reserve first.
+        assert.ok(round.setAside.eq(round.totalLiability));
+        const pool = fetch("insurancePool", usdcPool.insurancePool);
+        assert.ok(pool.settlementFund.eq(round.setAside));
+        assert.ok(pool.reserveBalance.eq(BN.max(reserveBefore.sub(round.setAside), new BN(0))));
+        const schedule = await getCapitalReleaseSchedule();
+        assert.ok(schedule.totalLiability.eq(poolLiability.sub(round.totalLiability)));
+      });
+
+      it("fails to open a second round for the same day", async () => {
+        await expectError(openSettlementRound(USDT_MINT_PUBKEY, roundDay), "already in use");
+      });
+
+      it("fails to pay a policy in the round without the round", async () => {
+        await expectError(checkAndPayout(roundPolicyIds[0]), "InvalidSettlementRound");
+      });
+
+      it("pays every policy in the round its recovery ratio and closes the round", async () => {
+        // The peg is back by the time the keeper gets to the policies, but the round
+        // already confirmed the depeg.
+        setPriceUpdate(usdtPriceUpdate, PYTH_USDT_USD_FEED_ID, 100_000_000);
+        for (const policyId of roundPolicyIds) {
+          const payout = fetch("policyAccount", policyPda(policyId)).payoutAmount;
+          const buyerBefore = tokenBalance(buyerUsdcAta);
+
+          await checkAndPayout(policyId, roundPda);
+
+          assert.strictEqual(tokenBalance(buyerUsdcAta) - buyerBefore, BigInt(payout.toString()));
+          assert.deepEqual(fetch("policyAccount", policyPda(policyId)).status, { expiredPaid: {} });
+        }
+
+        const round = fetch("settlementRound", roundPda);
+        assert.ok(round.settledLiability.eq(round.totalLiability));
+        assert.ok(round.paidOut.eq(round.totalLiability));
+        assert.ok(fetch("insurancePool", usdcPool.insurancePool).settlementFund.isZero());
+        const laterPayout = fetch("policyAccount", policyPda(laterPolicyId)).payoutAmount;
+        const exposure = fetch("assetExposure", assetExposurePda(USDT_MINT_PUBKEY));
+        assert.ok(exposure.activeLiability.eq(laterPayout));
+        assert.ok(exposure.activePolicies.eqn(1));
+
+        await cancelPolicy(laterPolicyId);
+      });
     });
   });
 
-  describe("Deposit collateral Test", () => {
-    it("Allow to deposit in collateral", async () => {
-      const depositAmount = new BN(1000 * (10 ** TOKEN_DECIMALS));
+  describe("Claim Premiums Tests", () => {
+    it("releases premiums to staked LP tokens over the policy term in claim mode", async () => {
+      await updatePoolConfig({ premiumDistributionMode: { claim: {} } });
+      const juniorBefore = fetchTranche(JUNIOR);
 
-      const initialLPSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const initialPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterInitialCollateral = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-      const initialUnderwriterLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
+      const policyId = await createPolicy(100 * ONE_TOKEN, USDT_MINT_PUBKEY);
 
-      await program.methods.depositCollateral(depositAmount)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterTokenAccount: underwriterTestUsdcAta,
-          underwriterLpTokenAccount: underwriterLpUsdcAta,
-          collateralTokenPool: usdcPoolCollateralPda,
-          lpMint: usdcPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdcMintPublicKey,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        })
-        .signers([underwriter])
-        .rpc();
-
-
-      const finalLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const finalPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterFinalCollateral = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-      const underwriterLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-
-
-      const expectedLpMinted = depositAmount;
-      assert.ok(finalLpSupply === initialLPSupply + BigInt(expectedLpMinted.toString()), "LP supply incorrect after first deposit");
-      assert.ok(finalPoolBalance === initialPoolBalance + BigInt(depositAmount.toString()), "Pool balance incorrect after first deposit");
-      assert.ok(underwriterFinalCollateral === underwriterInitialCollateral - BigInt(depositAmount.toString()), "Underwriter collateral debited incorrectly");
-      assert.ok(underwriterLpBalance === initialUnderwriterLpBalance + BigInt(expectedLpMinted.toString()), "Underwriter LP token balance incorrect after first deposit");
-      // console.log("First Test USDC Deposit: Assertions passed!");
-
-    })
-
-    it("Multiple deposits into collateral (USDC)", async () => {
-      const depositAmountSubsequent = new BN(500 * (10 ** TOKEN_DECIMALS));
-
-      const initialLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const initialPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterInitialCollateral = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-      const underwriterInitialLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-
-      let expectedLpMintedSubsequent: BN;
-      if (initialLpSupply === BigInt(0) || initialPoolBalance === BigInt(0)) {
-        console.warn("Warning: Pool seems empty or has no LP supply before subsequent deposit. LP calculation might be 1:1.");
-        expectedLpMintedSubsequent = depositAmountSubsequent;
-      } else {
-        const lpToMintU128 = new BN(depositAmountSubsequent.toString())
-          .mul(new BN(initialLpSupply.toString()))
-          .div(new BN(initialPoolBalance.toString()));
-        expectedLpMintedSubsequent = new BN(lpToMintU128.toString());
-      }
+      assert.deepEqual(fetch("insurancePool", usdcPool.insurancePool).premiumDistributionMode, { claim: {} });
+      const policy = fetch("policyAccount", policyPda(policyId));
+      let junior = fetchTranche(JUNIOR);
+      assert.ok(junior.unearnedPremium.eq(juniorBefore.unearnedPremium.add(policy.tranchePremiums[JUNIOR])));
+      assert.ok(junior.unclaimedPremiums.eq(juniorBefore.unclaimedPremiums));
 
-      // console.log(`  Expected LP to mint (approx): ${expectedLpMintedSubsequent.toString()}`);
+      setNow(now() + POLICY_TERM_SECONDS);
+      await releasePremiums();
 
-      await program.methods.depositCollateral(depositAmountSubsequent)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterTokenAccount: underwriterTestUsdcAta,
-          underwriterLpTokenAccount: underwriterLpUsdcAta,
-          collateralTokenPool: usdcPoolCollateralPda,
-          lpMint: usdcPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdcMintPublicKey,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        })
-        .signers([underwriter])
-        .rpc();
-
-      const finalLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const finalPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterFinalCollateral = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-      const underwriterLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-
-      assert.ok(finalLpSupply === initialLpSupply + BigInt(expectedLpMintedSubsequent.toString()), "LP supply incorrect after subsequent deposit");
-      assert.ok(finalPoolBalance === initialPoolBalance + BigInt(depositAmountSubsequent.toString()), "Pool balance incorrect after subsequent deposit");
-      assert.ok(underwriterFinalCollateral === underwriterInitialCollateral - BigInt(depositAmountSubsequent.toString()), "Underwriter collateral debited incorrectly for subsequent deposit");
-      assert.ok(underwriterLpBalance === underwriterInitialLpBalance + BigInt(expectedLpMintedSubsequent.toString()), "Underwriter LP token balance incorrect after subsequent deposit");
-    });
-
-    it("should fail to deposit zero amount", async () => {
-      const depositAmountZero = new BN(0);
-      try {
-        await program.methods.depositCollateral(depositAmountZero)
-          .accounts({
-            underwriter: underwriter.publicKey,
-            underwriterTokenAccount: underwriterTestUsdcAta,
-            underwriterLpTokenAccount: underwriterLpUsdcAta,
-            collateralTokenPool: usdcPoolCollateralPda,
-            lpMint: usdcPoolLpMintPda,
-            poolAuthority: poolAuthorityPda,
-            mint: testUsdcMintPublicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-          })
-          .signers([underwriter])
-          .rpc();
-        assert.fail("Should have failed to deposit zero amount.");
-      } catch (error) {
-        assert.isOk(error.message, "Deposit amount must be greater than zero");
-      }
+      junior = fetchTranche(JUNIOR);
+      assert.ok(junior.unclaimedPremiums.gt(juniorBefore.unclaimedPremiums));
+      assert.ok(junior.premiumPerLpShare.gt(juniorBefore.premiumPerLpShare));
+      // All senior LP tokens are unstaked, so the senior premium still compounds.
+      assert.ok(fetchTranche(SENIOR).unclaimedPremiums.isZero());
     });
 
-    it("Deposit collateral in USDT", async () => {
-      const depositAmount = new BN(1000 * (10 ** TOKEN_DECIMALS));
+    it("pays the claimable premiums without touching the LP tokens", async () => {
+      const claimable = claimablePremiums(JUNIOR);
+      assert.ok(claimable.gtn(0));
+      const juniorBefore = fetchTranche(JUNIOR);
+      const usdcBefore = tokenBalance(underwriterUsdcAta);
+      const stakedBefore = tokenBalance(tranches[JUNIOR].stakedLpVault);
+
+      await claimPremiums(JUNIOR);
+
+      assert.strictEqual(tokenBalance(underwriterUsdcAta) - usdcBefore, BigInt(claimable.toString()));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].stakedLpVault), stakedBefore);
+      const junior = fetchTranche(JUNIOR);
+      assert.ok(junior.unclaimedPremiums.eq(juniorBefore.unclaimedPremiums.sub(claimable)));
+      assert.ok(junior.collateral.eq(juniorBefore.collateral));
+      const position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(position.premiumsOwed.isZero());
+      assert.ok(position.premiumsClaimed.eq(claimable));
+    });
 
-      const initialLPSupply = (await getMint(connection, usdtPoolLpMintPda)).supply;
-      const initialPoolBalance = (await getAccount(connection, usdtPoolCollateralPda)).amount;
-      const underwriterInitialCollateral = (await getAccount(connection, underwriterTestUsdtAta)).amount;
-      const initialUnderwriterLpBalance = (await getAccount(connection, underwriterLpUsdtAta)).amount;
+    it("fails to claim when nothing is owed", async () => {
+      await expectError(claimPremiums(JUNIOR), "NoPremiumsToClaim");
+    });
 
-      await program.methods.depositCollateral(depositAmount)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterTokenAccount: underwriterTestUsdtAta,
-          underwriterLpTokenAccount: underwriterLpUsdtAta,
-          collateralTokenPool: usdtPoolCollateralPda,
-          lpMint: usdtPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdtMintPublicKey,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        })
-        .signers([underwriter])
-        .rpc();
+    it("counts claimed premiums as realized PnL", async () => {
+      const position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      const pnl = await getUnderwriterPnl(JUNIOR);
+      assert.ok(pnl.premiumsClaimed.eq(position.premiumsClaimed));
+      assert.ok(pnl.premiumsClaimable.isZero());
+      assert.ok(pnl.realizedPnl.eq(position.realizedPnl.add(position.premiumsClaimed)));
+    });
 
+    it("fails to unstake LP tokens still warming up", async () => {
+      await updatePoolConfig({ lpWarmupSeconds: new BN(WITHDRAWAL_COOLDOWN_SECONDS) });
+      await unstakeLp(new BN(ONE_TOKEN), JUNIOR);
+      await stakeLp(new BN(ONE_TOKEN), JUNIOR);
+
+      let position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(position.warmupLp.eq(new BN(ONE_TOKEN)));
+      assert.ok(position.warmupEndTimestamp.eq(new BN(now() + WITHDRAWAL_COOLDOWN_SECONDS)));
+      // Everything that is neither locked nor warming can still leave.
+      const unstakeable = position.stakedLp.sub(position.lockedLp).sub(position.warmupLp);
+      await expectError(unstakeLp(unstakeable.addn(1), JUNIOR), "InsufficientStakedLp");
+
+      const stakedBefore = position.stakedLp;
+      setNow(now() + WITHDRAWAL_COOLDOWN_SECONDS);
+      await unstakeLp(new BN(ONE_TOKEN), JUNIOR);
+      position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(position.stakedLp.eq(stakedBefore.subn(ONE_TOKEN)));
+    });
 
-      const finalLpSupply = (await getMint(connection, usdtPoolLpMintPda)).supply;
-      const finalPoolBalance = (await getAccount(connection, usdtPoolCollateralPda)).amount;
-      const underwriterFinalCollateral = (await getAccount(connection, underwriterTestUsdtAta)).amount;
-      const underwriterLpBalance = (await getAccount(connection, underwriterLpUsdtAta)).amount;
+    it("fails to switch back to compound mode while a warm-up is set", async () => {
+      await expectError(updatePoolConfig({ premiumDistributionMode: { compound: {} } }), "InvalidPoolConfig");
 
+      await updatePoolConfig({ premiumDistributionMode: { compound: {} }, lpWarmupSeconds: new BN(0) });
+      await stakeLp(new BN(ONE_TOKEN), JUNIOR);
+      assert.deepEqual(fetch("insurancePool", usdcPool.insurancePool).premiumDistributionMode, { compound: {} });
+    });
+  });
 
-      const expectedLpMinted = depositAmount;
-      assert.ok(finalLpSupply === initialLPSupply + BigInt(expectedLpMinted.toString()), "LP supply incorrect after first deposit");
-      assert.ok(finalPoolBalance === initialPoolBalance + BigInt(depositAmount.toString()), "Pool balance incorrect after first deposit");
-      assert.ok(underwriterFinalCollateral === underwriterInitialCollateral - BigInt(depositAmount.toString()), "Underwriter collateral debited incorrectly");
-      assert.ok(underwriterLpBalance === initialUnderwriterLpBalance + BigInt(expectedLpMinted.toString()), "Underwriter LP token balance incorrect after first deposit");
+  describe("Withdrawal Queue Tests", () => {
+    before(async () => {
+      // Count all capital towards capacity and insure payouts worth more than the junior
+      // tranche holds, so part of the liability falls on senior. The pool is nearly fully
+      // utilized, so the buyer needs a large premium.
+      await updatePoolConfig({ unlockedCapitalWeightBps: new BN(10000) });
+      const payout = fetchTranche(JUNIOR).collateral.add(new BN(200 * ONE_TOKEN));
+      mintTokens(USDC_MINT_PUBKEY, buyerUsdcAta, 100_000 * ONE_TOKEN);
+      await updateAssetExposureLimits(USDT_MINT_PUBKEY, NO_ASSET_LIABILITY_CAP, 10000);
+      await createPolicy(payout.muln(10000).divn(BINARY_PAYOUT_BPS).toNumber(), USDT_MINT_PUBKEY);
+    });
 
-    })
-  })
+    it("fails to request a withdrawal of zero LP tokens", async () => {
+      await expectError(requestWithdrawal(new BN(0), SENIOR), "WithdrawalAmountZero");
+    });
 
-  describe("Withdraw collateral tests", () => {
-    it("Withdraw Collateral (USDC)", async () => {
-      const initialUnderwriterLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-      const lpAmountToBurn = new BN(BigInt(initialUnderwriterLpBalance / BigInt(2)).toString());
+    it("fails to lock LP tokens of another tranche", async () => {
+      await expectError(requestWithdrawal(new BN(ONE_TOKEN), SENIOR, JUNIOR), "InvalidLpMint");
+    });
 
-      const initialLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const initialPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterInitialCollateral = (await getAccount(connection, underwriterTestUsdcAta)).amount;
+    it("locks the LP tokens in escrow until the cooldown ends", async () => {
+      const lpSupplyBefore = lpSupply(SENIOR);
+
+      await requestWithdrawal(new BN(500 * ONE_TOKEN), SENIOR);
+
+      const { withdrawalTicket, withdrawalEscrow } = withdrawalPdas(SENIOR);
+      const ticket = fetch("withdrawalTicket", withdrawalTicket);
+      assert.strictEqual(ticket.owner.toBase58(), underwriter.publicKey.toBase58());
+      assert.strictEqual(ticket.trancheIndex, SENIOR);
+      assert.ok(ticket.lpAmount.eq(new BN(500 * ONE_TOKEN)));
+      assert.ok(ticket.unlockTimestamp.eq(new BN(now() + WITHDRAWAL_COOLDOWN_SECONDS)));
+      assert.strictEqual(tokenBalance(withdrawalEscrow), BigInt(500 * ONE_TOKEN));
+      assert.strictEqual(tokenBalance(underwriterLpAtas[SENIOR]), BigInt(500 * ONE_TOKEN));
+      // Escrowed LP tokens stay in the supply and keep sharing in premiums and losses.
+      assert.ok(lpSupply(SENIOR).eq(lpSupplyBefore));
+    });
 
-      let expectedCollateralToWithdraw: BN;
-      if (initialLpSupply === BigInt(0)) { // Should not happen if LPs exist
-        assert.fail("Cannot withdraw, total LP supply is zero.");
-        return;
-      }
-      const collateralToWithdrawU128 = new BN(lpAmountToBurn.toString())
-        .mul(new BN(initialPoolBalance.toString()))
-        .div(new BN(initialLpSupply.toString()));
-      expectedCollateralToWithdraw = new BN(collateralToWithdrawU128.toString());
+    it("holds a separate ticket for each tranche", async () => {
+      await requestWithdrawal(new BN(ONE_TOKEN), JUNIOR);
+      assert.ok(fetch("withdrawalTicket", withdrawalPdas(JUNIOR).withdrawalTicket).lpAmount.eqn(ONE_TOKEN));
+    });
 
-      // console.log(`  LP to burn: ${lpAmountToBurn.toString()}`);
-      // console.log(`  Expected collateral to withdraw: ${expectedCollateralToWithdraw.toString()}`);
+    it("fails to execute a withdrawal before the cooldown ends", async () => {
+      await expectError(executeWithdrawal(SENIOR), "WithdrawalCooldownActive");
+    });
 
-      await program.methods.withdrawCollateral(lpAmountToBurn)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterLpAccount: underwriterLpUsdcAta,
-          underwriterTokenAccount: underwriterTestUsdcAta, // Receives withdrawn test USDC
-          collateralTokenPool: usdcPoolCollateralPda,
-          lpMint: usdcPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdcMintPublicKey, // Specifies the collateral type (test USDC)
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID, // Needed if underwriter_token_account might be init'd
-        })
-        .signers([underwriter])
-        .rpc();
-
-      // Assertions
-      const finalLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const finalPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterFinalLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-      const underwriterFinalCollateralBalance = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-
-      assert.ok(finalLpSupply === initialLpSupply - BigInt(lpAmountToBurn.toString()), "LP supply incorrect after withdrawal");
-      assert.ok(finalPoolBalance === initialPoolBalance - BigInt(expectedCollateralToWithdraw.toString()), "Pool balance incorrect after withdrawal");
-      assert.ok(underwriterFinalLpBalance === initialUnderwriterLpBalance - BigInt(lpAmountToBurn.toString()), "Underwriter LP tokens not burned correctly");
-      assert.ok(underwriterFinalCollateralBalance === underwriterInitialCollateral + BigInt(expectedCollateralToWithdraw.toString()), "Underwriter collateral not credited correctly");
-      // console.log("Partial Collateral Withdrawal: Assertions passed!");
-    });
-
-    it("Withdraw all From pool", async () => {
-      const lpAmountToBurnAll = new BN((await getAccount(connection, underwriterLpUsdcAta)).amount.toString());
-      assert.isTrue(lpAmountToBurnAll.gtn(0), "Pre-condition failed: Underwriter has no LP tokens left to withdraw all.");
-
-      const initialLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const initialPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterInitialCollateralBalance = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-
-      let expectedCollateralToWithdrawAll: BN;
-      if (initialLpSupply === BigInt(0)) {
-        assert.fail("Cannot withdraw, total LP supply is zero.");
-        return;
-      }
-      const collateralToWithdrawAllU128 = new BN(lpAmountToBurnAll.toString())
-        .mul(new BN(initialPoolBalance.toString()))
-        .div(new BN(initialLpSupply.toString()));
-      expectedCollateralToWithdrawAll = new BN(collateralToWithdrawAllU128.toString());
+    it("keeps junior capital locked while it backs policies", async () => {
+      setNow(now() + WITHDRAWAL_COOLDOWN_SECONDS);
+      await expectError(executeWithdrawal(JUNIOR), "WithdrawalBlockedByUtilization");
+    });
 
-      // console.log(`  LP to burn (all): ${lpAmountToBurnAll.toString()}`);
-      // console.log(`  Expected collateral to withdraw (all): ${expectedCollateralToWithdrawAll.toString()}`);
+    it("cancels a withdrawal and returns the escrowed LP tokens", async () => {
+      const lpBefore = tokenBalance(underwriterLpAtas[JUNIOR]);
+      const { withdrawalTicket, withdrawalEscrow } = withdrawalPdas(JUNIOR);
 
-      await program.methods.withdrawCollateral(lpAmountToBurnAll)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterLpAccount: underwriterLpUsdcAta,
-          underwriterTokenAccount: underwriterTestUsdcAta,
-          collateralTokenPool: usdcPoolCollateralPda,
-          lpMint: usdcPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdcMintPublicKey,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        })
-        .signers([underwriter])
-        .rpc();
-
-      const finalLpSupply = (await getMint(connection, usdcPoolLpMintPda)).supply;
-      const finalPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const underwriterFinalLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-      const underwriterFinalCollateralBalance = (await getAccount(connection, underwriterTestUsdcAta)).amount;
-
-      assert.ok(finalLpSupply === initialLpSupply - BigInt(lpAmountToBurnAll.toString()), "LP supply incorrect after full withdrawal");
-      assert.ok(finalPoolBalance === initialPoolBalance - BigInt(expectedCollateralToWithdrawAll.toString()), "Pool balance incorrect after full withdrawal");
-      assert.ok(underwriterFinalLpBalance === BigInt(0), "Underwriter LP tokens not fully burned"); // Should be 0
-      assert.ok(underwriterFinalCollateralBalance === underwriterInitialCollateralBalance + BigInt(expectedCollateralToWithdrawAll.toString()), "Underwriter collateral not fully credited for full withdrawal");
-      // console.log("Full Collateral Withdrawal: Assertions passed!");
-    });
-
-    it("should fail to withdraw zero LP tokens", async () => {
-      const lpAmountToBurnZero = new BN(0);
-      try {
-        await program.methods.withdrawCollateral(lpAmountToBurnZero)
-          .accounts({
-            underwriter: underwriter.publicKey,
-            underwriterLpAccount: underwriterLpUsdcAta,
-            underwriterTokenAccount: underwriterTestUsdcAta,
-            collateralTokenPool: usdcPoolCollateralPda,
-            lpMint: usdcPoolLpMintPda,
-            poolAuthority: poolAuthorityPda,
-            mint: testUsdcMintPublicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-          })
-          .signers([underwriter])
-          .rpc();
-        assert.fail("Should have failed to withdraw zero LP tokens.");
-      } catch (error) {
-        assert.isOk(error.message, "Withdraw amount must be greater than zero");
-      }
+      await cancelWithdrawal(JUNIOR);
+
+      assert.strictEqual(tokenBalance(underwriterLpAtas[JUNIOR]) - lpBefore, BigInt(ONE_TOKEN));
+      assert.isNull(svm.getAccount(withdrawalTicket));
+      assert.isNull(svm.getAccount(withdrawalEscrow));
     });
 
-    it("should fail to withdraw more LP tokens than owned", async () => {
-      const currentLpBalance = (await getAccount(connection, underwriterLpUsdcAta)).amount;
-      const lpAmountToBurnExcess = new BN(currentLpBalance.toString()).add(new BN(1)); // 1 more than owned
-
-      try {
-        await program.methods.withdrawCollateral(lpAmountToBurnExcess)
-          .accounts({
-            underwriter: underwriter.publicKey,
-            underwriterLpAccount: underwriterLpUsdcAta,
-            underwriterTokenAccount: underwriterTestUsdcAta,
-            collateralTokenPool: usdcPoolCollateralPda,
-            lpMint: usdcPoolLpMintPda,
-            poolAuthority: poolAuthorityPda,
-            mint: testUsdcMintPublicKey,
-            tokenProgram: TOKEN_PROGRAM_ID,
-            systemProgram: SystemProgram.programId,
-            associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-          })
-          .signers([underwriter])
-          .rpc();
-        assert.fail("Should have failed to withdraw more LP tokens than owned.");
-      } catch (error) {
-        assert.isOk(error.message, "Insufficient funds");
-      }
+    it("pays senior LPs at the share price when the ticket is executed", async () => {
+      const lpAmount = new BN(500 * ONE_TOKEN);
+      await releasePremiums();
+      const seniorBefore = fetchTranche(SENIOR);
+      const juniorBefore = fetchTranche(JUNIOR);
+      const expected = lpAmount
+        .mul(seniorBefore.collateral.addn(VIRTUAL_COLLATERAL))
+        .div(lpSupply(SENIOR).addn(VIRTUAL_LP_SHARES));
+      const underwriterBefore = tokenBalance(underwriterUsdcAta);
+
+      await expectError(executeWithdrawal(SENIOR, expected.addn(1)), "SlippageExceeded");
+      await executeWithdrawal(SENIOR, expected);
+
+      assert.strictEqual(tokenBalance(underwriterUsdcAta) - underwriterBefore, BigInt(expected.toString()));
+      assert.strictEqual(tokenBalance(underwriterLpAtas[SENIOR]), BigInt(500 * ONE_TOKEN));
+      assert.isNull(svm.getAccount(withdrawalPdas(SENIOR).withdrawalTicket));
+      assert.ok(fetchTranche(SENIOR).collateral.eq(seniorBefore.collateral.sub(expected)));
+      assert.ok(fetchTranche(JUNIOR).collateral.eq(juniorBefore.collateral));
+      const position = fetch("underwriterPosition", underwriterPositionPda(SENIOR));
+      assert.ok(position.lpBalance.eq(new BN(500 * ONE_TOKEN)));
+      assert.ok(position.withdrawnAmount.eq(expected));
+      assert.ok(position.realizedPnl.eq(expected.sub(new BN(500 * ONE_TOKEN))));
+    });
+
+    it("reports realized and unrealized PnL for the remaining position", async () => {
+      const senior = fetchTranche(SENIOR);
+      const position = fetch("underwriterPosition", underwriterPositionPda(SENIOR));
+      const currentValue = position.lpBalance
+        .mul(senior.collateral.addn(VIRTUAL_COLLATERAL))
+        .div(lpSupply(SENIOR).addn(VIRTUAL_LP_SHARES));
+
+      const pnl = await getUnderwriterPnl(SENIOR);
+
+      assert.ok(pnl.lpBalance.eq(position.lpBalance));
+      assert.ok(pnl.costBasis.eq(new BN(500 * ONE_TOKEN)));
+      assert.ok(pnl.currentValue.eq(currentValue));
+      assert.ok(pnl.realizedPnl.eq(position.realizedPnl));
+      assert.ok(pnl.unrealizedPnl.eq(currentValue.sub(new BN(500 * ONE_TOKEN))));
+    });
+
+    it("values the collateral at the low end of an aging, uncertain price on the way out", async () => {
+      await requestWithdrawal(new BN(10 * ONE_TOKEN), SENIOR);
+      setNow(now() + WITHDRAWAL_COOLDOWN_SECONDS);
+      setPriceUpdate(collateralPriceUpdate, PYTH_USDC_USD_FEED_ID, PEG_PRICE, now() - 601, PEG_PRICE / 50);
+      await expectError(executeWithdrawal(SENIOR, new BN(0), collateralPriceUpdate), "PriceTooOld");
+
+      // Too old and too wide to underwrite against, but still fresh enough to exit on.
+      setPriceUpdate(collateralPriceUpdate, PYTH_USDC_USD_FEED_ID, PEG_PRICE, now() - 300, PEG_PRICE / 50);
+      await executeWithdrawal(SENIOR, new BN(0), collateralPriceUpdate);
+      assert.isNull(svm.getAccount(withdrawalPdas(SENIOR).withdrawalTicket));
+    });
+
+    it("fails to withdraw senior capital the junior tranche cannot replace", async () => {
+      await requestWithdrawal(new BN(500 * ONE_TOKEN), SENIOR);
+      setNow(now() + WITHDRAWAL_COOLDOWN_SECONDS);
+      await expectError(executeWithdrawal(SENIOR), "WithdrawalBlockedByUtilization");
+      await cancelWithdrawal(SENIOR);
     });
   });
 
-  describe("Close Policy tests", () => {
+  describe("Switch Tranche Tests", () => {
+    const lpAmount = new BN(100 * ONE_TOKEN);
+
     before(async () => {
-      policyIdCounter++;
-      usdcPolicyIdForCheck = new BN(policyIdCounter);
-      const insuredAmountUSDC = new BN(100 * (10 ** TOKEN_DECIMALS));
-      await program.methods.createPolicy(insuredAmountUSDC, usdcPolicyIdForCheck)
-        .accounts({
-          buyer: buyer.publicKey,
-          policyAccount: (PublicKey.findProgramAddressSync([POLICY_SEED_BUF, buyer.publicKey.toBuffer(), usdcPolicyIdForCheck.toBuffer("le", 8)], program.programId))[0],
-          buyerTokenAccount: buyerTestUsdcAta,
-          collateralTokenPool: usdcPoolCollateralPda,
-          mint: testUsdcMintPublicKey,
-          insuredStablecoinMint: MAINNET_USDC_MINT_PUBKEY,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-        }).signers([buyer])
-        .rpc();
-      // console.log(`Created policy for USDC check, ID: ${usdcPolicyIdForCheck.toString()}`);
-
-      // Create a policy insuring USDT, premium in Test USDC
-      policyIdCounter++;
-      usdtPolicyIdForCheck = new BN(policyIdCounter);
-      const insuredAmountUsdt = new BN(100 * (10 ** TOKEN_DECIMALS));
-      await program.methods.createPolicy(insuredAmountUsdt, usdtPolicyIdForCheck)
-        .accounts({
-          buyer: buyer.publicKey,
-          policyAccount: (PublicKey.findProgramAddressSync([POLICY_SEED_BUF, buyer.publicKey.toBuffer(), usdtPolicyIdForCheck.toBuffer("le", 8)], program.programId))[0],
-          buyerTokenAccount: buyerTestUsdcAta, // Premium in test USDC
-          collateralTokenPool: usdcPoolCollateralPda, // Into USDC pool
-          mint: testUsdcMintPublicKey, // Premium in test USDC
-          insuredStablecoinMint: MAINNET_USDT_MINT_PUBKEY, // Insuring actual USDT
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([buyer])
-        .rpc();
-      // console.log(`Created policy for USDT check, ID: ${usdtPolicyIdForCheck.toString()}`);
-
-      // console.log(`Waiting ${POLICY_TERM_SECONDS_FOR_TESTING + 1} seconds for policies to expire...`);
-      await new Promise(resolve => setTimeout(resolve, (POLICY_TERM_SECONDS_FOR_TESTING + 1) * 1000));
-      // console.log("Policies should now be expired.");
-    })
-
-    it("Process an expired usdc policy", async () => {
-      const depositAmount = new BN(10000 * (10 ** TOKEN_DECIMALS)); // Deposit 10,000 test USDC
-      await program.methods.depositCollateral(depositAmount)
-        .accounts({
-          underwriter: underwriter.publicKey,
-          underwriterTokenAccount: underwriterTestUsdcAta,
-          underwriterLpTokenAccount: underwriterLpUsdcAta,
-          collateralTokenPool: usdcPoolCollateralPda,
-          lpMint: usdcPoolLpMintPda,
-          poolAuthority: poolAuthorityPda,
-          mint: testUsdcMintPublicKey,
-          tokenProgram: TOKEN_PROGRAM_ID,
-          systemProgram: SystemProgram.programId,
-          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        }).signers([underwriter]).rpc();
-      // console.log("Underwriter deposited initial collateral into Test USDC pool.");
-
-
-
-      const policyPda = (PublicKey.findProgramAddressSync([POLICY_SEED_BUF, buyer.publicKey.toBuffer(), usdcPolicyIdForCheck.toBuffer("le", 8)], program.programId))[0];
-
-      // console.log(`  Policy PDA: ${policyPda.toBase58()}`);
-      // console.log(`  Buyer (signer): ${buyer.publicKey.toBase58()}`);
-      // console.log(`  Buyer Token Account (for payout): ${buyerTestUsdcAta.toBase58()}`);
-      // console.log(`  Collateral Pool (USDC): ${usdcPoolCollateralPda.toBase58()}`);
-      // console.log(`  Mint (of pool/payout - Test USDC): ${testUsdcMintPublicKey.toBase58()}`);
-      // console.log(`  Pyth Price Update Account: ${PYTH_PRICE_UPDATE_V2_ACCOUNT_PUBKEY.toBase58()}`);
-
-      const initialBuyerBalance = (await getAccount(connection, buyerTestUsdcAta)).amount;
-      const initialPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-      const policyBefore = await program.account.policyAccount.fetch(policyPda);
-
-      // console.log(`  Collateral Pool Balance BEFORE check: ${(initialPoolBalance)}`);
-      // console.log(`  Policy Payout Amount to be transferred: ${policyBefore.payoutAmount.toString()}`);
-
-      let finalStatusName = "";
-      try {
-        const txsignature = await program.methods.checkAndPayout(usdcPolicyIdForCheck)
-          .accounts({
-            buyer: buyer.publicKey,
-            policyAccount: policyPda,
-            collateralTokenPool: usdcPoolCollateralPda,
-            poolAuthority: poolAuthorityPda,
-            buyerTokenAccount: buyerTestUsdcAta, // Payouts in test USDC
-            mint: testUsdcMintPublicKey, // Mint of the collateral pool & payout
-            pythPriceUpdate: PYTH_PRICE_UPDATE_V2_ACCOUNT_PUBKEY, // The cloned official Pyth account
-            tokenProgram: TOKEN_PROGRAM_ID,
-          })
-          .signers([buyer])
-          .rpc();
-
-        // console.log(`Transaction successful. Signature: ${txsignature}`);
-
-        // Optional: Confirm the transaction
-        const latestBlockHash = await provider.connection.getLatestBlockhash();
-        await provider.connection.confirmTransaction({
-          blockhash: latestBlockHash.blockhash,
-          lastValidBlockHeight: latestBlockHash.lastValidBlockHeight,
-          signature: txsignature
-        }, "confirmed");
-
-        // Fetch the transaction details to get logs
-        // const txDetails = await provider.connection.getTransaction(txsignature, {
-        //   commitment: "confirmed",
-        //   maxSupportedTransactionVersion: 0, // Add this if you encounter version issues
-        // });
-
-        // if (txDetails && txDetails.meta && txDetails.meta.logMessages) {
-        //   console.log("---- On-Chain Program Logs ----");
-        //   txDetails.meta.logMessages.forEach(log => console.log(log));
-        //   console.log("-----------------------------");
-        // } else {
-        //   console.log("Could not fetch transaction logs.");
-        // }
-
-        const policyAfter = await program.account.policyAccount.fetch(policyPda);
-        finalStatusName = Object.keys(policyAfter.status)[0];
-        // console.log(`  Policy status after check: ${finalStatusName}`);
-
-        // assert.deepStrictEqual(policyAfter.status, { active: {} }, "Policy status should have changed from Active");
-
-        if (finalStatusName === "expiredPaid") {
-          const finalBuyerBalance = (await getAccount(connection, buyerTestUsdcAta)).amount;
-          const finalPoolBalance = (await getAccount(connection, usdcPoolCollateralPda)).amount;
-          const expectedPayout = policyBefore.payoutAmount;
-          assert.ok(finalBuyerBalance === initialBuyerBalance + BigInt(expectedPayout.toString()), "Buyer balance incorrect after payout");
-          assert.ok(finalPoolBalance === initialPoolBalance - BigInt(expectedPayout.toString()), "Pool balance incorrect after payout");
-          // console.log(`  DEPEG detected and payout of ${expectedPayout.toString()} processed.`);
-        } else if (finalStatusName === "expiredNotPaid") {
-          const finalBuyerBalance = (await getAccount(connection, buyerTestUsdcAta)).amount;
-          assert.ok(finalBuyerBalance === initialBuyerBalance, "Buyer balance should not change if no payout");
-          console.log("  NO DEPEG detected. Policy expired without payout.");
-        } else {
-          assert.fail(`Unexpected policy status after check: ${finalStatusName}`);
-        }
+      await requestWithdrawal(lpAmount, SENIOR);
+    });
 
-      } catch (error) {
-        console.error(`Error during check_payout for USDC policy: ${error.toString()}`);
-        if (error.logs) console.error("Logs:", error.logs.join("\n"));
-        assert.fail(`check_payout for USDC policy failed: ${error.toString()}`);
-      }
+    it("fails to switch before the cooldown ends", async () => {
+      await expectError(switchTranche(SENIOR, JUNIOR), "WithdrawalCooldownActive");
     });
 
-    // it("should process an expired USDT policy (outcome depends on cloned Pyth data)", async () => {
-    //   const policyPda = (PublicKey.findProgramAddressSync([POLICY_SEED_BUF, buyer.publicKey.toBuffer(), usdtPolicyIdForCheck.toBuffer("le", 8)], program.programId))[0];
-
-    //   // This policy used testUsdcMintPublicKey for premiums and thus for policy.mint and payout.
-    //   const payoutMintForUsdtPolicy = testUsdcMintPublicKey;
-    //   const buyerPayoutAtaForUsdtPolicy = buyerTestUsdtAta; // Buyer receives payout in test USDC
-    //   const payoutCollateralPoolForUsdtPolicy = usdtPoolCollateralPda; // Payout from USDC pool
-
-    //   const initialBuyerBalance = (await getAccount(connection, buyerPayoutAtaForUsdtPolicy)).amount;
-    //   const initialPoolBalance = (await getAccount(connection, payoutCollateralPoolForUsdtPolicy)).amount;
-    //   const policyBefore = await program.account.policyAccount.fetch(policyPda);
-
-    //   assert.strictEqual(policyBefore.mint.toBase58(), payoutMintForUsdtPolicy.toBase58(), "Policy.mint for USDT policy is not the expected payout currency (testUsdcMintPublicKey)");
-
-    //   let finalStatusName = "";
-    //   try {
-    //     const txSignature = await program.methods.checkAndPayout(usdtPolicyIdForCheck)
-    //       .accounts({
-    //         buyer: buyer.publicKey,
-    //         policyAccount: policyPda,
-    //         collateralTokenPool: payoutCollateralPoolForUsdtPolicy,
-    //         poolAuthority: poolAuthorityPda,
-    //         buyerTokenAccount: buyerPayoutAtaForUsdtPolicy,
-    //         mint: payoutMintForUsdtPolicy, // Mint of the collateral pool & payout
-    //         pythPriceUpdate: PYTH_PRICE_UPDATE_V2_ACCOUNT_PUBKEY,
-    //         tokenProgram: TOKEN_PROGRAM_ID,
-    //       })
-    //       .signers([buyer])
-    //       .rpc();
-    //     const txDetails = await provider.connection.getTransaction(txSignature, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
-    //     if (txDetails?.meta?.logMessages) {
-    //       console.log("---- USDT Policy On-Chain Logs ----");
-    //       txDetails.meta.logMessages.forEach(log => console.log(log));
-    //       console.log("---------------------------------");
-    //     }
-
-    //     const policyAfter = await program.account.policyAccount.fetch(policyPda);
-    //     finalStatusName = Object.keys(policyAfter.status)[0];
-    //     console.log(`  USDT Policy status after check: ${finalStatusName}`);
-
-    //     if (finalStatusName === "expiredPaid") {
-    //       const finalBuyerBalance = (await getAccount(connection, buyerPayoutAtaForUsdtPolicy)).amount;
-    //       const finalPoolBalance = (await getAccount(connection, payoutCollateralPoolForUsdtPolicy)).amount;
-    //       const expectedPayout = policyBefore.payoutAmount;
-    //       assert.ok(finalBuyerBalance === initialBuyerBalance + BigInt(expectedPayout.toString()), "USDT Policy: Buyer balance incorrect after payout");
-    //       assert.ok(finalPoolBalance === initialPoolBalance - BigInt(expectedPayout.toString()), "USDT Policy: Pool balance incorrect after payout");
-    //       console.log(`  USDT Policy: DEPEG detected and payout of ${expectedPayout.toString()} processed.`);
-    //     } else if (finalStatusName === "expiredNotPaid") {
-    //       const finalBuyerBalance = (await getAccount(connection, buyerPayoutAtaForUsdtPolicy)).amount;
-    //       assert.ok(finalBuyerBalance === initialBuyerBalance, "USDT Policy: Buyer balance should not change if no depeg");
-    //       console.log("  USDT Policy: NO DEPEG detected. Policy expired without payout.");
-    //     } else {
-    //       assert.fail(`Unexpected policy status for USDT policy: ${finalStatusName}`);
-    //     }
-    //   } catch (error) {
-    //     console.error(`Error during check_payout for USDT policy: ${error.toString()}`);
-    //     if (error.logs) console.error("Logs:", error.logs.join("\n"));
-    //     assert.fail(`check_payout for USDT policy failed: ${error.toString()}`);
-    //   }
-    // });
-
-    it("Fail to process an already processed policy", async () => {
-      const policyPda = (PublicKey.findProgramAddressSync([POLICY_SEED_BUF, buyer.publicKey.toBuffer(), usdcPolicyIdForCheck.toBuffer("le", 8)], program.programId))[0];
-
-      try {
-        await program.methods.checkAndPayout(usdcPolicyIdForCheck)
-          .accounts({
-            buyer: buyer.publicKey,
-            policyAccount: policyPda,
-            collateralTokenPool: usdcPoolCollateralPda,
-            poolAuthority: poolAuthorityPda,
-            buyerTokenAccount: buyerTestUsdcAta,
-            mint: testUsdcMintPublicKey,
-            pythPriceUpdate: PYTH_PRICE_UPDATE_V2_ACCOUNT_PUBKEY,
-            tokenProgram: TOKEN_PROGRAM_ID,
-          })
-          .signers([buyer])
-          .rpc();
-        assert.fail("Should have failed because policy was already processed.");
-      } catch (error) {
-        assert.isOk(error.message, "Policy already processed");
-      }
+    it("fails to switch into the same tranche", async () => {
+      setNow(now() + WITHDRAWAL_COOLDOWN_SECONDS);
+      await expectError(switchTranche(SENIOR, SENIOR), "SameTrancheSwitch");
+    });
+
+    it("moves the collateral into the target tranche and stakes its LP tokens", async () => {
+      await releasePremiums();
+      const seniorBefore = fetchTranche(SENIOR);
+      const juniorBefore = fetchTranche(JUNIOR);
+      const collateral = lpAmount
+        .mul(seniorBefore.collateral.addn(VIRTUAL_COLLATERAL))
+        .div(lpSupply(SENIOR).addn(VIRTUAL_LP_SHARES));
+      const lpOut = collateral
+        .mul(lpSupply(JUNIOR).addn(VIRTUAL_LP_SHARES))
+        .div(juniorBefore.collateral.addn(VIRTUAL_COLLATERAL));
+      const seniorVaultBefore = tokenBalance(tranches[SENIOR].collateralVault);
+      const juniorVaultBefore = tokenBalance(tranches[JUNIOR].collateralVault);
+      const stakedBefore = tokenBalance(tranches[JUNIOR].stakedLpVault);
+      const positionBefore = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+
+      await expectError(switchTranche(SENIOR, JUNIOR, lpOut.addn(1)), "SlippageExceeded");
+      await switchTranche(SENIOR, JUNIOR, lpOut);
+
+      assert.strictEqual(seniorVaultBefore - tokenBalance(tranches[SENIOR].collateralVault), BigInt(collateral.toString()));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].collateralVault) - juniorVaultBefore, BigInt(collateral.toString()));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].stakedLpVault) - stakedBefore, BigInt(lpOut.toString()));
+      assert.isNull(svm.getAccount(withdrawalPdas(SENIOR).withdrawalTicket));
+      assert.ok(fetchTranche(SENIOR).collateral.eq(seniorBefore.collateral.sub(collateral)));
+      assert.ok(fetchTranche(JUNIOR).collateral.eq(juniorBefore.collateral.add(collateral)));
+      const position = fetch("underwriterPosition", underwriterPositionPda(JUNIOR));
+      assert.ok(position.stakedLp.eq(positionBefore.stakedLp.add(lpOut)));
+      assert.ok(position.depositedPrincipal.eq(positionBefore.depositedPrincipal.add(collateral)));
     });
+  });
+
+  describe("Reserve Tests", () => {
+    it("fails to top up the reserve for anyone but the pool authority", async () => {
+      await expectError(topUpReserve(ONE_TOKEN, underwriter), "UnauthorizedPoolAuthority");
+    });
+
+    it("fails to top up the reserve with nothing", async () => {
+      await expectError(topUpReserve(0), "ReserveTopUpZero");
+    });
+
+    it("adds external funds to the reserve", async () => {
+      const reserveBefore = fetch("insurancePool", usdcPool.insurancePool).reserveBalance;
+      const vaultBefore = tokenBalance(usdcPool.reserveVault);
+
+      await topUpReserve(50 * ONE_TOKEN);
+
+      assert.strictEqual(tokenBalance(usdcPool.reserveVault) - vaultBefore, BigInt(50 * ONE_TOKEN));
+      const pool = fetch("insurancePool", usdcPool.insurancePool);
+      assert.ok(pool.reserveBalance.eq(reserveBefore.add(new BN(50 * ONE_TOKEN))));
+    });
+  });
+
+  describe("Claim Token Tests", () => {
+    it("fails to redeem claims before any are funded", async () => {
+      createAta(usdcPool.claimMint, buyer.publicKey);
+      await expectError(redeemClaims(new BN(ONE_TOKEN)), "NoClaimsToRedeem");
+    });
+  });
 
+  describe("Protocol Fee Tests", () => {
+    const protocolFeeBps = 1000;
+
+    it("takes the protocol fee off the top of the premium", async () => {
+      await updatePoolConfig({ protocolFeeBps: new BN(protocolFeeBps) });
+      const treasuryBefore = tokenBalance(usdcPool.treasuryVault);
+      const juniorBefore = tokenBalance(tranches[JUNIOR].collateralVault);
+      const seniorBefore = tokenBalance(tranches[SENIOR].collateralVault);
+
+      const policyId = await createPolicy(100 * ONE_TOKEN, USDT_MINT_PUBKEY);
+
+      const policy = fetch("policyAccount", policyPda(policyId));
+      const fee = policy.premiumPaid.muln(protocolFeeBps).divn(10000);
+      assert.ok(fee.gtn(0));
+      assert.strictEqual(tokenBalance(usdcPool.treasuryVault) - treasuryBefore, BigInt(fee.toString()));
+      // The reserve's cut is sized on the premium after the fee and the tranches split the rest.
+      const reserveCut = policy.premiumPaid.sub(fee).muln(RESERVE_PREMIUM_BPS).divn(10000);
+      const [juniorPremium, seniorPremium] = policy.tranchePremiums;
+      assert.ok(juniorPremium.add(seniorPremium).eq(policy.premiumPaid.sub(fee).sub(reserveCut)));
+      assert.strictEqual(tokenBalance(tranches[JUNIOR].collateralVault) - juniorBefore, BigInt(juniorPremium.toString()));
+      assert.strictEqual(tokenBalance(tranches[SENIOR].collateralVault) - seniorBefore, BigInt(seniorPremium.toString()));
+      assert.ok(fetch("insurancePool", usdcPool.insurancePool).totalProtocolFeesAccrued.eq(fee));
+    });
+
+    it("fails to claim the fees for anyone but the treasury authority", async () => {
+      await expectError(claimProtocolFees(underwriter), "UnauthorizedTreasuryAuthority");
+    });
+
+    it("pays the accrued fees to the treasury authority", async () => {
+      const pool = fetch("insurancePool", usdcPool.insurancePool);
+      const destinationBefore = tokenBalance(authorityUsdcAta);
+
+      await claimProtocolFees();
+
+      const claimed = pool.totalProtocolFeesAccrued.sub(pool.totalProtocolFeesClaimed);
+      assert.strictEqual(tokenBalance(authorityUsdcAta) - destinationBefore, BigInt(claimed.toString()));
+      assert.strictEqual(tokenBalance(usdcPool.treasuryVault), BigInt(0));
+      const after = fetch("insurancePool", usdcPool.insurancePool);
+      assert.ok(after.totalProtocolFeesClaimed.eq(after.totalProtocolFeesAccrued));
+
+      await expectError(claimProtocolFees(), "NoProtocolFeesToClaim");
+      await updatePoolConfig({ protocolFeeBps: new BN(0) });
+    });
+  });
+
+  describe("Unlock Position Tests", () => {
+    // Runs last, once the rest of the suite has moved the clock past the lock.
+    it("moves the locked LP tokens to the staked vault and pays the boost premium", async () => {
+      const position = fetch("lockedPosition", lockedPositionPda(lockedPositionId));
+      setNow(Math.max(now(), position.unlockTimestamp.toNumber()));
+      await releasePremiums();
+      const juniorBefore = fetchTranche(JUNIOR);
+      const boostReward = position.boostWeight
+        .mul(juniorBefore.boostRewardPerWeight.sub(position.boostRewardPerWeightSnapshot))
+        .div(REWARD_PRECISION);
+      assert.ok(boostReward.gtn(0));
+      const stakedBefore = tokenBalance(tranches[JUNIOR].stakedLpVault);
+      const usdcBefore = tokenBalance(underwriterUsdcAta);
+
+      await unlockPosition(lockedPositionId, JUNIOR);
+
+      // The LP tokens stay staked and can be unstaked from there.
+      assert.strictEqual(
+        tokenBalance(tranches[JUNIOR].stakedLpVault) - stakedBefore,
+        BigInt(position.lpAmount.toString())
+      );
+      assert.ok(fetch("underwriterPosition", underwriterPositionPda(JUNIOR)).lockedLp.isZero());
+      assert.strictEqual(tokenBalance(underwriterUsdcAta) - usdcBefore, BigInt(boostReward.toString()));
+      assert.isNull(svm.getAccount(lockedPositionPda(lockedPositionId)));
+      const junior = fetchTranche(JUNIOR);
+      assert.ok(junior.lockedLp.isZero());
+      assert.ok(junior.boostWeight.isZero());
+      assert.ok(junior.boostRewards.eq(juniorBefore.boostRewards.sub(boostReward)));
+    });
   });
 });